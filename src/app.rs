@@ -1,9 +1,34 @@
-use crate::Cli;
+use crate::args::{Cli, Command};
+use crate::dig;
+use crate::models::auth::{AuthScheme, Credentials};
 use crate::models::client::Client;
-use anyhow::Result;
+use crate::models::cookie::CookieJar;
+use crate::models::dns::{DnsCache, Upstream};
+use crate::models::netrc::Netrc;
+use crate::models::oauth2::{OAuth2, OAuth2Config};
+use crate::models::proxy::{Proxy, ProxyConfig};
+use crate::models::resolver::{
+    BuiltinResolver, IpVersion, OverrideResolver, Resolver, ResolverKind, SystemResolver,
+};
+use crate::models::retry::RetryPolicy;
+use crate::models::tls::TlsConfig;
+use crate::models::url::Url;
+use crate::models::utils::sanitize_filename;
+use crate::models::{Headers, Method, Response};
+use anyhow::{Context, Result, anyhow};
+use log::debug;
+use percent_encoding::percent_decode_str;
+use std::fs::{File, OpenOptions};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::Arc;
+use std::time::Duration;
 pub struct App {
     cli: Cli,
     client: Client,
+    // --dns-cache 读取的缓存，运行结束后写回文件
+    dns_cache: Option<Arc<DnsCache>>,
 }
 
 impl App {
@@ -11,20 +36,627 @@ impl App {
         Self {
             cli,
             client: Client::new(),
+            dns_cache: None,
         }
     }
     pub fn run(&mut self) -> Result<()> {
+        if let Some(Command::Dig(args)) = self.cli.command.as_ref() {
+            return dig::run(args);
+        }
         self.client.set_timeout(self.cli.timeout);
-        let request = self.client.get(&self.cli.url);
-        for header in self.cli.headers.iter() {
-            let header = header.split(':').collect::<Vec<&str>>();
-            if header.len() != 2 {
-                return Err(anyhow::anyhow!("Invalid header format"));
+        self.client.set_tls_config(self.tls_config());
+        self.client.set_follow_redirects(self.cli.location);
+        self.client.set_max_redirects(self.cli.max_redirs);
+        self.client.set_compressed(self.cli.compressed);
+        self.client.set_retry_policy(self.retry_policy());
+        // 令牌端点与请求共享同一个解析器
+        let resolver: Rc<dyn Resolver> = Rc::from(self.resolver()?);
+        self.client.set_resolver(Box::new(Rc::clone(&resolver)));
+        self.client.set_proxy(self.proxy()?);
+        self.client
+            .set_credentials(self.cli.user.as_deref().map(Credentials::parse));
+        self.client
+            .set_auth_scheme(match (self.cli.digest, self.cli.anyauth) {
+                (true, _) => AuthScheme::Digest,
+                (_, true) => AuthScheme::Any,
+                _ => AuthScheme::Basic,
+            });
+        self.client.set_netrc(self.netrc()?);
+        self.client.set_oauth2(self.oauth2(&resolver)?);
+        self.client.set_ip_version(self.ip_version());
+        if let Some(jar) = self.cookie_jar()? {
+            self.client.set_cookie_jar(jar);
+        }
+        let body = self.request_body()?;
+        let method = self.request_method();
+        let url = self.url().to_string();
+        let request = self.client.request(&url, method)?;
+        apply_cookie(
+            &mut request.borrow_mut().headers,
+            self.cli.cookie.as_deref(),
+        )?;
+        apply_headers(&mut request.borrow_mut().headers, &self.cli.headers)?;
+        if let Some(body) = body {
+            let mut request = request.borrow_mut();
+            request.set_body(&body);
+            // 与 curl 一样，-d 默认按表单编码发送
+            request.headers.add(
+                "Content-Type".to_string(),
+                "application/x-www-form-urlencoded".to_string(),
+            );
+        }
+        let result = self.client.execute_stream();
+        // 请求失败时也保存已经收到的 cookie 和 DNS 缓存
+        self.save_cookies()?;
+        self.save_dns_cache()?;
+        let mut response = result?;
+        for redirect in response.redirects.iter() {
+            debug!(
+                "Redirect: {} {} -> {}",
+                redirect.status, redirect.url, redirect.location
+            );
+        }
+        debug!("{} {}", response.version, response.status);
+        debug!("Response Headers:\n{:?}", response.headers);
+        // 消息体直接从连接写入文件或标准输出，不在内存中缓存
+        match self.output_file(&response)? {
+            Some((mut file, path)) => {
+                let written = response.copy_to(&mut file)?;
+                debug!(
+                    "已写入 {}/{:?} 字节到 {}",
+                    written,
+                    response.content_length(),
+                    path.display()
+                );
+            }
+            None => {
+                response.copy_to(&mut std::io::stdout().lock())?;
+            }
+        }
+        if let Some(trailers) = response.trailers() {
+            debug!("Trailers:\n{:?}", trailers);
+        }
+        if !response.chunk_extensions().is_empty() {
+            debug!("Chunk Extensions: {:?}", response.chunk_extensions());
+        }
+        Ok(())
+    }
+
+    // --retry/--interval/--retry-*: 没有 --retry 时与 curl 一样只发送一次
+    fn retry_policy(&self) -> RetryPolicy {
+        let default = RetryPolicy::default();
+        RetryPolicy {
+            max_retries: self.cli.retry.unwrap_or(default.max_retries),
+            interval: Duration::from_secs(self.cli.interval),
+            backoff: self.cli.retry_backoff,
+            statuses: self.cli.retry_status.clone(),
+            retry_all: self.cli.retry_all,
+        }
+    }
+
+    // --cacert/--insecure，同样用于 DoH 和 DoT 服务器
+    fn tls_config(&self) -> TlsConfig {
+        TlsConfig {
+            ca_file: self.cli.cacert.as_ref().map(Into::into),
+            insecure: self.cli.insecure,
+            alpn: None,
+        }
+    }
+
+    // -x/--proxy 和 --socks5/--socks5-hostname/--socks4a 代替环境变量中的代理，
+    // -x 为空字符串时不使用任何代理；--proxy-user 设置代理的认证信息
+    fn proxy(&self) -> Result<ProxyConfig> {
+        let mut config = ProxyConfig::from_env().context("环境变量中的代理设置无效")?;
+        let socks = [
+            ("socks5", &self.cli.socks5),
+            ("socks5h", &self.cli.socks5_hostname),
+            ("socks4a", &self.cli.socks4a),
+        ]
+        .into_iter()
+        .find_map(|(scheme, host)| Some(format!("{}://{}", scheme, host.as_deref()?.trim())));
+        match socks
+            .as_deref()
+            .or(self.cli.proxy.as_deref().map(str::trim))
+        {
+            Some("") => config.clear(),
+            Some(proxy) => config.set_proxy(Proxy::parse(proxy)?),
+            None => {}
+        }
+        if let Some(user) = self.cli.proxy_user.as_ref() {
+            config.set_credentials(user);
+        }
+        Ok(config)
+    }
+
+    // -n/--netrc-optional/--netrc-file: 读取 .netrc，--netrc-optional 时文件不存在也继续
+    fn netrc(&self) -> Result<Option<Netrc>> {
+        let path = match self.cli.netrc_file.as_ref() {
+            Some(path) => PathBuf::from(path),
+            None if self.cli.netrc || self.cli.netrc_optional => Netrc::default_path()
+                .ok_or_else(|| anyhow!("无法确定.netrc的位置，请使用--netrc-file指定"))?,
+            None => return Ok(None),
+        };
+        match Netrc::load(&path) {
+            Ok(netrc) => Ok(Some(netrc)),
+            Err(e) if self.cli.netrc_optional && e.kind() == std::io::ErrorKind::NotFound => {
+                debug!(".netrc文件 {} 不存在", path.display());
+                Ok(None)
+            }
+            Err(e) => Err(e).with_context(|| format!("读取.netrc文件 {} 失败", path.display())),
+        }
+    }
+
+    // -4/-6
+    fn ip_version(&self) -> Option<IpVersion> {
+        match (self.cli.ipv4, self.cli.ipv6) {
+            (true, _) => Some(IpVersion::V4),
+            (_, true) => Some(IpVersion::V6),
+            _ => None,
+        }
+    }
+
+    // --oauth2-*: 令牌端点与请求使用相同的 --cacert/--insecure、代理、解析器和 -4/-6
+    fn oauth2(&self, resolver: &Rc<dyn Resolver>) -> Result<Option<OAuth2>> {
+        let args = &self.cli.oauth2;
+        let (Some(token_url), Some(client_id)) = (&args.token_url, &args.client_id) else {
+            return Ok(None);
+        };
+        Ok(Some(OAuth2::new(OAuth2Config {
+            token_url: token_url.clone(),
+            client_id: client_id.clone(),
+            client_secret: args.client_secret.clone(),
+            scope: args.scope.clone(),
+            refresh_token: args.refresh_token.clone(),
+            cache: args.token_cache.as_ref().map(PathBuf::from),
+            tls: self.tls_config(),
+            proxy: self.proxy()?,
+            resolver: Some(Rc::clone(resolver)),
+            ip_version: self.ip_version(),
+        })))
+    }
+
+    // --resolve/--connect-to 在选择的解析器之前生效
+    fn resolver(&mut self) -> Result<Box<dyn Resolver>> {
+        let resolver = self.dns_resolver()?;
+        if self.cli.resolve.is_empty() && self.cli.connect_to.is_empty() {
+            return Ok(resolver);
+        }
+        Ok(Box::new(OverrideResolver::new(
+            resolver,
+            self.cli.resolve.clone(),
+            self.cli.connect_to.clone(),
+        )))
+    }
+
+    // --resolver/--dns-servers/--dns-cache/--doh-url/--dot-server:
+    // 与 curl 一样，指定 DNS 服务器时使用内置解析器
+    fn dns_resolver(&mut self) -> Result<Box<dyn Resolver>> {
+        let servers = &self.cli.dns_servers;
+        let upstream = self.upstream()?;
+        let builtin_only =
+            !servers.is_empty() || self.cli.dns_cache.is_some() || upstream.is_some();
+        let kind = self.cli.resolver.unwrap_or(if builtin_only {
+            ResolverKind::Builtin
+        } else {
+            ResolverKind::System
+        });
+        if kind == ResolverKind::System {
+            if builtin_only {
+                return Err(anyhow!(
+                    "--dns-servers、--dns-cache、--doh-url 和 --dot-server 只能配合内置解析器使用"
+                ));
+            }
+            return Ok(Box::new(SystemResolver));
+        }
+        let mut resolver = BuiltinResolver::new(servers.clone());
+        if let Some(upstream) = upstream {
+            resolver.set_upstream(upstream);
+        }
+        if let Some(path) = self.cli.dns_cache.as_ref() {
+            let cache = DnsCache::default();
+            let path = Path::new(path);
+            if path.exists() {
+                cache
+                    .load(path)
+                    .with_context(|| format!("读取DNS缓存文件 {} 失败", path.display()))?;
+            }
+            let cache = Arc::new(cache);
+            resolver.set_cache(Arc::clone(&cache));
+            self.dns_cache = Some(cache);
+        }
+        Ok(Box::new(resolver))
+    }
+
+    // --doh-url/--doh-method/--dot-server: 加密的 DNS 服务器，DoH 与请求使用相同的代理
+    fn upstream(&self) -> Result<Option<Upstream>> {
+        if let Some(doh) = self.cli.doh_url.as_ref() {
+            let mut doh = doh.clone();
+            doh.method = self.cli.doh_method;
+            doh.tls = self.tls_config();
+            doh.proxy = self.proxy()?;
+            return Ok(Some(Upstream::Https(doh)));
+        }
+        let Some(mut dot) = self.cli.dot_server.clone() else {
+            return Ok(None);
+        };
+        dot.tls = self.tls_config();
+        Ok(Some(Upstream::Tls(dot)))
+    }
+
+    // --dns-cache: 将缓存写回文件
+    fn save_dns_cache(&self) -> Result<()> {
+        let (Some(path), Some(cache)) = (self.cli.dns_cache.as_ref(), self.dns_cache.as_ref())
+        else {
+            return Ok(());
+        };
+        debug!("写入 {} 条DNS缓存到 {}", cache.len(), path);
+        cache
+            .save(Path::new(path))
+            .with_context(|| format!("写入DNS缓存文件 {} 失败", path))
+    }
+
+    // -b/-c 开启 cookie 引擎；-b 不是 NAME=VALUE 形式时作为 cookie 文件读取，
+    // 与 curl 一样，文件不存在时忽略
+    fn cookie_jar(&self) -> Result<Option<CookieJar>> {
+        let file = self.cli.cookie.as_ref().filter(|c| !c.contains('='));
+        let Some(path) = file else {
+            return Ok(self.cli.cookie_jar.as_ref().map(|_| CookieJar::new()));
+        };
+        let path = Path::new(path);
+        if !path.exists() {
+            debug!("cookie文件 {} 不存在", path.display());
+            return Ok(Some(CookieJar::new()));
+        }
+        let jar = CookieJar::load(path)
+            .with_context(|| format!("读取cookie文件 {} 失败", path.display()))?;
+        Ok(Some(jar))
+    }
+
+    // -c: 将所有 cookie 写入文件
+    fn save_cookies(&self) -> Result<()> {
+        let (Some(path), Some(jar)) = (self.cli.cookie_jar.as_ref(), self.client.cookie_jar())
+        else {
+            return Ok(());
+        };
+        debug!("写入 {} 个cookie到 {}", jar.iter().count(), path);
+        jar.save(Path::new(path))
+            .with_context(|| format!("写入cookie文件 {} 失败", path))
+    }
+
+    // 打开输出文件: -o 指定的路径，或 -O/-J 从 URL、Content-Disposition 得到的文件名；
+    // 都未指定时返回 None，输出到标准输出
+    fn output_file(&self, response: &Response) -> Result<Option<(File, PathBuf)>> {
+        if let Some(out) = self.cli.out.as_ref() {
+            let file = File::create(out).with_context(|| format!("创建文件 {} 失败", out))?;
+            return Ok(Some((file, out.into())));
+        }
+        if !self.cli.remote_name {
+            return Ok(None);
+        }
+        if self.cli.remote_header_name
+            && let Some(name) = response
+                .suggested_filename()
+                .and_then(|name| sanitize_filename(&name))
+        {
+            // 与 curl 一样，不覆盖已存在的文件
+            let file = OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&name)
+                .with_context(|| format!("创建文件 {} 失败", name))?;
+            return Ok(Some((file, name.into())));
+        }
+        let name = remote_name(self.url())
+            .ok_or_else(|| anyhow!("无法从URL中获取文件名: {}", self.url()))?;
+        let file = File::create(&name).with_context(|| format!("创建文件 {} 失败", name))?;
+        Ok(Some((file, name.into())))
+    }
+
+    // 没有子命令时 clap 保证指定了 URL
+    fn url(&self) -> &str {
+        self.cli.url.as_deref().unwrap_or_default()
+    }
+
+    // 未指定 -X 时，有请求体使用 POST，否则使用 GET
+    fn request_method(&self) -> Method {
+        self.cli.x.unwrap_or(if self.cli.data.is_some() {
+            Method::POST
+        } else {
+            Method::GET
+        })
+    }
+
+    // 解析 -d 参数: @FILE 从文件读取，@- 从标准输入读取，
+    // 与 curl 一样去掉从文件读取内容中的换行符
+    fn request_body(&self) -> Result<Option<Vec<u8>>> {
+        let Some(data) = self.cli.data.as_ref() else {
+            return Ok(None);
+        };
+        let Some(path) = data.strip_prefix('@') else {
+            return Ok(Some(data.as_bytes().to_vec()));
+        };
+        let mut body = Vec::new();
+        if path == "-" {
+            std::io::stdin()
+                .read_to_end(&mut body)
+                .context("读取标准输入失败")?;
+        } else {
+            body = std::fs::read(path).with_context(|| format!("读取文件 {} 失败", path))?;
+        }
+        body.retain(|&b| b != b'\r' && b != b'\n');
+        Ok(Some(body))
+    }
+}
+
+// -b NAME=VALUE: 与 -H 一样校验后直接作为 Cookie 头发送，其他值是 cookie 文件
+fn apply_cookie(headers: &mut Headers, cookie: Option<&str>) -> Result<()> {
+    if let Some(cookie) = cookie
+        && cookie.contains('=')
+    {
+        headers.try_set("Cookie", cookie)?;
+    }
+    Ok(())
+}
+
+// 应用 -H 参数，与 curl 相同:
+// "Name: value" 替换同名的默认请求头，同一个名称多次指定时全部发送；
+// "Name:" 删除该请求头；"Name;" 发送值为空的请求头
+fn apply_headers(headers: &mut Headers, args: &[String]) -> Result<()> {
+    let mut seen: Vec<&str> = Vec::new();
+    for arg in args {
+        if let Some((name, value)) = arg.split_once(':') {
+            let (name, value) = (name.trim(), value.trim());
+            if value.is_empty() {
+                headers.remove(name);
+            } else if seen.iter().any(|s| s.eq_ignore_ascii_case(name)) {
+                headers.try_append(name, value)?;
+            } else {
+                headers.try_set(name, value)?;
             }
-            request.borrow_mut().set(header[0].to_string(), header[1].to_string());
+            seen.push(name);
+        } else if let Some(name) = arg.trim_end().strip_suffix(';') {
+            headers.try_set(name.trim(), "")?;
+            seen.push(name.trim());
+        } else {
+            return Err(anyhow!("无效的请求头格式: {}", arg));
+        }
+    }
+    Ok(())
+}
+
+// URL 路径的最后一段作为文件名
+fn remote_name(url: &str) -> Option<String> {
+    let url = Url::parse(url).ok()?;
+    let segment = url.path.rsplit('/').next()?;
+    sanitize_filename(&percent_decode_str(segment).decode_utf8_lossy())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::models::dns::DohMethod;
+    use clap::Parser;
+
+    fn app(args: &[&str]) -> App {
+        let mut argv = vec!["rcurl"];
+        argv.extend_from_slice(args);
+        App::new(Cli::parse_from(argv))
+    }
+
+    #[test]
+    fn test_remote_name() {
+        assert_eq!(
+            remote_name("http://localhost/files/report%20v2.pdf?dl=1").as_deref(),
+            Some("report v2.pdf")
+        );
+        assert_eq!(
+            remote_name("http://localhost/a/..%2F..%2Fetc%2Fpasswd").as_deref(),
+            Some("passwd")
+        );
+        assert_eq!(remote_name("http://localhost/"), None);
+        assert_eq!(remote_name("http://localhost"), None);
+    }
+
+    #[test]
+    fn test_request_method() {
+        assert_eq!(app(&["http://localhost"]).request_method(), Method::GET);
+        assert_eq!(
+            app(&["-d", "a=1", "http://localhost"]).request_method(),
+            Method::POST
+        );
+        assert_eq!(
+            app(&["-X", "PUT", "-d", "a=1", "http://localhost"]).request_method(),
+            Method::PUT
+        );
+    }
+
+    #[test]
+    fn test_upstream() -> Result<()> {
+        assert!(app(&["http://localhost"]).upstream()?.is_none());
+        let upstream = app(&[
+            "-k",
+            "--doh-url",
+            "https://dns.example/dns-query",
+            "--doh-method",
+            "get",
+            "-x",
+            "proxy.example:3128",
+            "http://localhost",
+        ])
+        .upstream()?;
+        assert!(matches!(
+            upstream,
+            Some(Upstream::Https(doh)) if doh.method == DohMethod::Get
+                && doh.tls.insecure
+                && doh.proxy.proxy_for(&Url::parse(&doh.url).unwrap()).is_some()
+        ));
+        let upstream = app(&["--dot-server", "1.1.1.1", "http://localhost"]).upstream()?;
+        assert!(matches!(
+            upstream,
+            Some(Upstream::Tls(dot)) if dot.addr() == "1.1.1.1:853"
+        ));
+        assert!(
+            app(&[
+                "--resolver",
+                "system",
+                "--dot-server",
+                "1.1.1.1",
+                "http://localhost"
+            ])
+            .resolver()
+            .is_err()
+        );
+        let invalid: [&[&str]; 3] = [
+            &[
+                "--doh-url",
+                "https://dns.example/",
+                "--dot-server",
+                "1.1.1.1",
+            ],
+            &["--dns-servers", "8.8.8.8", "--dot-server", "1.1.1.1"],
+            &["--doh-url", "ftp://dns.example/"],
+        ];
+        for args in invalid {
+            let mut argv = vec!["rcurl"];
+            argv.extend_from_slice(args);
+            argv.push("http://localhost");
+            assert!(Cli::try_parse_from(argv).is_err(), "{:?}", args);
         }
-        let mut response = self.client.execute()?;
-        println!("{}", String::from_utf8_lossy(&response.body));
         Ok(())
     }
+
+    #[test]
+    fn test_proxy() -> Result<()> {
+        use crate::models::proxy::ProxyKind;
+        let url = Url::parse("https://example.com/")?;
+        let config = app(&[
+            "--socks5-hostname",
+            "jump:1081",
+            "-U",
+            "u:p",
+            "http://localhost",
+        ])
+        .proxy()?;
+        let proxy = config.proxy_for(&url).unwrap();
+        assert_eq!(proxy.kind, ProxyKind::Socks5h);
+        assert_eq!(proxy.addr(), "jump:1081");
+        let config = app(&["-x", "", "http://localhost"]).proxy()?;
+        assert_eq!(config.proxy_for(&url), None);
+        assert!(
+            Cli::try_parse_from(["rcurl", "-x", "p:3128", "--socks5", "s", "http://localhost"])
+                .is_err()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_retry_policy() {
+        assert_eq!(app(&["http://localhost"]).retry_policy().max_retries, 0);
+        assert_eq!(
+            app(&["--retry", "3", "http://localhost"])
+                .retry_policy()
+                .max_retries,
+            3
+        );
+    }
+
+    #[test]
+    fn test_oauth2() -> Result<()> {
+        let resolver: Rc<dyn Resolver> = Rc::new(SystemResolver);
+        assert!(app(&["http://localhost"]).oauth2(&resolver)?.is_none());
+        assert!(
+            app(&[
+                "--oauth2-token-url",
+                "http://localhost/token",
+                "--oauth2-client-id",
+                "id",
+                "http://localhost"
+            ])
+            .oauth2(&resolver)?
+            .is_some()
+        );
+        let invalid: [&[&str]; 3] = [
+            &["--oauth2-token-url", "http://localhost/token"],
+            &["--oauth2-client-id", "id"],
+            &[
+                "--oauth2-token-url",
+                "http://localhost/token",
+                "--oauth2-client-id",
+                "id",
+                "-u",
+                "user:pass",
+            ],
+        ];
+        for args in invalid {
+            let mut argv = vec!["rcurl"];
+            argv.extend_from_slice(args);
+            argv.push("http://localhost");
+            assert!(Cli::try_parse_from(argv).is_err(), "{:?}", args);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_request_body() -> Result<()> {
+        assert_eq!(app(&["http://localhost"]).request_body()?, None);
+        assert_eq!(
+            app(&["-d", "a=1&b=2", "http://localhost"]).request_body()?,
+            Some(b"a=1&b=2".to_vec())
+        );
+        let path = std::env::temp_dir().join(format!("rcurl-test-data-{}", std::process::id()));
+        std::fs::write(&path, "a=1\r\n&b=2\n")?;
+        let arg = format!("@{}", path.display());
+        let body = app(&["-d", &arg, "http://localhost"]).request_body();
+        std::fs::remove_file(&path)?;
+        assert_eq!(body?, Some(b"a=1&b=2".to_vec()));
+        assert!(
+            app(&["-d", "@/nonexistent/rcurl", "http://localhost"])
+                .request_body()
+                .is_err()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_apply_headers() -> Result<()> {
+        let mut headers = Headers::default();
+        let args = [
+            "accept: text/html",
+            "X-Tag: a",
+            "x-tag:  b ",
+            "User-Agent:",
+            "X-Empty;",
+        ]
+        .map(String::from);
+        apply_headers(&mut headers, &args)?;
+        assert_eq!(headers.get("Accept").unwrap(), "text/html");
+        assert_eq!(headers.get_all("X-Tag").collect::<Vec<_>>(), ["a", "b"]);
+        assert!(!headers.contains("User-Agent"));
+        assert_eq!(headers.get("X-Empty").unwrap(), "");
+        assert!(apply_headers(&mut headers, &["X-Bad".to_string()]).is_err());
+        assert!(apply_headers(&mut headers, &["X-Bad: a\nb".to_string()]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_apply_cookie() -> Result<()> {
+        let mut headers = Headers::default();
+        apply_cookie(&mut headers, Some("a=1; b=2"))?;
+        assert_eq!(headers.get("Cookie").unwrap(), "a=1; b=2");
+        // cookie 文件不作为请求头
+        let mut headers = Headers::default();
+        apply_cookie(&mut headers, Some("cookies.txt"))?;
+        assert!(!headers.contains("Cookie"));
+        assert!(apply_cookie(&mut headers, Some("a=1\r\nX-Evil: 1")).is_err());
+        assert!(!headers.contains("Cookie"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_cookie_jar_short_flag() {
+        // -c 是 --cookie-jar，重试次数只能用 --retry 指定
+        let cli = Cli::parse_from(["rcurl", "-c", "3", "--retry", "2", "http://localhost"]);
+        assert_eq!(cli.cookie_jar.as_deref(), Some("3"));
+        assert_eq!(cli.retry, Some(2));
+    }
 }
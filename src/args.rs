@@ -1,22 +1,54 @@
 use crate::models::Method;
-use clap::Parser;
+use crate::models::dns::{DohMethod, DohServer, DotServer, RecordType};
+use crate::models::resolver::{
+    ConnectTo, ResolveEntry, ResolverKind, parse_connect_to, parse_dns_server, parse_resolve,
+};
+use crate::models::retry::Backoff;
+use clap::{Args, Parser, Subcommand};
+use std::net::{IpAddr, SocketAddr};
 #[derive(Parser, Debug)]
-#[command(author, version, about)]
+#[command(
+    author,
+    version,
+    about,
+    subcommand_negates_reqs = true,
+    args_conflicts_with_subcommands = true
+)]
 pub struct Cli {
-    #[arg(short = 'X', long = "X", value_enum, default_value_t = Method::GET, help="请求方式")]
-    pub x: Method,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+    #[arg(
+        short = 'X',
+        long = "X",
+        value_enum,
+        help = "请求方式，默认为GET，设置了请求体时为POST"
+    )]
+    pub x: Option<Method>,
     #[arg(required = true, value_name = "URL")]
-    pub url: String,
+    pub url: Option<String>,
     #[arg(short, long, help = "Output file", value_name = "FILE")]
     pub out: Option<String>,
-    #[arg(short = 'v', long, help = "启用详细日志输出")]
+    #[arg(
+        short = 'O',
+        long = "remote-name",
+        help = "使用URL中的文件名保存响应体"
+    )]
+    pub remote_name: bool,
+    #[arg(
+        short = 'J',
+        long = "remote-header-name",
+        help = "配合-O使用，优先使用Content-Disposition中的文件名",
+        requires = "remote_name"
+    )]
+    pub remote_header_name: bool,
+    #[arg(short = 'v', long, global = true, help = "启用详细日志输出")]
     pub verbose: bool,
     #[arg(short = 'H', long, help = "设置请求头", value_name = "HEADER")]
     pub headers: Vec<String>,
     #[arg(
         short = 'd',
         long,
-        help = "POST请求时，设置请求体",
+        help = "POST请求时，设置请求体，@FILE 从文件读取，@- 从标准输入读取",
         value_name = "DATA"
     )]
     pub data: Option<String>,
@@ -28,20 +60,292 @@ pub struct Cli {
         value_name = "TIMEOUT"
     )]
     pub timeout: u64,
+    // 只有长选项: -c 与 curl 一样用于 --cookie-jar
     #[arg(
-        short = 'c',
         long,
-        help = "设置最大重试次数",
-        default_value = "4",
+        help = "设置最大重试次数，默认不重试(只能使用 --retry，-c 为 --cookie-jar)",
         value_name = "RETRY"
     )]
-    pub retry: u64,
+    pub retry: Option<u32>,
     #[arg(
         short = 's',
         long,
-        help = "设置请求间隔时间",
+        help = "设置重试间隔时间(秒)，指数退避时为初始间隔",
         default_value = "4",
         value_name = "INTERVAL"
     )]
     pub interval: u64,
+    #[arg(
+        long = "retry-backoff",
+        value_enum,
+        help = "重试间隔的计算方式",
+        default_value = "fixed",
+        value_name = "BACKOFF"
+    )]
+    pub retry_backoff: Backoff,
+    #[arg(
+        long = "retry-status",
+        help = "需要重试的响应状态码，多个用逗号分隔",
+        value_delimiter = ',',
+        default_value = "408,429,500,502,503,504",
+        value_name = "CODES"
+    )]
+    pub retry_status: Vec<u16>,
+    #[arg(long = "retry-all", help = "POST等非幂等请求失败时也进行重试")]
+    pub retry_all: bool,
+    #[arg(
+        short = 'b',
+        long,
+        help = "发送cookie，NAME=VALUE 形式的字符串直接发送，否则作为cookie文件读取",
+        value_name = "DATA|FILE"
+    )]
+    pub cookie: Option<String>,
+    #[arg(
+        short = 'c',
+        long = "cookie-jar",
+        help = "请求结束后将所有cookie写入Netscape格式的文件",
+        value_name = "FILE"
+    )]
+    pub cookie_jar: Option<String>,
+    #[arg(long, help = "请求压缩的响应并自动解压")]
+    pub compressed: bool,
+    #[arg(long, help = "使用指定的CA证书文件校验服务器证书", value_name = "FILE")]
+    pub cacert: Option<String>,
+    #[arg(short = 'k', long, help = "跳过服务器证书校验")]
+    pub insecure: bool,
+    #[arg(short = 'L', long, help = "自动跟随重定向")]
+    pub location: bool,
+    #[arg(
+        long = "max-redirs",
+        help = "最多跟随的重定向次数",
+        default_value = "50",
+        value_name = "NUM"
+    )]
+    pub max_redirs: usize,
+    #[arg(
+        long,
+        value_enum,
+        help = "域名解析器，指定了--dns-servers时默认为builtin，否则为system",
+        value_name = "RESOLVER"
+    )]
+    pub resolver: Option<ResolverKind>,
+    #[arg(
+        long = "dns-servers",
+        help = "内置解析器使用的DNS服务器，多个地址用逗号分隔",
+        value_delimiter = ',',
+        value_parser = parse_dns_server,
+        value_name = "ADDRESSES"
+    )]
+    pub dns_servers: Vec<SocketAddr>,
+    #[arg(
+        long = "dns-cache",
+        help = "内置解析器的DNS缓存文件，运行前读取、结束后写回",
+        value_name = "FILE"
+    )]
+    pub dns_cache: Option<String>,
+    #[arg(
+        long = "doh-url",
+        help = "通过DNS-over-HTTPS服务器解析域名，使用内置解析器",
+        value_parser = DohServer::parse,
+        value_name = "URL",
+        conflicts_with_all = ["dns_servers", "dot_server"]
+    )]
+    pub doh_url: Option<DohServer>,
+    #[arg(
+        long = "doh-method",
+        value_enum,
+        help = "DoH查询使用的请求方式",
+        default_value = "post",
+        value_name = "METHOD"
+    )]
+    pub doh_method: DohMethod,
+    #[arg(
+        long = "dot-server",
+        help = "通过DNS-over-TLS服务器解析域名，使用内置解析器，默认端口为853",
+        value_parser = DotServer::parse,
+        value_name = "HOST[:PORT]",
+        conflicts_with = "dns_servers"
+    )]
+    pub dot_server: Option<DotServer>,
+    #[arg(
+        long,
+        help = "将host:port解析为指定的地址，可以多次使用",
+        value_parser = parse_resolve,
+        value_name = "HOST:PORT:ADDR[,ADDR]"
+    )]
+    pub resolve: Vec<ResolveEntry>,
+    #[arg(
+        long = "connect-to",
+        help = "连接HOST1:PORT1时改为连接HOST2:PORT2，Host头和SNI不变，可以多次使用",
+        value_parser = parse_connect_to,
+        value_name = "HOST1:PORT1:HOST2:PORT2"
+    )]
+    pub connect_to: Vec<ConnectTo>,
+    #[arg(
+        short = 'u',
+        long,
+        help = "服务器认证的用户名和密码，默认使用Basic认证",
+        value_name = "USER:PASSWORD"
+    )]
+    pub user: Option<String>,
+    #[arg(long, help = "使用Digest认证", conflicts_with = "anyauth")]
+    pub digest: bool,
+    #[arg(long, help = "根据服务器的质询自动选择最安全的认证方式")]
+    pub anyauth: bool,
+    #[arg(
+        short = 'n',
+        long,
+        help = "从~/.netrc读取服务器的用户名和密码",
+        conflicts_with = "netrc_optional"
+    )]
+    pub netrc: bool,
+    #[arg(
+        long = "netrc-optional",
+        help = "与--netrc相同，但.netrc文件不存在时不报错"
+    )]
+    pub netrc_optional: bool,
+    #[arg(
+        long = "netrc-file",
+        help = "从指定的文件读取服务器的用户名和密码，代替~/.netrc",
+        value_name = "FILE"
+    )]
+    pub netrc_file: Option<String>,
+    #[arg(
+        short = 'x',
+        long,
+        help = "使用代理，格式为[scheme://][user:password@]host[:port]，支持http、socks4a、socks5和socks5h，为空时不使用代理",
+        value_name = "PROXY"
+    )]
+    pub proxy: Option<String>,
+    #[arg(
+        long,
+        help = "使用SOCKS5代理，目标域名在本地解析",
+        value_name = "HOST[:PORT]",
+        conflicts_with_all = ["proxy", "socks5_hostname", "socks4a"]
+    )]
+    pub socks5: Option<String>,
+    #[arg(
+        long = "socks5-hostname",
+        help = "使用SOCKS5代理，目标域名由代理解析",
+        value_name = "HOST[:PORT]",
+        conflicts_with_all = ["proxy", "socks4a"]
+    )]
+    pub socks5_hostname: Option<String>,
+    #[arg(
+        long,
+        help = "使用SOCKS4a代理，目标域名由代理解析",
+        value_name = "HOST[:PORT]",
+        conflicts_with = "proxy"
+    )]
+    pub socks4a: Option<String>,
+    #[arg(
+        short = 'U',
+        long = "proxy-user",
+        help = "代理认证的用户名和密码",
+        value_name = "USER:PASSWORD"
+    )]
+    pub proxy_user: Option<String>,
+    #[arg(
+        short = '4',
+        long = "ipv4",
+        help = "只使用IPv4地址",
+        conflicts_with = "ipv6"
+    )]
+    pub ipv4: bool,
+    #[arg(short = '6', long = "ipv6", help = "只使用IPv6地址")]
+    pub ipv6: bool,
+    #[command(flatten)]
+    pub oauth2: OAuth2Args,
+}
+
+// 使用 OAuth2 客户端凭据或刷新令牌获取访问令牌，代替 -u 的用户名和密码
+#[derive(Args, Debug, Default)]
+#[command(next_help_heading = "OAuth2")]
+pub struct OAuth2Args {
+    #[arg(
+        long = "oauth2-token-url",
+        help = "OAuth2令牌端点，获取访问令牌后通过Bearer认证发送请求",
+        value_name = "URL",
+        requires = "client_id",
+        conflicts_with = "user"
+    )]
+    pub token_url: Option<String>,
+    #[arg(
+        long = "oauth2-client-id",
+        help = "OAuth2客户端ID",
+        value_name = "ID",
+        requires = "token_url"
+    )]
+    pub client_id: Option<String>,
+    #[arg(
+        long = "oauth2-client-secret",
+        help = "OAuth2客户端密钥，通过Basic认证发送给令牌端点",
+        value_name = "SECRET",
+        requires = "token_url"
+    )]
+    pub client_secret: Option<String>,
+    #[arg(
+        long = "oauth2-scope",
+        help = "申请的权限范围，多个范围用空格分隔",
+        value_name = "SCOPE",
+        requires = "token_url"
+    )]
+    pub scope: Option<String>,
+    #[arg(
+        long = "oauth2-refresh-token",
+        help = "优先使用刷新令牌获取访问令牌，失败时改用客户端凭据",
+        value_name = "TOKEN",
+        requires = "token_url"
+    )]
+    pub refresh_token: Option<String>,
+    #[arg(
+        long = "oauth2-token-cache",
+        help = "访问令牌的缓存文件，令牌过期前重复使用",
+        value_name = "FILE",
+        requires = "token_url"
+    )]
+    pub token_cache: Option<String>,
+}
+
+// 子命令，不指定子命令时发送 HTTP 请求
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// 使用内置解析器查询DNS记录，用法与dig相同
+    Dig(DigArgs),
+}
+
+#[derive(Args, Debug)]
+pub struct DigArgs {
+    #[arg(
+        required_unless_present = "reverse",
+        help = "查询的域名和类型，@SERVER 指定DNS服务器，+trace/+short/+json/+tcp 与对应的选项相同",
+        value_name = "[@SERVER] NAME [TYPE] [+OPTION]"
+    )]
+    pub query: Vec<String>,
+    #[arg(
+        short = 't',
+        long = "type",
+        help = "查询的记录类型，默认为A",
+        value_name = "TYPE"
+    )]
+    pub qtype: Option<RecordType>,
+    #[arg(short = 'x', help = "反向查询IP地址的PTR记录", value_name = "ADDR")]
+    pub reverse: Option<IpAddr>,
+    #[arg(short = 'p', long, help = "DNS服务器的端口", value_name = "PORT")]
+    pub port: Option<u16>,
+    #[arg(long, help = "从根服务器开始迭代查询，显示每一步的响应")]
+    pub trace: bool,
+    #[arg(long, help = "只输出回答部分的记录数据")]
+    pub short: bool,
+    #[arg(long, help = "以JSON格式输出")]
+    pub json: bool,
+    #[arg(long, help = "使用TCP查询")]
+    pub tcp: bool,
+    #[arg(
+        long,
+        help = "等待一个服务器响应的时间(秒)",
+        default_value = "5",
+        value_name = "SECONDS"
+    )]
+    pub timeout: u64,
 }
@@ -0,0 +1,695 @@
+//! dig 子命令: 使用内置解析器的查询代码查询任意类型的记录，便于排查域名解析问题
+use crate::args::DigArgs;
+use crate::models::dns::{self, Message, RData, Record, RecordType, Reply, ResolvConf};
+use crate::models::resolver::parse_dns_server;
+use anyhow::{Result, anyhow};
+use log::debug;
+use std::fmt::Write;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::time::{Duration, Instant};
+
+// 根服务器的 IPv4 地址(https://www.iana.org/domains/root/servers)
+const ROOT_HINTS: [(&str, Ipv4Addr); 13] = [
+    ("a.root-servers.net", Ipv4Addr::new(198, 41, 0, 4)),
+    ("b.root-servers.net", Ipv4Addr::new(170, 247, 170, 2)),
+    ("c.root-servers.net", Ipv4Addr::new(192, 33, 4, 12)),
+    ("d.root-servers.net", Ipv4Addr::new(199, 7, 91, 13)),
+    ("e.root-servers.net", Ipv4Addr::new(192, 203, 230, 10)),
+    ("f.root-servers.net", Ipv4Addr::new(192, 5, 5, 241)),
+    ("g.root-servers.net", Ipv4Addr::new(192, 112, 36, 4)),
+    ("h.root-servers.net", Ipv4Addr::new(198, 97, 190, 53)),
+    ("i.root-servers.net", Ipv4Addr::new(192, 36, 148, 17)),
+    ("j.root-servers.net", Ipv4Addr::new(192, 58, 128, 30)),
+    ("k.root-servers.net", Ipv4Addr::new(193, 0, 14, 129)),
+    ("l.root-servers.net", Ipv4Addr::new(199, 7, 83, 42)),
+    ("m.root-servers.net", Ipv4Addr::new(202, 12, 27, 33)),
+];
+// 迭代查询最多经过的引用次数
+const MAX_TRACE_STEPS: usize = 16;
+
+// 合并命令行选项和 dig 风格参数后的查询
+#[derive(Debug, PartialEq, Eq)]
+struct Query {
+    server: Option<String>,
+    name: String,
+    qtype: RecordType,
+    trace: bool,
+    short: bool,
+    json: bool,
+    tcp: bool,
+}
+
+// 一次查询的结果，迭代查询时每一步一个
+struct Step {
+    // 服务器的名称，直接指定地址时为地址
+    server: String,
+    addr: SocketAddr,
+    reply: Reply,
+    elapsed: Duration,
+}
+
+pub fn run(args: &DigArgs) -> Result<()> {
+    let query = parse_query(args)?;
+    let timeout = Duration::from_secs(args.timeout.max(1));
+    if query.trace {
+        let roots: Vec<(String, SocketAddr)> = ROOT_HINTS
+            .iter()
+            .map(|(name, ip)| (name.to_string(), SocketAddr::new(IpAddr::V4(*ip), 53)))
+            .collect();
+        let mut steps = Vec::new();
+        let result = trace(&query, roots, args.port, timeout, &mut |step| {
+            if query.json {
+                steps.push(step_json(&step));
+            } else {
+                print!("{}", format_trace_step(&step, query.short));
+            }
+        });
+        if query.json {
+            println!("[{}]", steps.join(","));
+        }
+        return result;
+    }
+
+    let servers = servers(query.server.as_deref(), args.port)?;
+    let step = ask(&query, &servers, timeout, true)?;
+    if query.json {
+        println!("{}", step_json(&step));
+    } else if query.short {
+        print!("{}", format_short(&step.reply.message));
+    } else {
+        print!("{}", format_step(&query, &step));
+    }
+    Ok(())
+}
+
+// 解析 dig 风格的参数: @SERVER、+选项，以及域名和类型(顺序任意，与 dig 一样类型也可以写在前面)
+fn parse_query(args: &DigArgs) -> Result<Query> {
+    let mut query = Query {
+        server: None,
+        name: String::new(),
+        qtype: args.qtype.unwrap_or(RecordType::A),
+        trace: args.trace,
+        short: args.short,
+        json: args.json,
+        tcp: args.tcp,
+    };
+    let mut plain = Vec::new();
+    for arg in &args.query {
+        if let Some(server) = arg.strip_prefix('@') {
+            query.server = Some(server.to_string());
+        } else if let Some(option) = arg.strip_prefix('+') {
+            match option {
+                "trace" => query.trace = true,
+                "notrace" => query.trace = false,
+                "short" => query.short = true,
+                "noshort" => query.short = false,
+                "json" => query.json = true,
+                "tcp" | "vc" => query.tcp = true,
+                "notcp" | "novc" => query.tcp = false,
+                _ => return Err(anyhow!("未知的选项: {}", arg)),
+            }
+        } else {
+            plain.push(arg.as_str());
+        }
+    }
+    let name = match (args.reverse, plain.as_slice()) {
+        (Some(ip), []) => {
+            query.qtype = RecordType::PTR;
+            reverse_name(ip)
+        }
+        (Some(_), _) => return Err(anyhow!("-x 不能与域名同时使用")),
+        (None, [name]) => name.to_string(),
+        (None, [first, second]) => match (first.parse::<RecordType>(), second.parse()) {
+            (Ok(qtype), Err(_)) => {
+                query.qtype = qtype;
+                second.to_string()
+            }
+            (_, Ok(qtype)) => {
+                query.qtype = qtype;
+                first.to_string()
+            }
+            (Err(e), Err(_)) => return Err(anyhow!(e)),
+        },
+        (None, _) => return Err(anyhow!("只能查询一个域名")),
+    };
+    // 查询报文中的域名不带结尾的 '.'，根域为空
+    query.name = name.trim_end_matches('.').to_string();
+    Ok(query)
+}
+
+// -x: IPv4 地址反转后加 in-addr.arpa，IPv6 地址按半字节反转后加 ip6.arpa
+fn reverse_name(ip: IpAddr) -> String {
+    match ip {
+        IpAddr::V4(ip) => {
+            let octets: Vec<String> = ip.octets().iter().rev().map(u8::to_string).collect();
+            format!("{}.in-addr.arpa", octets.join("."))
+        }
+        IpAddr::V6(ip) => {
+            let nibbles: Vec<String> = ip
+                .octets()
+                .iter()
+                .rev()
+                .flat_map(|b| [b & 0x0F, b >> 4])
+                .map(|n| format!("{:x}", n))
+                .collect();
+            format!("{}.ip6.arpa", nibbles.join("."))
+        }
+    }
+}
+
+// @SERVER 可以是地址或者域名，域名使用内置解析器查询；未指定时使用 resolv.conf 中的服务器
+fn servers(server: Option<&str>, port: Option<u16>) -> Result<Vec<(String, SocketAddr)>> {
+    let mut servers: Vec<(String, SocketAddr)> = match server {
+        None => ResolvConf::system()
+            .nameservers
+            .iter()
+            .map(|addr| (addr.ip().to_string(), *addr))
+            .collect(),
+        Some(server) => match parse_dns_server(server) {
+            Ok(addr) => vec![(addr.ip().to_string(), addr)],
+            Err(_) => dns::resolve_domain(server)
+                .map_err(|e| anyhow!("无法解析DNS服务器 {}: {}", server, e))?
+                .into_iter()
+                .map(|ip| (server.to_string(), SocketAddr::new(ip, 53)))
+                .collect(),
+        },
+    };
+    if let Some(port) = port {
+        for (_, addr) in servers.iter_mut() {
+            addr.set_port(port);
+        }
+    }
+    Ok(servers)
+}
+
+// 依次询问每个服务器，返回第一个响应
+fn ask(
+    query: &Query,
+    servers: &[(String, SocketAddr)],
+    timeout: Duration,
+    recursive: bool,
+) -> Result<Step> {
+    let mut message = dns::build_query(&query.name, query.qtype, true).map_err(|e| anyhow!(e))?;
+    message.header.rd = recursive;
+    let mut last_error = "没有可用的DNS服务器".to_string();
+    for (server, addr) in servers {
+        let start = Instant::now();
+        match dns::send(&message, *addr, timeout, query.tcp) {
+            Ok(reply) => {
+                return Ok(Step {
+                    server: server.clone(),
+                    addr: *addr,
+                    reply,
+                    elapsed: start.elapsed(),
+                });
+            }
+            Err(e) => {
+                debug!("DNS服务器 {} 查询失败: {}", addr, e);
+                last_error = format!("{}: {}", addr, e);
+            }
+        }
+    }
+    Err(anyhow!("DNS查询失败: {}", last_error))
+}
+
+// +trace: 从根服务器开始不使用递归，沿着权威部分的 NS 引用逐级查询，直到得到回答。
+// 引用的区域必须比当前区域更接近查询的域名，防止服务器返回的引用形成循环
+fn trace(
+    query: &Query,
+    roots: Vec<(String, SocketAddr)>,
+    port: Option<u16>,
+    timeout: Duration,
+    emit: &mut dyn FnMut(Step),
+) -> Result<()> {
+    let port = port.unwrap_or(53);
+    let mut servers = roots;
+    for (_, addr) in servers.iter_mut() {
+        addr.set_port(port);
+    }
+    let mut zone = String::new();
+    for _ in 0..MAX_TRACE_STEPS {
+        let step = ask(query, &servers, timeout, false)?;
+        let message = step.reply.message.clone();
+        emit(step);
+        if message.header.rcode != 0 || message.header.aa || !message.answers.is_empty() {
+            return Ok(());
+        }
+        let referral: Vec<&Record> = message
+            .authorities
+            .iter()
+            .filter(|record| matches!(record.data, RData::NS(_)))
+            .collect();
+        let Some(next_zone) = referral.first().map(|record| record.name.clone()) else {
+            return Ok(());
+        };
+        if !is_subdomain(&query.name, &next_zone) || labels(&next_zone) <= labels(&zone) {
+            return Err(anyhow!("服务器返回了无效的引用: {}", dns::fqdn(&next_zone)));
+        }
+        servers = delegated_servers(&message, &referral, port);
+        if servers.is_empty() {
+            return Err(anyhow!(
+                "无法获得 {} 的DNS服务器地址",
+                dns::fqdn(&next_zone)
+            ));
+        }
+        zone = next_zone;
+    }
+    Err(anyhow!("迭代查询的引用次数过多"))
+}
+
+// 引用的服务器地址: 优先使用附加部分的粘合记录，IPv4 地址在前；
+// 没有粘合记录时用内置解析器查询服务器的地址
+fn delegated_servers(
+    message: &Message,
+    referral: &[&Record],
+    port: u16,
+) -> Vec<(String, SocketAddr)> {
+    let names: Vec<&str> = referral
+        .iter()
+        .filter_map(|record| match &record.data {
+            RData::NS(name) => Some(name.as_str()),
+            _ => None,
+        })
+        .collect();
+    let mut servers: Vec<(String, SocketAddr)> = Vec::new();
+    for name in &names {
+        for record in &message.additionals {
+            if !record.name.eq_ignore_ascii_case(name) {
+                continue;
+            }
+            let ip = match record.data {
+                RData::A(ip) => IpAddr::V4(ip),
+                RData::AAAA(ip) => IpAddr::V6(ip),
+                _ => continue,
+            };
+            servers.push((name.to_string(), SocketAddr::new(ip, port)));
+        }
+    }
+    if servers.is_empty() {
+        for name in names {
+            match dns::resolve_domain(name) {
+                Ok(ips) => servers.extend(
+                    ips.into_iter()
+                        .map(|ip| (name.to_string(), SocketAddr::new(ip, port))),
+                ),
+                Err(e) => debug!("无法解析DNS服务器 {}: {}", name, e),
+            }
+        }
+    }
+    servers.sort_by_key(|(_, addr)| addr.is_ipv6());
+    servers
+}
+
+fn is_subdomain(name: &str, zone: &str) -> bool {
+    let (name, zone) = (name.to_ascii_lowercase(), zone.to_ascii_lowercase());
+    zone.is_empty() || name == zone || name.ends_with(&format!(".{}", zone))
+}
+
+fn labels(name: &str) -> usize {
+    if name.is_empty() {
+        0
+    } else {
+        name.split('.').count()
+    }
+}
+
+// 与 dig 相同的完整输出
+fn format_step(query: &Query, step: &Step) -> String {
+    let message = &step.reply.message;
+    let header = &message.header;
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        "; <<>> rcurl dig <<>> {} {}",
+        dns::fqdn(&query.name),
+        query.qtype
+    );
+    let _ = writeln!(out, ";; Got answer:");
+    let _ = writeln!(
+        out,
+        ";; ->>HEADER<<- opcode: QUERY, status: {}, id: {}",
+        header.status(),
+        header.id
+    );
+    let _ = writeln!(
+        out,
+        ";; flags: {}; QUERY: {}, ANSWER: {}, AUTHORITY: {}, ADDITIONAL: {}",
+        header.flag_names().join(" "),
+        message.questions.len(),
+        message.answers.len(),
+        message.authorities.len(),
+        message.additionals.len()
+    );
+    if let Some(size) = message.edns_payload_size() {
+        let _ = writeln!(out, "\n;; OPT PSEUDOSECTION:");
+        let _ = writeln!(out, "; EDNS: version: 0, flags:; udp: {}", size);
+    }
+    let _ = writeln!(out, "\n;; QUESTION SECTION:");
+    for question in &message.questions {
+        let _ = writeln!(
+            out,
+            ";{}\t\t{}\t{}",
+            dns::fqdn(&question.name),
+            dns::class_name(question.qclass),
+            question.qtype
+        );
+    }
+    out.push_str(&format_sections(message));
+    let _ = writeln!(out, "\n;; Query time: {} msec", step.elapsed.as_millis());
+    let _ = writeln!(
+        out,
+        ";; SERVER: {}#{}({}) ({})",
+        step.addr.ip(),
+        step.addr.port(),
+        step.server,
+        if step.reply.tcp { "TCP" } else { "UDP" }
+    );
+    let _ = writeln!(out, ";; MSG SIZE  rcvd: {}", step.reply.size);
+    out
+}
+
+// 回答、权威和附加部分，OPT 伪记录不显示
+fn format_sections(message: &Message) -> String {
+    let mut out = String::new();
+    for (title, records) in [
+        ("ANSWER", &message.answers),
+        ("AUTHORITY", &message.authorities),
+        ("ADDITIONAL", &message.additionals),
+    ] {
+        let records: Vec<&Record> = records
+            .iter()
+            .filter(|record| record.rtype() != RecordType::OPT)
+            .collect();
+        if records.is_empty() {
+            continue;
+        }
+        let _ = writeln!(out, "\n;; {} SECTION:", title);
+        for record in records {
+            let _ = writeln!(out, "{}", record);
+        }
+    }
+    out
+}
+
+// +short: 只输出回答部分的记录数据
+fn format_short(message: &Message) -> String {
+    message
+        .answers
+        .iter()
+        .map(|record| format!("{}\n", record.data))
+        .collect()
+}
+
+// +trace 的一步: 响应中的记录和来源，与 dig +trace 相同
+fn format_trace_step(step: &Step, short: bool) -> String {
+    let message = &step.reply.message;
+    if short {
+        return format_short(message);
+    }
+    let mut out = String::new();
+    for record in message
+        .answers
+        .iter()
+        .chain(&message.authorities)
+        .chain(&message.additionals)
+        .filter(|record| record.rtype() != RecordType::OPT)
+    {
+        let _ = writeln!(out, "{}", record);
+    }
+    let _ = writeln!(
+        out,
+        ";; Received {} bytes from {}#{}({}) in {} ms",
+        step.reply.size,
+        step.addr.ip(),
+        step.addr.port(),
+        step.server,
+        step.elapsed.as_millis()
+    );
+    if message.header.rcode != 0 {
+        let _ = writeln!(out, ";; status: {}", message.header.status());
+    }
+    out.push('\n');
+    out
+}
+
+fn step_json(step: &Step) -> String {
+    let message = &step.reply.message;
+    let header = &message.header;
+    let flags: Vec<String> = header.flag_names().iter().map(|f| json_string(f)).collect();
+    let questions: Vec<String> = message
+        .questions
+        .iter()
+        .map(|question| {
+            format!(
+                "{{\"name\":{},\"type\":{},\"class\":{}}}",
+                json_string(&dns::fqdn(&question.name)),
+                json_string(&question.qtype.to_string()),
+                json_string(&dns::class_name(question.qclass))
+            )
+        })
+        .collect();
+    let records = |records: &[Record]| -> String {
+        let records: Vec<String> = records
+            .iter()
+            .filter(|record| record.rtype() != RecordType::OPT)
+            .map(record_json)
+            .collect();
+        format!("[{}]", records.join(","))
+    };
+    let edns = message
+        .edns_payload_size()
+        .map_or("null".to_string(), |size| size.to_string());
+    format!(
+        "{{\"server\":{},\"address\":{},\"protocol\":{},\"query_time_ms\":{},\"size\":{},\
+         \"id\":{},\"status\":{},\"flags\":[{}],\"edns_udp_size\":{},\"question\":[{}],\
+         \"answer\":{},\"authority\":{},\"additional\":{}}}",
+        json_string(&step.server),
+        json_string(&step.addr.to_string()),
+        json_string(if step.reply.tcp { "tcp" } else { "udp" }),
+        step.elapsed.as_millis(),
+        step.reply.size,
+        header.id,
+        json_string(&header.status()),
+        flags.join(","),
+        edns,
+        questions.join(","),
+        records(&message.answers),
+        records(&message.authorities),
+        records(&message.additionals)
+    )
+}
+
+fn record_json(record: &Record) -> String {
+    format!(
+        "{{\"name\":{},\"ttl\":{},\"class\":{},\"type\":{},\"data\":{}}}",
+        json_string(&dns::fqdn(&record.name)),
+        record.ttl,
+        json_string(&dns::class_name(record.class)),
+        json_string(&record.rtype().to_string()),
+        json_string(&record.data.to_string())
+    )
+}
+
+fn json_string(value: &str) -> String {
+    let mut out = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Cli;
+    use crate::args::Command;
+    use clap::Parser;
+    use std::net::UdpSocket;
+    use std::thread;
+
+    fn dig_args(args: &[&str]) -> DigArgs {
+        let mut argv = vec!["rcurl", "dig"];
+        argv.extend_from_slice(args);
+        match Cli::parse_from(argv).command {
+            Some(Command::Dig(args)) => args,
+            None => panic!("没有解析出 dig 子命令"),
+        }
+    }
+
+    fn query(args: &[&str]) -> Query {
+        parse_query(&dig_args(args)).unwrap()
+    }
+
+    #[test]
+    fn test_parse_query() {
+        let q = query(&["@1.1.1.1", "example.com.", "mx", "+short"]);
+        assert_eq!(q.server.as_deref(), Some("1.1.1.1"));
+        assert_eq!(q.name, "example.com");
+        assert_eq!(q.qtype, RecordType::MX);
+        assert!(q.short && !q.trace && !q.tcp);
+
+        let q = query(&["AAAA", "example.com", "+trace", "+json", "--tcp"]);
+        assert_eq!(
+            (q.name.as_str(), q.qtype),
+            ("example.com", RecordType::AAAA)
+        );
+        assert!(q.trace && q.json && q.tcp);
+        assert_eq!(query(&["-t", "txt", "example.com"]).qtype, RecordType::TXT);
+        assert_eq!(query(&["."]).name, "");
+
+        let q = query(&["-x", "192.0.2.10"]);
+        assert_eq!(
+            (q.name.as_str(), q.qtype),
+            ("10.2.0.192.in-addr.arpa", RecordType::PTR)
+        );
+        assert!(
+            query(&["-x", "2001:db8::1"])
+                .name
+                .starts_with("1.0.0.0.0.0.0.0.")
+        );
+        assert!(
+            query(&["-x", "2001:db8::1"])
+                .name
+                .ends_with(".8.b.d.0.1.0.0.2.ip6.arpa")
+        );
+
+        assert!(parse_query(&dig_args(&["example.com", "+bogus"])).is_err());
+        assert!(parse_query(&dig_args(&["a.example", "b.example"])).is_err());
+        assert!(parse_query(&dig_args(&["a", "b", "c"])).is_err());
+        assert!(Cli::try_parse_from(["rcurl", "dig"]).is_err());
+        // 不使用子命令时仍然需要 URL
+        assert!(Cli::try_parse_from(["rcurl"]).is_err());
+        assert!(Cli::try_parse_from(["rcurl", "dig", "-v", "example.com"]).is_ok());
+        assert!(Cli::try_parse_from(["rcurl", "-X", "GET", "dig", "example.com"]).is_err());
+    }
+
+    fn reply_to(query: &Message) -> Message {
+        let mut reply = query.clone();
+        reply.header.qr = true;
+        reply.additionals.clear();
+        reply
+    }
+
+    // 假的权威服务器: 第一个查询返回 example 区域的引用，第二个查询返回回答
+    fn fake_hierarchy() -> SocketAddr {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let addr = socket.local_addr().unwrap();
+        thread::spawn(move || {
+            let mut buf = [0u8; 512];
+            let (n, peer) = socket.recv_from(&mut buf).unwrap();
+            let query = Message::parse(&buf[..n]).unwrap();
+            assert!(!query.header.rd);
+            let mut referral = reply_to(&query);
+            referral.authorities = vec![Record::new(
+                "example",
+                172800,
+                RData::NS("ns1.example".to_string()),
+            )];
+            referral.additionals = vec![Record::new(
+                "ns1.example",
+                172800,
+                RData::A(Ipv4Addr::LOCALHOST),
+            )];
+            socket.send_to(&referral.to_bytes().unwrap(), peer).unwrap();
+
+            let (n, peer) = socket.recv_from(&mut buf).unwrap();
+            let query = Message::parse(&buf[..n]).unwrap();
+            let mut answer = reply_to(&query);
+            answer.header.aa = true;
+            answer.answers = vec![Record::new(
+                "www.example",
+                300,
+                RData::TXT(vec![b"say \"hi\"".to_vec()]),
+            )];
+            socket.send_to(&answer.to_bytes().unwrap(), peer).unwrap();
+        });
+        addr
+    }
+
+    #[test]
+    fn test_trace() -> Result<()> {
+        let server = fake_hierarchy();
+        let q = query(&["www.example", "TXT", "+trace"]);
+        let roots = vec![("root.test".to_string(), server)];
+        let mut steps = Vec::new();
+        trace(
+            &q,
+            roots,
+            Some(server.port()),
+            Duration::from_secs(2),
+            &mut |step| steps.push(step),
+        )?;
+        assert_eq!(steps.len(), 2);
+        assert_eq!(steps[1].server, "ns1.example");
+
+        let first = format_trace_step(&steps[0], false);
+        assert!(first.contains("example.\t172800\tIN\tNS\tns1.example.\n"));
+        assert!(first.contains("ns1.example.\t172800\tIN\tA\t127.0.0.1\n"));
+        assert!(first.contains(&format!("#{}(root.test) in ", server.port())));
+        assert_eq!(format_trace_step(&steps[1], true), "\"say \\\"hi\\\"\"\n");
+
+        let json = step_json(&steps[1]);
+        assert!(json.contains("\"flags\":[\"qr\",\"aa\"]"));
+        assert!(json.contains(
+            "\"answer\":[{\"name\":\"www.example.\",\"ttl\":300,\"class\":\"IN\",\"type\":\"TXT\",\"data\":\"\\\"say \\\\\\\"hi\\\\\\\"\\\"\"}]"
+        ));
+
+        let full = format_step(&q, &steps[1]);
+        assert!(full.contains(";; ->>HEADER<<- opcode: QUERY, status: NOERROR, id: "));
+        assert!(
+            full.contains(";; flags: qr aa; QUERY: 1, ANSWER: 1, AUTHORITY: 0, ADDITIONAL: 0\n")
+        );
+        assert!(full.contains(";www.example.\t\tIN\tTXT\n"));
+        assert!(full.contains(&format!(
+            ";; SERVER: 127.0.0.1#{}(ns1.example) (UDP)\n",
+            server.port()
+        )));
+        Ok(())
+    }
+
+    #[test]
+    fn test_referral_loop() {
+        // 引用指向与当前相同的区域
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let server = socket.local_addr().unwrap();
+        thread::spawn(move || {
+            let mut buf = [0u8; 512];
+            for _ in 0..2 {
+                let (n, peer) = socket.recv_from(&mut buf).unwrap();
+                let query = Message::parse(&buf[..n]).unwrap();
+                let mut referral = reply_to(&query);
+                referral.authorities = vec![Record::new(
+                    "example",
+                    60,
+                    RData::NS("ns.example".to_string()),
+                )];
+                referral.additionals =
+                    vec![Record::new("ns.example", 60, RData::A(Ipv4Addr::LOCALHOST))];
+                socket.send_to(&referral.to_bytes().unwrap(), peer).unwrap();
+            }
+        });
+        let q = query(&["www.example", "+trace"]);
+        let roots = vec![("root.test".to_string(), server)];
+        let mut count = 0;
+        let result = trace(
+            &q,
+            roots,
+            Some(server.port()),
+            Duration::from_secs(2),
+            &mut |_| count += 1,
+        );
+        assert!(result.is_err());
+        assert_eq!(count, 2);
+    }
+}
@@ -1,6 +1,7 @@
 use std::io;
 use thiserror::Error;
 
+#[allow(dead_code)]
 #[derive(Error, Debug)]
 pub enum RcurlError {
     #[error("IO error: {0}")]
@@ -1,5 +1,6 @@
 mod app;
 mod args;
+mod dig;
 mod error;
 mod models;
 
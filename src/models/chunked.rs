@@ -0,0 +1,247 @@
+use super::Headers;
+use std::io::{BufRead, Error, ErrorKind, Read, Result as IoResult};
+
+// 单行(块大小行/尾部字段)允许的最大长度，防止恶意服务器发送无限长的行
+const MAX_LINE_LEN: usize = 8192;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    // 等待读取块大小行
+    Size,
+    // 正在读取块数据，剩余字节数
+    Data(u64),
+    // 块数据之后的 CRLF
+    DataEnd,
+    // 最后一个块之后的尾部字段
+    Trailers,
+    // 解码完成
+    Done,
+}
+
+/// `Transfer-Encoding: chunked` 解码器
+///
+/// 解码器本身不持有底层读取器，每次读取时由调用方传入，
+/// 这样 `Response` 可以继续持有自己的 `BufReader`。
+#[derive(Debug)]
+pub struct ChunkedDecoder {
+    state: State,
+    trailers: Headers,
+    extensions: Vec<(String, Option<String>)>,
+}
+
+impl ChunkedDecoder {
+    pub fn new() -> Self {
+        ChunkedDecoder {
+            state: State::Size,
+            trailers: Headers::new(),
+            extensions: Vec::new(),
+        }
+    }
+
+    /// 是否已经读到最后一个块以及尾部字段
+    pub fn is_done(&self) -> bool {
+        self.state == State::Done
+    }
+
+    /// 最后一个块之后的尾部字段
+    pub fn trailers(&self) -> &Headers {
+        &self.trailers
+    }
+
+    /// 所有块上携带的扩展参数，按出现顺序排列
+    pub fn extensions(&self) -> &[(String, Option<String>)] {
+        &self.extensions
+    }
+
+    /// 从 `reader` 中读取解码后的数据到 `buf`，返回 0 表示消息体结束
+    pub fn read<R: BufRead>(&mut self, reader: &mut R, buf: &mut [u8]) -> IoResult<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        loop {
+            match self.state {
+                State::Size => {
+                    let line = read_line(reader)?;
+                    let size = self.parse_size_line(&line)?;
+                    self.state = if size == 0 {
+                        State::Trailers
+                    } else {
+                        State::Data(size)
+                    };
+                }
+                State::Data(remaining) => {
+                    let to_read = std::cmp::min(buf.len() as u64, remaining) as usize;
+                    let n = reader.read(&mut buf[..to_read])?;
+                    if n == 0 {
+                        return Err(Error::new(
+                            ErrorKind::UnexpectedEof,
+                            format!("分块数据提前结束，还剩{}字节未读取", remaining),
+                        ));
+                    }
+                    let remaining = remaining - n as u64;
+                    self.state = if remaining == 0 {
+                        State::DataEnd
+                    } else {
+                        State::Data(remaining)
+                    };
+                    return Ok(n);
+                }
+                State::DataEnd => {
+                    let line = read_line(reader)?;
+                    if !line.is_empty() {
+                        return Err(invalid_data("分块数据之后缺少CRLF"));
+                    }
+                    self.state = State::Size;
+                }
+                State::Trailers => {
+                    let line = read_line(reader)?;
+                    if line.is_empty() {
+                        self.state = State::Done;
+                        continue;
+                    }
+                    match line.split_once(':') {
+                        Some((key, value)) => {
+                            self.trailers
+                                .append(key.trim().to_string(), value.trim().to_string());
+                        }
+                        None => return Err(invalid_data("无效的尾部字段")),
+                    }
+                }
+                State::Done => return Ok(0),
+            }
+        }
+    }
+
+    // 解析块大小行: chunk-size [ ; ext-name [ = ext-value ] ]*
+    fn parse_size_line(&mut self, line: &str) -> IoResult<u64> {
+        let mut parts = line.split(';');
+        let size = parts.next().unwrap_or_default().trim();
+        if size.is_empty() || size.len() > 16 {
+            return Err(invalid_data(format!("无效的块大小: {:?}", size)));
+        }
+        let size = u64::from_str_radix(size, 16)
+            .map_err(|_| invalid_data(format!("无效的块大小: {:?}", size)))?;
+        for ext in parts {
+            let ext = ext.trim();
+            if ext.is_empty() {
+                continue;
+            }
+            let (name, value) = match ext.split_once('=') {
+                Some((name, value)) => (
+                    name.trim().to_string(),
+                    Some(value.trim().trim_matches('"').to_string()),
+                ),
+                None => (ext.to_string(), None),
+            };
+            self.extensions.push((name, value));
+        }
+        Ok(size)
+    }
+}
+
+impl Default for ChunkedDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// 读取一行并去掉结尾的 CRLF，连接关闭时返回错误
+fn read_line<R: BufRead>(reader: &mut R) -> IoResult<String> {
+    let mut line = Vec::new();
+    let n = reader
+        .by_ref()
+        .take(MAX_LINE_LEN as u64 + 1)
+        .read_until(b'\n', &mut line)?;
+    if n == 0 {
+        return Err(Error::new(ErrorKind::UnexpectedEof, "分块数据提前结束"));
+    }
+    if line.last() != Some(&b'\n') {
+        if line.len() > MAX_LINE_LEN {
+            return Err(invalid_data("分块编码行过长"));
+        }
+        return Err(Error::new(ErrorKind::UnexpectedEof, "分块数据提前结束"));
+    }
+    line.pop();
+    if line.last() == Some(&b'\r') {
+        line.pop();
+    }
+    String::from_utf8(line).map_err(|_| invalid_data("分块编码行不是有效的UTF-8"))
+}
+
+fn invalid_data<E: Into<Box<dyn std::error::Error + Send + Sync>>>(msg: E) -> Error {
+    Error::new(ErrorKind::InvalidData, msg)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::BufReader;
+
+    fn decode(data: &[u8]) -> IoResult<(Vec<u8>, ChunkedDecoder)> {
+        let mut reader = BufReader::new(data);
+        let mut decoder = ChunkedDecoder::new();
+        let mut body = Vec::new();
+        let mut buf = [0u8; 3];
+        loop {
+            let n = decoder.read(&mut reader, &mut buf)?;
+            if n == 0 {
+                break;
+            }
+            body.extend_from_slice(&buf[..n]);
+        }
+        Ok((body, decoder))
+    }
+
+    #[test]
+    fn test_decode_chunks() {
+        let data = b"4\r\nWiki\r\n5\r\npedia\r\nE\r\n in\r\n\r\nchunks.\r\n0\r\n\r\n";
+        let (body, decoder) = decode(data).unwrap();
+        assert_eq!(body, b"Wikipedia in\r\n\r\nchunks.");
+        assert!(decoder.is_done());
+        assert!(decoder.extensions().is_empty());
+    }
+
+    #[test]
+    fn test_extensions_and_trailers() {
+        let data = b"5;name=\"value\";flag\r\nhello\r\n0\r\nExpires: never\r\nX-Sum: 42\r\n\r\n";
+        let (body, decoder) = decode(data).unwrap();
+        assert_eq!(body, b"hello");
+        assert_eq!(
+            decoder.extensions(),
+            &[
+                ("name".to_string(), Some("value".to_string())),
+                ("flag".to_string(), None)
+            ]
+        );
+        assert_eq!(decoder.trailers().get("Expires").unwrap(), "never");
+        assert_eq!(decoder.trailers().get("X-Sum").unwrap(), "42");
+    }
+
+    #[test]
+    fn test_leaves_following_bytes() {
+        let data = b"3\r\nabc\r\n0\r\n\r\nHTTP/1.1 200 OK\r\n";
+        let mut reader = BufReader::new(&data[..]);
+        let mut decoder = ChunkedDecoder::new();
+        let mut body = Vec::new();
+        let mut buf = [0u8; 16];
+        loop {
+            let n = decoder.read(&mut reader, &mut buf).unwrap();
+            if n == 0 {
+                break;
+            }
+            body.extend_from_slice(&buf[..n]);
+        }
+        assert_eq!(body, b"abc");
+        let mut rest = String::new();
+        reader.read_to_string(&mut rest).unwrap();
+        assert_eq!(rest, "HTTP/1.1 200 OK\r\n");
+    }
+
+    #[test]
+    fn test_invalid_input() {
+        assert!(decode(b"zz\r\nabc\r\n0\r\n\r\n").is_err());
+        assert!(decode(b"3\r\nabcd\r\n0\r\n\r\n").is_err());
+        let err = decode(b"a\r\nabc").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+    }
+}
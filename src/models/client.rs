@@ -1,51 +1,183 @@
+use super::Method;
+use super::auth::{AuthScheme, Authenticator, Challenge, Credentials, parse_challenges};
+use super::cookie::CookieJar;
+use super::decoder;
 use super::error::RequestError;
 use super::error::Result;
-use super::headers;
-use super::request;
+use super::headers::HeaderKey;
+use super::netrc::Netrc;
+use super::oauth2::OAuth2;
+use super::pool::{Connection, Pool, PoolKey};
+use super::proxy::{Proxy, ProxyConfig};
 use super::request::Request;
+use super::resolver::{IpVersion, Resolver, SystemResolver};
+use super::retry::RetryPolicy;
+use super::tls::{self, TlsConfig, TlsConnector};
 use super::url::Url;
-use super::{Headers, Method};
-use crate::models::response::Response;
+use crate::models::response::{Redirect, Response};
 use anyhow::anyhow;
-use log::debug;
-use std::cell::RefCell;
-use std::io::{Read, Write};
-use std::net::{TcpStream, ToSocketAddrs};
+use log::{debug, warn};
+use std::borrow::Cow;
+use std::cell::{OnceCell, Ref, RefCell};
+use std::collections::HashMap;
+use std::io;
+use std::io::{BufRead, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::rc::Rc;
+use std::sync::mpsc;
+use std::thread;
 use std::time::Duration;
 
 pub struct Client {
-    stream: Option<TcpStream>,
     timeout: Duration,
     request: Option<RefCell<Request>>,
+    // 空闲的 keep-alive 连接，响应读完后由 Response 放回
+    pool: Rc<RefCell<Pool>>,
+    tls_config: TlsConfig,
+    // TLS 后端在第一次访问 https 地址时才创建，避免无谓地加载系统根证书
+    tls: OnceCell<Box<dyn TlsConnector>>,
+    // 是否自动跟随重定向，以及最多跟随的次数
+    follow_redirects: bool,
+    max_redirects: usize,
+    // 请求失败时的重试策略，默认不重试
+    retry: RetryPolicy,
+    // 是否请求压缩的响应并自动解压
+    compressed: bool,
+    // 开启 cookie 引擎后保存响应中的 cookie，并在之后的请求中发送
+    cookie_jar: Option<RefCell<CookieJar>>,
+    // 域名解析器，默认使用系统解析器
+    resolver: Box<dyn Resolver>,
+    // -4/-6 限制使用的 IP 协议版本
+    ip_version: Option<IpVersion>,
+    // 代理设置，默认直接连接
+    proxy: ProxyConfig,
+    // -u 指定的用户名和密码，没有时使用 URL 中的用户信息
+    credentials: Option<Credentials>,
+    auth_scheme: AuthScheme,
+    // 每个服务器上一次成功应答的质询，之后发给该服务器的请求直接携带认证头(Digest 的 nc 依次递增)
+    authenticators: RefCell<HashMap<PoolKey, Authenticator>>,
+    // -n/--netrc-file 读取的 .netrc
+    netrc: Option<Netrc>,
+    // 设置后使用 OAuth2 访问令牌代替用户名和密码
+    oauth2: Option<OAuth2>,
 }
 
 impl Client {
     /// 创建新客户端
     pub fn new() -> Self {
         Client {
-            stream: None,
             timeout: Duration::new(20, 0), // 默认超时时间为5秒
             request: None,
+            pool: Rc::new(RefCell::new(Pool::new())),
+            tls_config: TlsConfig::default(),
+            tls: OnceCell::new(),
+            follow_redirects: false,
+            max_redirects: 50,
+            retry: RetryPolicy::default(),
+            compressed: false,
+            cookie_jar: None,
+            resolver: Box::new(SystemResolver),
+            ip_version: None,
+            proxy: ProxyConfig::default(),
+            credentials: None,
+            auth_scheme: AuthScheme::default(),
+            authenticators: RefCell::new(HashMap::new()),
+            netrc: None,
+            oauth2: None,
         }
     }
 
-    /// 连接到服务器(带5秒超时)
-    fn connect<A: ToSocketAddrs>(&mut self, addr: A) -> Result<()> {
-        let addr = addr.to_socket_addrs()?.next().unwrap();
-        let stream = TcpStream::connect_timeout(&addr, self.timeout)?;
-        self.stream = Some(stream);
-        Ok(())
+    /// 连接到服务器(带超时)，https 地址会在 TCP 连接上完成 TLS 握手
+    fn connect(&self, url: &Url) -> Result<Connection> {
+        let tls = match url.scheme.to_ascii_lowercase().as_str() {
+            "" | "http" => false,
+            "https" => true,
+            scheme => return Err(anyhow!("不支持的协议: {}", scheme).into()),
+        };
+        // 通过代理建立到目标服务器的连接(CONNECT 隧道或 SOCKS 握手)，之后再进行 TLS 握手
+        let stream = match self.proxy.proxy_for(url) {
+            Some(proxy) => self.connect_proxy(proxy, url)?,
+            None => {
+                let stream = self.connect_tcp(&url.host, url.port_or_default())?;
+                stream.set_read_timeout(Some(self.timeout))?;
+                stream.set_write_timeout(Some(self.timeout))?;
+                stream
+            }
+        };
+        let stream: Box<dyn tls::Stream> = if tls {
+            self.tls_connector()?.connect(&url.host, stream)?
+        } else {
+            Box::new(stream)
+        };
+        Ok(Connection::new(stream, PoolKey::from(url)))
+    }
+
+    // 连接代理并握手。socks5:// 在本地解析目标主机，按 Happy Eyeballs 的顺序交给代理，
+    // 代理报告网络、主机不可达或连接被拒绝时重新连接代理，换下一个地址握手
+    fn connect_proxy(&self, proxy: &Proxy, url: &Url) -> Result<TcpStream> {
+        let hosts = match proxy.resolves_locally() {
+            true => interleave(self.resolve(&url.host, url.port_or_default())?)
+                .iter()
+                .map(|addr| addr.ip().to_string())
+                .collect(),
+            false => vec![url.host.clone()],
+        };
+        let mut last_error = None;
+        for host in hosts {
+            debug!("通过代理 {} 访问 {} ({})", proxy.addr(), url.addr(), host);
+            let mut stream = self.connect_tcp(&proxy.host, proxy.port)?;
+            stream.set_read_timeout(Some(self.timeout))?;
+            stream.set_write_timeout(Some(self.timeout))?;
+            match proxy.handshake(&mut stream, url, &host) {
+                Ok(()) => return Ok(stream),
+                Err(RequestError::Connect(e)) => {
+                    debug!("代理无法连接 {}: {}", host, e);
+                    last_error = Some(e);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        Err(RequestError::Connect(
+            last_error.unwrap_or_else(|| io::Error::other("没有可用的地址")),
+        ))
+    }
+
+    // 解析域名并按 Happy Eyeballs 连接，全部失败时返回最后一个错误
+    fn connect_tcp(&self, host: &str, port: u16) -> Result<TcpStream> {
+        let addrs = self.resolve(host, port)?;
+        happy_eyeballs(interleave(addrs), self.timeout).map_err(RequestError::Connect)
+    }
+
+    // 解析域名，只保留 -4/-6 允许的地址，没有可用的地址时返回错误
+    fn resolve(&self, host: &str, port: u16) -> Result<Vec<SocketAddr>> {
+        let mut addrs = self.resolver.resolve(host, port)?;
+        if let Some(version) = self.ip_version {
+            addrs.retain(|addr| version.matches(addr));
+        }
+        if addrs.is_empty() {
+            return Err(RequestError::Resolve(format!("{}: 没有可用的地址", host)));
+        }
+        Ok(addrs)
+    }
+
+    fn tls_connector(&self) -> Result<&dyn TlsConnector> {
+        if self.tls.get().is_none() {
+            let connector = tls::default_connector(&self.tls_config)?;
+            let _ = self.tls.set(connector);
+        }
+        Ok(self.tls.get().unwrap().as_ref())
+    }
+
+    /// 设置 TLS 配置(CA 证书、是否跳过证书校验)
+    pub fn set_tls_config(&mut self, config: TlsConfig) {
+        self.tls_config = config;
+        self.tls = OnceCell::new();
     }
 
     /// 发送请求
+    #[allow(dead_code)]
     pub fn send_request(&mut self, url: &str, method: Method) -> Result<Response> {
-        let url_ = Url::from(url);
-        self.connect(url_.addr())?;
-        if self.request.is_none() {
-            self.request = Some(RefCell::new(Request::build(url, method)));
-        }
-        let host_value = format!("{}", url_.host);
-        self.request.as_ref().unwrap().borrow_mut();
+        self.request = Some(RefCell::new(Request::build(url, method)?));
         self.execute()
     }
 
@@ -53,64 +185,1332 @@ impl Client {
     pub fn set_timeout(&mut self, timeout: u64) {
         self.timeout = Duration::new(timeout, 0);
     }
+
+    /// 设置是否自动跟随重定向
+    pub fn set_follow_redirects(&mut self, follow: bool) {
+        self.follow_redirects = follow;
+    }
+
+    /// 设置最多跟随的重定向次数
+    pub fn set_max_redirects(&mut self, max: usize) {
+        self.max_redirects = max;
+    }
+
+    /// 设置请求失败时的重试策略
+    pub fn set_retry_policy(&mut self, policy: RetryPolicy) {
+        self.retry = policy;
+    }
+
+    /// 设置是否发送 Accept-Encoding 并按 Content-Encoding 解压响应，需要在创建请求之前调用
+    pub fn set_compressed(&mut self, compressed: bool) {
+        self.compressed = compressed;
+    }
+
+    /// 开启 cookie 引擎，使用 jar 中已有的 cookie
+    pub fn set_cookie_jar(&mut self, jar: CookieJar) {
+        self.cookie_jar = Some(RefCell::new(jar));
+    }
+
+    /// 当前的 cookie 存储，未开启 cookie 引擎时返回 None
+    pub fn cookie_jar(&self) -> Option<Ref<'_, CookieJar>> {
+        self.cookie_jar.as_ref().map(RefCell::borrow)
+    }
+
+    /// 设置代理
+    pub fn set_proxy(&mut self, proxy: ProxyConfig) {
+        self.proxy = proxy;
+    }
+
+    /// 设置认证使用的用户名和密码
+    pub fn set_credentials(&mut self, credentials: Option<Credentials>) {
+        self.credentials = credentials;
+    }
+
+    /// 设置认证方式
+    pub fn set_auth_scheme(&mut self, scheme: AuthScheme) {
+        self.auth_scheme = scheme;
+    }
+
+    /// 设置 .netrc，没有其他认证信息时按主机查找用户名和密码
+    pub fn set_netrc(&mut self, netrc: Option<Netrc>) {
+        self.netrc = netrc;
+    }
+
+    /// 使用 OAuth2 获取的访问令牌进行认证
+    pub fn set_oauth2(&mut self, oauth2: Option<OAuth2>) {
+        self.oauth2 = oauth2;
+    }
+
+    /// 设置域名解析器
+    pub fn set_resolver(&mut self, resolver: Box<dyn Resolver>) {
+        self.resolver = resolver;
+    }
+
+    /// 只使用 IPv4 或 IPv6 地址，None 表示都可以
+    pub fn set_ip_version(&mut self, version: Option<IpVersion>) {
+        self.ip_version = version;
+    }
+
+    /// 设置空闲连接的最长保留时间
+    #[allow(dead_code)]
+    pub fn set_pool_idle_timeout(&mut self, timeout: Duration) {
+        self.pool.borrow_mut().set_idle_timeout(timeout);
+    }
+
     /// get请求
-    pub fn get(&mut self, url: &str) -> &RefCell<Request> {
-        let url = Url::from(url);
-        if self.request.is_none() {
-            self.request = Some(RefCell::new(Request::build(
-                &url.host.as_str(),
-                Method::GET,
-            )));
-        }
-        let host_value = format!("{}", url.host);
-        debug!("Host: {}", host_value);
-        let mut header = Headers::default();
-        header.set("Host".to_string(), host_value);
-        self.request
-            .as_mut()
-            .unwrap()
-            .borrow_mut()
-            .set_headers(header);
-        self.request.as_mut().unwrap()
+    #[allow(dead_code)]
+    pub fn get(&mut self, url: &str) -> Result<&RefCell<Request>> {
+        self.request(url, Method::GET)
+    }
+
+    /// 使用指定的请求方式创建请求
+    pub fn request(&mut self, url: &str, method: Method) -> Result<&RefCell<Request>> {
+        let mut request = Request::build(url, method)?;
+        if self.compressed
+            && let Some(accept) = decoder::accept_encoding()
+        {
+            request.set("Accept-Encoding".to_string(), accept);
+        }
+        debug!("Host: {:?}", request.headers.get("Host"));
+        Ok(self.request.insert(RefCell::new(request)))
     }
 
-    /// 执行请求
+    /// 执行请求，返回的响应已经读取了完整的消息体
     pub fn execute(&mut self) -> Result<Response> {
+        self.execute_with(true)
+    }
+
+    /// 执行请求，但不读取消息体，调用方可以通过 Read 或 copy_to 流式读取
+    pub fn execute_stream(&mut self) -> Result<Response> {
+        self.execute_with(false)
+    }
+
+    fn execute_with(&mut self, load_body: bool) -> Result<Response> {
         if let Some(request) = self.request.take() {
-            let addr = request.borrow().addr();
-            self.connect(addr)?;
-            if let Some(stream) = self.stream.as_mut() {
-                let request_bytes = request.borrow().to_bytes();
-                debug!("Request:\n{}", String::from_utf8_lossy(&request_bytes));
-                match stream.write_all(&request_bytes) {
-                    Ok(_) => (),
-                    Err(e) => {
-                        return Err(RequestError::SendRquestError(format!("{e}")));
+            let result = self.execute_retrying(&request.borrow(), load_body);
+            self.request = Some(request);
+            result
+        } else {
+            Err(anyhow!("No request to execute").into())
+        }
+    }
+
+    // 按重试策略执行请求: 连接失败、超时或返回指定的状态码时等待一段时间后重新发送
+    fn execute_retrying(&self, request: &Request, load_body: bool) -> Result<Response> {
+        let retryable = self.retry.allows_method(&request.method);
+        let mut attempt = 0;
+        loop {
+            let result = self.execute_request(request, load_body);
+            if attempt >= self.retry.max_retries {
+                return result;
+            }
+            let (reason, retry_after) = match &result {
+                Ok(response) if self.retry.retries_status(response.status) => (
+                    format!("状态码 {}", response.status),
+                    response.retry_after(),
+                ),
+                Err(e) if self.retry.retries_error(e) => (e.to_string(), None),
+                _ => return result,
+            };
+            if !retryable {
+                debug!("{} 请求不是幂等的，不进行重试", request.method);
+                return result;
+            }
+            attempt += 1;
+            let delay = self.retry.delay(attempt, retry_after);
+            warn!(
+                "请求失败({})，{:.1}秒后进行第{}/{}次重试",
+                reason,
+                delay.as_secs_f64(),
+                attempt,
+                self.retry.max_retries
+            );
+            // 先释放连接再等待
+            drop(result);
+            thread::sleep(delay);
+        }
+    }
+
+    // 发送请求，开启重定向时依次请求 Location 指向的地址
+    fn execute_request(&self, request: &Request, load_body: bool) -> Result<Response> {
+        let mut redirects = Vec::new();
+        let mut next: Option<Request> = None;
+        loop {
+            let current = next.as_ref().unwrap_or(request);
+            let mut response = self.send_authenticated(current, request.url())?;
+            let location = match response.location() {
+                Some(location) if self.follow_redirects => location.to_string(),
+                _ => {
+                    if load_body {
+                        response.get_body()?;
                     }
-                };
-                self.request = Some(request);
-                let mut response = Response::from_bytes(stream)?;
-                response.get_body()?;
-                Ok(response)
-            } else {
-                Err(anyhow!("Not connected to server").into())
+                    response.redirects = redirects;
+                    return Ok(response);
+                }
+            };
+            // 读完重定向响应的消息体，以便连接可以复用
+            response.get_body()?;
+            if redirects.len() >= self.max_redirects {
+                return Err(RequestError::TooManyRedirects(self.max_redirects));
+            }
+            let redirected = current.redirect(response.status, &location)?;
+            debug!(
+                "重定向 {}: {} -> {}",
+                response.status,
+                current.url(),
+                redirected.url()
+            );
+            redirects.push(Redirect {
+                status: response.status,
+                url: current.url().to_string(),
+                location: redirected.url().to_string(),
+            });
+            next = Some(redirected);
+        }
+    }
+
+    // 发送请求并处理认证: 设置了 OAuth2 时携带访问令牌(只发给与 origin 同源的地址)；
+    // Basic 直接携带认证头，其他方式在收到 401 质询后计算应答重新发送一次。
+    // 请求中已经有 Authorization 头时不做处理
+    fn send_authenticated(&self, request: &Request, origin: &Url) -> Result<Response> {
+        let key = HeaderKey::Authorization.as_str();
+        if let Some(oauth2) = self.oauth2.as_ref()
+            && request.url().same_origin(origin)
+            && !request.headers.contains(key)
+        {
+            return self.send_bearer(request, oauth2);
+        }
+        let credentials = self
+            .credentials_for(request.url(), origin)
+            .filter(|_| !request.headers.contains(key));
+        let Some(credentials) = credentials else {
+            return self.send(request);
+        };
+        let uri = request.url().get_path();
+        let server = PoolKey::from(request.url());
+        let mut request = Cow::Borrowed(request);
+        let authorization = match self.authenticators.borrow_mut().get_mut(&server) {
+            Some(authenticator) => Some((
+                matches!(authenticator, Authenticator::Basic),
+                authenticator.authorization(&credentials, &request.method, &uri)?,
+            )),
+            None if self.auth_scheme == AuthScheme::Basic => Some((true, credentials.basic())),
+            None => None,
+        };
+        let mut sent_basic = false;
+        if let Some((basic, authorization)) = authorization {
+            sent_basic = basic;
+            request.to_mut().set(key.to_string(), authorization);
+        }
+        let mut response = self.send(&request)?;
+        if response.status != 401 {
+            return Ok(response);
+        }
+        let challenges: Vec<Challenge> = response
+            .headers
+            .get_all("WWW-Authenticate")
+            .flat_map(|value| parse_challenges(value))
+            .collect();
+        let Some(mut authenticator) = Authenticator::select(&challenges, self.auth_scheme) else {
+            return Ok(response);
+        };
+        // 已经发送过 Basic 认证时重试也不会成功
+        if sent_basic && matches!(authenticator, Authenticator::Basic) {
+            return Ok(response);
+        }
+        let authorization = authenticator.authorization(&credentials, &request.method, &uri)?;
+        debug!("服务器要求认证，使用 {:?} 重新发送请求", challenges);
+        // 读完 401 响应的消息体，以便连接可以复用
+        response.get_body()?;
+        request.to_mut().set(key.to_string(), authorization);
+        let response = self.send(&request)?;
+        if response.status != 401 {
+            self.authenticators
+                .borrow_mut()
+                .insert(server, authenticator);
+        }
+        Ok(response)
+    }
+
+    // 携带 OAuth2 访问令牌发送请求，服务器返回 401 时重新获取令牌再发送一次
+    fn send_bearer(&self, request: &Request, oauth2: &OAuth2) -> Result<Response> {
+        let key = HeaderKey::Authorization.as_str().to_string();
+        let mut request = request.clone();
+        request.set(key.clone(), oauth2.token()?.authorization()?);
+        let mut response = self.send(&request)?;
+        if response.status != 401 {
+            return Ok(response);
+        }
+        debug!("服务器拒绝了访问令牌，重新获取后再次发送");
+        response.get_body()?;
+        request.set(key, oauth2.refresh()?.authorization()?);
+        self.send(&request)
+    }
+
+    // 请求使用的认证信息: -u 和 URL 中的用户信息只发给与 origin 同源的地址；
+    // .netrc 按主机匹配，重定向到其他主机时使用该主机的条目，URL 中只有用户名时查找该用户的密码
+    fn credentials_for(&self, url: &Url, origin: &Url) -> Option<Credentials> {
+        let netrc = |login: Option<&str>| self.netrc.as_ref()?.find(&url.host, login);
+        if !url.same_origin(origin) {
+            return netrc(None);
+        }
+        if let Some(credentials) = &self.credentials {
+            return Some(credentials.clone());
+        }
+        match (&origin.username, &origin.password) {
+            (Some(username), None) => {
+                netrc(Some(username)).or_else(|| Credentials::from_url(origin))
+            }
+            (Some(_), Some(_)) => Credentials::from_url(origin),
+            (None, _) => netrc(None),
+        }
+    }
+
+    // 优先使用连接池中的空闲连接发送请求，复用的连接失败时重新建立连接再试一次
+    fn send(&self, request: &Request) -> Result<Response> {
+        let mut request = self.with_cookies(request);
+        // 明文 HTTP 请求直接发给代理，https 请求通过隧道发送，与直接连接相同
+        let proxy = self.forward_proxy(request.url());
+        if let Some(authorization) = proxy.and_then(Proxy::authorization) {
+            request
+                .to_mut()
+                .set("Proxy-Authorization".to_string(), authorization);
+        }
+        let request_bytes = match proxy {
+            Some(_) => request.to_proxy_bytes(),
+            None => request.to_bytes(),
+        };
+        debug!("Request:\n{}", String::from_utf8_lossy(&request_bytes));
+        let key = PoolKey::from(request.url());
+        let pooled = self.pool.borrow_mut().checkout(&key);
+        if let Some(conn) = pooled
+            && let Some(response) = self.send_reused(conn, &request, &request_bytes)?
+        {
+            return Ok(response);
+        }
+        let conn = self.connect(request.url())?;
+        self.send_on(conn, &request, &request_bytes)
+    }
+
+    // 在复用的连接上发送请求。服务器可能已经关闭了空闲连接: 只有写入失败，
+    // 或者在收到任何响应数据之前连接就被关闭时，请求肯定没有被处理，返回 None 由调用方重新建立连接；
+    // 其他错误(例如读取超时)直接返回，避免请求被重复发送
+    fn send_reused(
+        &self,
+        mut conn: Connection,
+        request: &Request,
+        request_bytes: &[u8],
+    ) -> Result<Option<Response>> {
+        if let Err(e) = conn.write_all(request_bytes) {
+            debug!("复用连接写入失败，重新建立连接: {e}");
+            return Ok(None);
+        }
+        match conn.fill_buf() {
+            Ok([]) => {
+                debug!("复用连接已经被服务器关闭，重新建立连接");
+                return Ok(None);
+            }
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::ConnectionReset | io::ErrorKind::ConnectionAborted
+                ) =>
+            {
+                debug!("复用连接被重置，重新建立连接: {e}");
+                return Ok(None);
+            }
+            Err(e) => return Err(e.into()),
+            Ok(_) => {}
+        }
+        self.receive(conn, request).map(Some)
+    }
+
+    // 转发明文 HTTP 请求的代理
+    fn forward_proxy(&self, url: &Url) -> Option<&Proxy> {
+        self.proxy
+            .proxy_for(url)
+            .filter(|proxy| proxy.forwards(url))
+    }
+
+    // 在请求的 Cookie 头后追加 cookie 存储中与地址匹配的 cookie
+    fn with_cookies<'a>(&self, request: &'a Request) -> Cow<'a, Request> {
+        let mut request = Cow::Borrowed(request);
+        let Some(jar) = self.cookie_jar.as_ref() else {
+            return request;
+        };
+        if let Some(cookies) = jar.borrow().cookie_header(request.url()) {
+            let value = match request.headers.get("Cookie") {
+                Some(existing) => format!("{}; {}", existing, cookies),
+                None => cookies,
+            };
+            request.to_mut().set("Cookie".to_string(), value);
+        }
+        request
+    }
+
+    fn send_on(
+        &self,
+        mut conn: Connection,
+        request: &Request,
+        request_bytes: &[u8],
+    ) -> Result<Response> {
+        if let Err(e) = conn.write_all(request_bytes) {
+            return Err(RequestError::SendRquestError(format!("{e}")));
+        }
+        self.receive(conn, request)
+    }
+
+    // 读取响应头，处理连接复用、解压和 cookie
+    fn receive(&self, conn: Connection, request: &Request) -> Result<Response> {
+        let mut response = Response::from_bytes(conn, &request.method)?;
+        if request.keep_alive() {
+            response.set_pool(Rc::clone(&self.pool));
+        }
+        if self.compressed {
+            response.decode_content()?;
+        }
+        if let Some(jar) = self.cookie_jar.as_ref() {
+            let mut jar = jar.borrow_mut();
+            for set_cookie in response.set_cookies() {
+                jar.store(request.url(), set_cookie);
+            }
+        }
+        Ok(response)
+    }
+}
+
+impl Default for Client {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// RFC 8305 建议的两次连接尝试之间的间隔
+const CONNECTION_ATTEMPT_DELAY: Duration = Duration::from_millis(250);
+
+// 按 RFC 8305 第 4 节交替排列 IPv6 和 IPv4 地址，第一个地址的协议版本优先
+fn interleave(addrs: Vec<SocketAddr>) -> Vec<SocketAddr> {
+    let Some(first) = addrs.first() else {
+        return addrs;
+    };
+    let first_v6 = first.is_ipv6();
+    let (mut preferred, mut other): (Vec<_>, Vec<_>) =
+        addrs.into_iter().partition(|a| a.is_ipv6() == first_v6);
+    let mut result = Vec::with_capacity(preferred.len() + other.len());
+    preferred.reverse();
+    other.reverse();
+    loop {
+        match (preferred.pop(), other.pop()) {
+            (None, None) => return result,
+            (a, b) => result.extend(a.into_iter().chain(b)),
+        }
+    }
+}
+
+// Happy Eyeballs(RFC 8305): 按顺序发起连接，前一个连接在
+// CONNECTION_ATTEMPT_DELAY 内没有结果时不再等待，直接并行尝试下一个地址，
+// 使用最先建立的连接；某个地址连接失败时立即尝试下一个
+fn happy_eyeballs(addrs: Vec<SocketAddr>, timeout: Duration) -> io::Result<TcpStream> {
+    let (tx, rx) = mpsc::channel();
+    let mut addrs = addrs.into_iter().peekable();
+    let mut pending = 0;
+    let mut last_error = None;
+    loop {
+        if let Some(addr) = addrs.next() {
+            let tx = tx.clone();
+            // 没有被使用的连接在发送失败时随线程一起关闭
+            thread::spawn(move || {
+                let _ = tx.send((addr, TcpStream::connect_timeout(&addr, timeout)));
+            });
+            pending += 1;
+        }
+        if pending == 0 {
+            break;
+        }
+        let result = if addrs.peek().is_some() {
+            match rx.recv_timeout(CONNECTION_ATTEMPT_DELAY) {
+                Ok(result) => result,
+                Err(_) => continue,
             }
         } else {
-            Err(anyhow!("No request to execute").into())
+            match rx.recv() {
+                Ok(result) => result,
+                Err(_) => break,
+            }
+        };
+        pending -= 1;
+        match result {
+            (addr, Ok(stream)) => {
+                debug!("建立新连接: {}", addr);
+                return Ok(stream);
+            }
+            (addr, Err(e)) => {
+                debug!("连接 {} 失败: {}", addr, e);
+                last_error = Some(e);
+            }
         }
     }
+    Err(last_error.unwrap_or_else(|| io::Error::other("没有可用的地址")))
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::models::test_server::{
+        self, read_request, response, serve, serve_proxy, serve_socks,
+    };
+    use std::io::BufReader;
+    use std::net::TcpListener;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
 
     #[test]
+    #[ignore = "需要访问外网"]
     fn test_client_request_response() -> Result<()> {
         let mut client = Client::new();
-        client.get("http://www.baidu.com/hello");
+        client.get("http://www.baidu.com/hello")?;
         let response = client.execute()?;
         println!("{}", String::from_utf8_lossy(&response.body));
         Ok(())
     }
+
+    #[test]
+    fn test_reuse_connection() -> Result<()> {
+        let server = serve(|_| response("200 OK", &[], b"ok"));
+        let mut client = Client::new();
+        for _ in 0..3 {
+            client.get(&format!("{}/ping", server.url))?;
+            let response = client.execute()?;
+            assert_eq!(response.body, b"ok");
+        }
+        assert_eq!(server.accepted(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_connection_close() -> Result<()> {
+        let server = serve(|_| response("200 OK", &[("Connection", "close")], b"ok"));
+        let mut client = Client::new();
+        for _ in 0..2 {
+            client.get(&server.url)?;
+            client.execute()?;
+        }
+        assert_eq!(server.accepted(), 2);
+
+        let server = serve(|_| response("200 OK", &[], b"ok"));
+        for _ in 0..2 {
+            client
+                .get(&server.url)?
+                .borrow_mut()
+                .set("Connection".to_string(), "close".to_string());
+            client.execute()?;
+        }
+        assert_eq!(server.accepted(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_stale_connection_reconnects() -> Result<()> {
+        // 服务器每个连接只处理一个请求，之后不声明就关闭连接
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                if read_request(&mut reader).is_some() {
+                    stream.write_all(&response("200 OK", &[], b"ok")).unwrap();
+                }
+            }
+        });
+        let mut client = Client::new();
+        for _ in 0..3 {
+            client.get(&url)?;
+            assert_eq!(client.execute()?.body, b"ok");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_reused_connection_timeout_not_resent() -> Result<()> {
+        // 第二个请求写入复用的连接后服务器不再响应
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&requests);
+        let server = serve(move |_| {
+            if counter.fetch_add(1, Ordering::SeqCst) > 0 {
+                thread::sleep(Duration::from_secs(3));
+            }
+            response("200 OK", &[], b"ok")
+        });
+        let mut client = Client::new();
+        client.set_timeout(1);
+        client.request(&server.url, Method::POST)?;
+        assert_eq!(client.execute()?.body, b"ok");
+        assert!(client.execute().is_err());
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+        assert_eq!(server.accepted(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_follow_redirects() -> Result<()> {
+        let server = serve(|req| match req.target.as_str() {
+            "/a" => response("302 Found", &[("Location", "/b?x=1")], b""),
+            "/b?x=1" => response("301 Moved Permanently", &[("Location", "c")], b""),
+            "/c" => response("200 OK", &[], b"done"),
+            _ => response("404 Not Found", &[], b""),
+        });
+        let mut client = Client::new();
+        client.get(&format!("{}/a", server.url))?;
+        let response = client.execute()?;
+        assert_eq!(response.status, 302);
+        assert!(response.redirects.is_empty());
+
+        client.set_follow_redirects(true);
+        client.get(&format!("{}/a", server.url))?;
+        let response = client.execute()?;
+        assert_eq!(response.status, 200);
+        assert_eq!(response.body, b"done");
+        let chain: Vec<_> = response
+            .redirects
+            .iter()
+            .map(|r| (r.status, r.location.clone()))
+            .collect();
+        assert_eq!(
+            chain,
+            vec![
+                (302, format!("{}/b?x=1", server.url)),
+                (301, format!("{}/c", server.url)),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_redirect_method_rewrite() -> Result<()> {
+        let server = serve(|req| match req.target.as_str() {
+            "/see-other" => response("303 See Other", &[("Location", "/echo")], b""),
+            "/temporary" => response("307 Temporary Redirect", &[("Location", "/echo")], b""),
+            _ => {
+                let body = format!("{} {}", req.method, String::from_utf8_lossy(&req.body));
+                response("200 OK", &[], body.as_bytes())
+            }
+        });
+        let mut client = Client::new();
+        client.set_follow_redirects(true);
+        for (path, expected) in [("/see-other", "GET "), ("/temporary", "POST a=1")] {
+            client
+                .request(&format!("{}{}", server.url, path), Method::POST)?
+                .borrow_mut()
+                .set_body(b"a=1");
+            assert_eq!(client.execute()?.body, expected.as_bytes());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_redirect_strips_authorization_across_hosts() -> Result<()> {
+        let echo = |req: &test_server::TestRequest| {
+            let auth = req.header("Authorization").unwrap_or("none").to_string();
+            response("200 OK", &[], auth.as_bytes())
+        };
+        let other = serve(echo);
+        let target = format!("{}/", other.url);
+        let origin = serve(move |req| match req.target.as_str() {
+            "/local" => response("302 Found", &[("Location", "/echo")], b""),
+            "/remote" => response("302 Found", &[("Location", target.as_str())], b""),
+            _ => echo(req),
+        });
+        let mut client = Client::new();
+        client.set_follow_redirects(true);
+        for (path, expected) in [("/local", "Basic dXNlcjpwYXNz"), ("/remote", "none")] {
+            client
+                .get(&format!("{}{}", origin.url, path))?
+                .borrow_mut()
+                .set(
+                    "Authorization".to_string(),
+                    "Basic dXNlcjpwYXNz".to_string(),
+                );
+            assert_eq!(client.execute()?.body, expected.as_bytes());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_basic_auth() -> Result<()> {
+        let server = serve(|req| {
+            let auth = req.header("Authorization").unwrap_or("none");
+            response("200 OK", &[], auth.as_bytes())
+        });
+        let mut client = Client::new();
+        client.set_credentials(Some(Credentials::parse("user:pass")));
+        client.get(&format!("{}/", server.url))?;
+        assert_eq!(client.execute()?.body, b"Basic dXNlcjpwYXNz");
+        // 没有 -u 时使用 URL 中的用户信息
+        let mut client = Client::new();
+        let url = server.url.replace("http://", "http://user:p%40ss@");
+        client.get(&format!("{}/", url))?;
+        assert_eq!(client.execute()?.body, b"Basic dXNlcjpwQHNz");
+        Ok(())
+    }
+
+    #[test]
+    fn test_netrc() -> Result<()> {
+        let echo = |req: &test_server::TestRequest| {
+            let auth = req.header("Authorization").unwrap_or("none").to_string();
+            response("200 OK", &[], auth.as_bytes())
+        };
+        let other = serve(echo);
+        let target = other.url.replace("127.0.0.1", "localhost");
+        let origin = serve(move |req| match req.target.as_str() {
+            "/remote" => response("302 Found", &[("Location", target.as_str())], b""),
+            _ => echo(req),
+        });
+        let mut client = Client::new();
+        client.set_follow_redirects(true);
+        client.set_netrc(Some(Netrc::parse(
+            "machine 127.0.0.1 login alice password one\n\
+             machine 127.0.0.1 login bob password two",
+        )));
+        let bob = origin.url.replace("http://", "http://bob@");
+        // 只发给 .netrc 中匹配的主机，URL 中只有用户名时查找该用户的密码
+        for (url, expected) in [
+            (format!("{}/", origin.url), "Basic YWxpY2U6b25l"),
+            (format!("{}/remote", origin.url), "none"),
+            (format!("{}/", bob), "Basic Ym9iOnR3bw=="),
+        ] {
+            client.get(&url)?;
+            assert_eq!(client.execute()?.body, expected.as_bytes());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_oauth2_bearer() -> Result<()> {
+        use crate::models::oauth2::OAuth2Config;
+        let issued = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&issued);
+        let token_server = serve(move |_| {
+            let n = counter.fetch_add(1, Ordering::SeqCst) + 1;
+            let body = format!(r#"{{"access_token":"t{}","token_type":"Bearer"}}"#, n);
+            response("200 OK", &[], body.as_bytes())
+        });
+        let echo = |req: &test_server::TestRequest| {
+            let auth = req.header("Authorization").unwrap_or("none").to_string();
+            response("200 OK", &[], auth.as_bytes())
+        };
+        let other = serve(echo);
+        let target = format!("{}/", other.url);
+        // 第一个令牌被拒绝，之后的令牌有效
+        let api = serve(
+            move |req| match (req.target.as_str(), req.header("Authorization")) {
+                (_, Some("Bearer t1")) => response("401 Unauthorized", &[], b""),
+                ("/remote", _) => response("302 Found", &[("Location", target.as_str())], b""),
+                _ => echo(req),
+            },
+        );
+        let mut client = Client::new();
+        client.set_follow_redirects(true);
+        client.set_oauth2(Some(OAuth2::new(OAuth2Config {
+            token_url: format!("{}/token", token_server.url),
+            client_id: "id".to_string(),
+            ..OAuth2Config::default()
+        })));
+        client.get(&format!("{}/", api.url))?;
+        assert_eq!(client.execute()?.body, b"Bearer t2");
+        assert_eq!(issued.load(Ordering::SeqCst), 2);
+        // 令牌在之后的请求中复用，不会发给其他地址
+        client.get(&format!("{}/remote", api.url))?;
+        assert_eq!(client.execute()?.body, b"none");
+        assert_eq!(issued.load(Ordering::SeqCst), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_digest_auth() -> Result<()> {
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&requests);
+        let server = serve(move |req| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            match req.header("Authorization") {
+                Some(auth) if auth.starts_with("Digest ") => {
+                    response("200 OK", &[], auth.as_bytes())
+                }
+                _ => response(
+                    "401 Unauthorized",
+                    &[
+                        ("WWW-Authenticate", "Basic realm=\"test\""),
+                        (
+                            "WWW-Authenticate",
+                            "Digest realm=\"test\", qop=\"auth\", nonce=\"abc\", algorithm=MD5, \
+                             Digest realm=\"test\", qop=\"auth\", nonce=\"abc\", algorithm=SHA-256",
+                        ),
+                    ],
+                    b"unauthorized",
+                ),
+            }
+        });
+        for scheme in [AuthScheme::Digest, AuthScheme::Any] {
+            requests.store(0, Ordering::SeqCst);
+            let mut client = Client::new();
+            client.set_credentials(Some(Credentials::parse("user:pass")));
+            client.set_auth_scheme(scheme);
+            client.get(&format!("{}/private", server.url))?;
+            let body = String::from_utf8_lossy(&client.execute()?.body).into_owned();
+            assert!(body.contains("username=\"user\""));
+            assert!(body.contains("uri=\"/private\""));
+            assert!(body.contains("algorithm=SHA-256"));
+            assert!(body.contains("nc=00000001"));
+            assert_eq!(requests.load(Ordering::SeqCst), 2);
+            // 之后的请求直接携带认证头，nc 递增
+            let body = String::from_utf8_lossy(&client.execute()?.body).into_owned();
+            assert!(body.contains("nc=00000002"));
+            assert_eq!(requests.load(Ordering::SeqCst), 3);
+        }
+        // 只有 Basic 认证时不会重试
+        let mut client = Client::new();
+        client.set_credentials(Some(Credentials::parse("user:pass")));
+        client.get(&format!("{}/private", server.url))?;
+        requests.store(0, Ordering::SeqCst);
+        assert_eq!(client.execute()?.status, 401);
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_digest_auth_per_server() -> Result<()> {
+        let echo = |req: &test_server::TestRequest| {
+            let auth = req.header("Authorization").unwrap_or("none").to_string();
+            response("200 OK", &[], auth.as_bytes())
+        };
+        let other = serve(echo);
+        let target = other.url.replace("127.0.0.1", "localhost");
+        let server = serve(
+            move |req| match (req.target.as_str(), req.header("Authorization")) {
+                ("/remote", _) => response("302 Found", &[("Location", target.as_str())], b""),
+                (_, Some(auth)) if auth.starts_with("Digest ") => echo(req),
+                _ => response(
+                    "401 Unauthorized",
+                    &[("WWW-Authenticate", "Digest realm=\"test\", nonce=\"abc\"")],
+                    b"",
+                ),
+            },
+        );
+        let mut client = Client::new();
+        client.set_follow_redirects(true);
+        client.set_auth_scheme(AuthScheme::Digest);
+        client.set_netrc(Some(Netrc::parse(
+            "machine 127.0.0.1 login alice password one\n\
+             machine localhost login alice password one",
+        )));
+        client.get(&format!("{}/private", server.url))?;
+        assert!(client.execute()?.body.starts_with(b"Digest "));
+        // 另一个服务器没有发出过质询，不会提前携带为原服务器计算的认证头
+        client.get(&format!("{}/remote", server.url))?;
+        assert_eq!(client.execute()?.body, b"none");
+        Ok(())
+    }
+
+    #[test]
+    fn test_max_redirects() {
+        let server = serve(|_| response("302 Found", &[("Location", "/loop")], b""));
+        let mut client = Client::new();
+        client.set_follow_redirects(true);
+        client.set_max_redirects(3);
+        client.get(&server.url).unwrap();
+        assert!(matches!(
+            client.execute(),
+            Err(RequestError::TooManyRedirects(3))
+        ));
+    }
+
+    // 前 failures 个请求返回 503，之后返回 200
+    fn flaky_server(failures: usize) -> (test_server::TestServer, Arc<AtomicUsize>) {
+        let count = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&count);
+        let server = serve(move |_| {
+            if counter.fetch_add(1, Ordering::SeqCst) < failures {
+                response("503 Service Unavailable", &[("Retry-After", "0")], b"")
+            } else {
+                response("200 OK", &[], b"ok")
+            }
+        });
+        (server, count)
+    }
+
+    fn retry_policy(max_retries: u32) -> RetryPolicy {
+        RetryPolicy {
+            max_retries,
+            interval: Duration::ZERO,
+            ..RetryPolicy::default()
+        }
+    }
+
+    #[test]
+    fn test_retry_status() -> Result<()> {
+        let (server, count) = flaky_server(2);
+        let mut client = Client::new();
+        client.get(&server.url)?;
+        assert_eq!(client.execute()?.status, 503);
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+
+        let (server, count) = flaky_server(2);
+        client.set_retry_policy(retry_policy(3));
+        client.get(&server.url)?;
+        assert_eq!(client.execute()?.body, b"ok");
+        assert_eq!(count.load(Ordering::SeqCst), 3);
+
+        let (server, count) = flaky_server(5);
+        client.get(&server.url)?;
+        assert_eq!(client.execute()?.status, 503);
+        assert_eq!(count.load(Ordering::SeqCst), 4);
+        Ok(())
+    }
+
+    #[test]
+    fn test_retry_non_idempotent() -> Result<()> {
+        let mut client = Client::new();
+        client.set_retry_policy(retry_policy(3));
+        let (server, count) = flaky_server(1);
+        client.request(&server.url, Method::POST)?;
+        assert_eq!(client.execute()?.status, 503);
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+
+        client.set_retry_policy(RetryPolicy {
+            retry_all: true,
+            ..retry_policy(3)
+        });
+        let (server, count) = flaky_server(1);
+        client.request(&server.url, Method::POST)?;
+        assert_eq!(client.execute()?.status, 200);
+        assert_eq!(count.load(Ordering::SeqCst), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_retry_connect_error() {
+        // 绑定后立即关闭，得到一个没有监听的端口
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let mut client = Client::new();
+        client.set_retry_policy(retry_policy(2));
+        client.get(&format!("http://127.0.0.1:{}/", port)).unwrap();
+        assert!(matches!(client.execute(), Err(RequestError::Connect(_))));
+    }
+
+    // 把所有域名解析为固定的地址列表
+    struct StaticResolver(Vec<std::net::SocketAddr>);
+
+    impl Resolver for StaticResolver {
+        fn resolve(&self, _host: &str, _port: u16) -> Result<Vec<std::net::SocketAddr>> {
+            Ok(self.0.clone())
+        }
+    }
+
+    #[test]
+    fn test_try_all_addresses() -> Result<()> {
+        let closed = TcpListener::bind("127.0.0.1:0")?.local_addr()?;
+        let server = serve(|req| {
+            let host = req.header("Host").unwrap_or_default();
+            response("200 OK", &[], host.as_bytes())
+        });
+        let addr = server.url.trim_start_matches("http://").parse().unwrap();
+        let mut client = Client::new();
+        client.set_resolver(Box::new(StaticResolver(vec![closed, addr])));
+        client.get("http://rcurl.test/")?;
+        assert_eq!(client.execute()?.body, b"rcurl.test");
+
+        // 连接池中没有连接时才会重新解析
+        let mut client = Client::new();
+        client.get("http://rcurl.test/")?;
+        client.set_resolver(Box::new(StaticResolver(vec![closed])));
+        assert!(matches!(client.execute(), Err(RequestError::Connect(_))));
+        client.set_resolver(Box::new(StaticResolver(vec![])));
+        assert!(matches!(client.execute(), Err(RequestError::Resolve(_))));
+        Ok(())
+    }
+
+    #[test]
+    fn test_resolve_and_connect_to() -> Result<()> {
+        use crate::models::resolver::{OverrideResolver, parse_connect_to, parse_resolve};
+        let server = serve(|req| match req.target.as_str() {
+            "/old" => response(
+                "302 Found",
+                &[("Location", "http://api.rcurl.test/new")],
+                b"",
+            ),
+            _ => {
+                let host = req.header("Host").unwrap_or_default();
+                response("200 OK", &[], host.as_bytes())
+            }
+        });
+        let port = server.url.rsplit(':').next().unwrap();
+        let resolve = parse_resolve(&format!("www.rcurl.test:{}:127.0.0.1", port)).unwrap();
+        let connect_to =
+            parse_connect_to(&format!("api.rcurl.test:80:www.rcurl.test:{}", port)).unwrap();
+        let mut client = Client::new();
+        client.set_follow_redirects(true);
+        client.set_resolver(Box::new(OverrideResolver::new(
+            Box::new(StaticResolver(vec![])),
+            vec![resolve],
+            vec![connect_to],
+        )));
+        // 重定向后的地址同样被改写，改写后的目标再按 --resolve 解析，Host 头保持原来的主机名
+        client.get(&format!("http://www.rcurl.test:{}/old", port))?;
+        let response = client.execute()?;
+        assert_eq!(response.body, b"api.rcurl.test");
+        assert_eq!(response.redirects.len(), 1);
+        Ok(())
+    }
+
+    // 通过代理访问的客户端，代理需要认证时使用 user:secret
+    fn proxy_client(proxy: &str, user: Option<&str>) -> Client {
+        let mut config = ProxyConfig::default();
+        config.set_proxy(Proxy::parse(proxy).unwrap());
+        if let Some(user) = user {
+            config.set_credentials(user);
+        }
+        let mut client = Client::new();
+        client.set_proxy(config);
+        client
+    }
+
+    #[test]
+    fn test_http_proxy() -> Result<()> {
+        let proxy = serve_proxy(|req| {
+            let body = format!(
+                "{} {} {}",
+                req.method,
+                req.target,
+                req.header("Proxy-Authorization").unwrap_or("none")
+            );
+            response("200 OK", &[], body.as_bytes())
+        });
+        // 目标地址不需要解析，由代理负责连接
+        let mut client = proxy_client(&proxy.url, Some("user:secret"));
+        client.get("http://origin.rcurl.test:8080/a?b=1#frag")?;
+        assert_eq!(
+            client.execute()?.body,
+            b"GET http://origin.rcurl.test:8080/a?b=1 Basic dXNlcjpzZWNyZXQ="
+        );
+        let mut client = proxy_client(&proxy.url, None);
+        client.get("http://origin.rcurl.test/")?;
+        assert_eq!(
+            client.execute()?.body,
+            b"GET http://origin.rcurl.test/ none"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_socks_proxy() -> Result<()> {
+        let server = serve(|req| {
+            let host = req.header("Host").unwrap_or_default();
+            response("200 OK", &[], host.as_bytes())
+        });
+        let backend = server.url.trim_start_matches("http://").parse().unwrap();
+
+        // socks5h 和 socks4a 由代理解析域名
+        let (socks, targets) = serve_socks(backend, Some(("user", "secret")), &[]);
+        let socks_url = socks.url.replace("http://", "socks5h://");
+        let mut client = proxy_client(&socks_url, Some("user:secret"));
+        client.get("http://origin.rcurl.test/")?;
+        assert_eq!(client.execute()?.body, b"origin.rcurl.test");
+        let socks_url = socks.url.replace("http://", "socks4a://");
+        let mut client = proxy_client(&socks_url, Some("user"));
+        client.get("http://origin.rcurl.test:8080/")?;
+        assert_eq!(client.execute()?.body, b"origin.rcurl.test:8080");
+        assert_eq!(
+            *targets.lock().unwrap(),
+            ["origin.rcurl.test:80", "origin.rcurl.test:8080"]
+        );
+
+        // 密码错误
+        let socks_url = socks.url.replace("http://", "socks5h://");
+        let mut client = proxy_client(&socks_url, Some("user:wrong"));
+        client.get("http://origin.rcurl.test/")?;
+        assert!(matches!(client.execute(), Err(RequestError::Proxy(_))));
+
+        // socks5 在本地解析域名，代理收到的是地址
+        let (socks, targets) = serve_socks(backend, None, &[]);
+        let socks_url = socks.url.replace("http://", "socks5://");
+        let mut client = proxy_client(&socks_url, None);
+        client.get("http://localhost:8080/")?;
+        assert_eq!(client.execute()?.body, b"localhost:8080");
+        let target = targets.lock().unwrap()[0].clone();
+        assert!(target.parse::<SocketAddr>().is_ok(), "{}", target);
+        Ok(())
+    }
+
+    // origin.rcurl.test 解析为固定的地址，其他主机使用系统解析器
+    struct OriginResolver(Vec<SocketAddr>);
+
+    impl Resolver for OriginResolver {
+        fn resolve(&self, host: &str, port: u16) -> Result<Vec<SocketAddr>> {
+            match host {
+                "origin.rcurl.test" => Ok(self.0.clone()),
+                _ => SystemResolver.resolve(host, port),
+            }
+        }
+    }
+
+    #[test]
+    fn test_socks5_tries_every_address() -> Result<()> {
+        let server = serve(|_| response("200 OK", &[], b"ok"));
+        let backend = server.url.trim_start_matches("http://").parse().unwrap();
+        let (socks, targets) = serve_socks(backend, None, &["[2001:db8::1]", "192.0.2.1"]);
+        let socks_url = socks.url.replace("http://", "socks5://");
+        let addrs: Vec<SocketAddr> = ["[2001:db8::1]:80", "192.0.2.1:80", "127.0.0.1:80"]
+            .iter()
+            .map(|a| a.parse().unwrap())
+            .collect();
+        // 代理拒绝连接前两个地址，每个地址重新连接代理后握手
+        let mut client = proxy_client(&socks_url, None);
+        client.set_resolver(Box::new(OriginResolver(addrs.clone())));
+        client.get("http://origin.rcurl.test/")?;
+        assert_eq!(client.execute()?.body, b"ok");
+        assert_eq!(socks.accepted(), 3);
+        assert_eq!(*targets.lock().unwrap(), ["127.0.0.1:80"]);
+
+        // -4 时只尝试 IPv4 地址；所有地址都被拒绝时返回最后一个错误
+        let mut client = proxy_client(&socks_url, None);
+        client.set_resolver(Box::new(OriginResolver(addrs[..2].to_vec())));
+        client.set_ip_version(Some(IpVersion::V4));
+        client.get("http://origin.rcurl.test/")?;
+        assert!(matches!(client.execute(), Err(RequestError::Connect(_))));
+        assert_eq!(socks.accepted(), 4);
+        Ok(())
+    }
+
+    #[test]
+    fn test_interleave() {
+        let addrs: Vec<SocketAddr> = ["[::1]:1", "[::2]:1", "[::3]:1", "10.0.0.1:1", "10.0.0.2:1"]
+            .iter()
+            .map(|a| a.parse().unwrap())
+            .collect();
+        let sorted: Vec<String> = interleave(addrs).iter().map(|a| a.to_string()).collect();
+        assert_eq!(
+            sorted,
+            ["[::1]:1", "10.0.0.1:1", "[::2]:1", "10.0.0.2:1", "[::3]:1"]
+        );
+        assert!(interleave(Vec::new()).is_empty());
+    }
+
+    #[test]
+    fn test_happy_eyeballs() -> Result<()> {
+        let server = serve(|_| response("200 OK", &[], b"ok"));
+        let addr: SocketAddr = server.url.trim_start_matches("http://").parse().unwrap();
+        let closed = TcpListener::bind("[::1]:0")
+            .or_else(|_| TcpListener::bind("127.0.0.1:0"))?
+            .local_addr()?;
+        // 文档保留地址，连接可能一直没有结果，不能阻塞后面的地址
+        let blackhole: SocketAddr = "[2001:db8::1]:80".parse().unwrap();
+        let start = std::time::Instant::now();
+        let stream = happy_eyeballs(vec![blackhole, closed, addr], Duration::from_secs(10))?;
+        assert_eq!(stream.peer_addr()?, addr);
+        assert!(start.elapsed() < Duration::from_secs(2));
+
+        let mut client = Client::new();
+        client.set_resolver(Box::new(StaticResolver(vec![closed, addr])));
+        client.set_ip_version(Some(IpVersion::V4));
+        client.get("http://rcurl.test/")?;
+        assert_eq!(client.execute()?.body, b"ok");
+
+        let mut client = Client::new();
+        client.set_resolver(Box::new(StaticResolver(vec![addr])));
+        client.set_ip_version(Some(IpVersion::V6));
+        client.get("http://rcurl.test/")?;
+        assert!(matches!(client.execute(), Err(RequestError::Resolve(_))));
+        Ok(())
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn test_compressed() -> Result<()> {
+        use flate2::{Compression, write::GzEncoder};
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(b"compressed body")?;
+        let gzipped = encoder.finish()?;
+        let server = serve(move |req| {
+            let accept = req.header("Accept-Encoding").unwrap_or_default();
+            if accept.contains("gzip") {
+                response("200 OK", &[("Content-Encoding", "gzip")], &gzipped)
+            } else {
+                response("200 OK", &[], b"plain body")
+            }
+        });
+        let mut client = Client::new();
+        client.get(&server.url)?;
+        assert_eq!(client.execute()?.body, b"plain body");
+
+        client.set_compressed(true);
+        for _ in 0..2 {
+            client.get(&server.url)?;
+            assert_eq!(client.execute()?.body, b"compressed body");
+        }
+        assert_eq!(server.accepted(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_cookie_jar() -> Result<()> {
+        let server = serve(|req| match req.target.as_str() {
+            "/login" => response(
+                "302 Found",
+                &[
+                    ("Location", "/home"),
+                    ("Set-Cookie", "sid=abc; Path=/; HttpOnly"),
+                    (
+                        "Set-Cookie",
+                        "theme=dark; Expires=Wed, 21 Oct 2099 07:28:00 GMT",
+                    ),
+                ],
+                b"",
+            ),
+            _ => {
+                let cookie = req.header("Cookie").unwrap_or("none").to_string();
+                response("200 OK", &[], cookie.as_bytes())
+            }
+        });
+        let mut client = Client::new();
+        client.set_follow_redirects(true);
+        client.get(&format!("{}/login", server.url))?;
+        assert_eq!(client.execute()?.body, b"none");
+
+        client.set_cookie_jar(CookieJar::new());
+        client.get(&format!("{}/login", server.url))?;
+        assert_eq!(client.execute()?.body, b"sid=abc; theme=dark");
+        client
+            .get(&format!("{}/home", server.url))?
+            .borrow_mut()
+            .set("Cookie".to_string(), "extra=1".to_string());
+        assert_eq!(client.execute()?.body, b"extra=1; sid=abc; theme=dark");
+        assert_eq!(client.cookie_jar().unwrap().iter().count(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_unsupported_encoding() {
+        let server = serve(|_| response("200 OK", &[("Content-Encoding", "compress")], b"x"));
+        let mut client = Client::new();
+        client.set_compressed(true);
+        client.get(&server.url).unwrap();
+        assert!(matches!(
+            client.execute(),
+            Err(RequestError::UnsupportedEncoding(name)) if name == "compress"
+        ));
+    }
+
+    #[cfg(feature = "rustls")]
+    mod tls {
+        use super::*;
+        use rustls::pki_types::PrivateKeyDer;
+        use rustls::{ServerConfig, ServerConnection, StreamOwned};
+        use std::io::Read;
+
+        // 使用自签名证书的本地 https 服务器，返回端口和证书的 PEM
+        fn tls_server() -> (u16, String) {
+            let cert = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+            let pem = cert.cert.pem();
+            let provider = Arc::new(rustls::crypto::ring::default_provider());
+            let config = ServerConfig::builder_with_provider(provider)
+                .with_safe_default_protocol_versions()
+                .unwrap()
+                .with_no_client_auth()
+                .with_single_cert(
+                    vec![cert.cert.der().clone()],
+                    PrivateKeyDer::Pkcs8(cert.key_pair.serialize_der().into()),
+                )
+                .unwrap();
+            let config = Arc::new(config);
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let port = listener.local_addr().unwrap().port();
+            thread::spawn(move || {
+                for stream in listener.incoming() {
+                    let conn = ServerConnection::new(Arc::clone(&config)).unwrap();
+                    let mut tls = StreamOwned::new(conn, stream.unwrap());
+                    let mut buf = [0u8; 1024];
+                    let mut request = Vec::new();
+                    while !request.ends_with(b"\r\n\r\n") {
+                        match tls.read(&mut buf) {
+                            Ok(0) | Err(_) => break,
+                            Ok(n) => request.extend_from_slice(&buf[..n]),
+                        }
+                    }
+                    let _ = tls.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 6\r\n\r\nsecure");
+                    let _ = tls.flush();
+                }
+            });
+            (port, pem)
+        }
+
+        #[test]
+        fn test_https_through_proxy() {
+            let (port, _) = tls_server();
+            let proxy = serve_proxy(|req| match req.header("Proxy-Authorization") {
+                Some("Basic dXNlcjpzZWNyZXQ=") if req.method == "CONNECT" => {
+                    b"HTTP/1.1 200 Connection established\r\n\r\n".to_vec()
+                }
+                _ => response("407 Proxy Authentication Required", &[], b""),
+            });
+            let url = format!("https://localhost:{}/", port);
+            let tls = TlsConfig {
+                ca_file: None,
+                insecure: true,
+                alpn: None,
+            };
+            let mut client = proxy_client(&proxy.url, Some("user:secret"));
+            client.set_tls_config(tls.clone());
+            client.get(&url).unwrap();
+            assert_eq!(client.execute().unwrap().body, b"secure");
+
+            let mut client = proxy_client(&proxy.url, None);
+            client.set_tls_config(tls);
+            client.get(&url).unwrap();
+            assert!(matches!(client.execute(), Err(RequestError::Proxy(_))));
+        }
+
+        #[test]
+        fn test_https_with_cacert() -> Result<()> {
+            let (port, pem) = tls_server();
+            let ca_file = std::env::temp_dir().join(format!("rcurl-test-ca-{}.pem", port));
+            std::fs::write(&ca_file, pem)?;
+            let mut client = Client::new();
+            client.set_tls_config(TlsConfig {
+                ca_file: Some(ca_file.clone()),
+                insecure: false,
+                alpn: None,
+            });
+            client.get(&format!("https://localhost:{}/", port))?;
+            let response = client.execute();
+            std::fs::remove_file(&ca_file)?;
+            assert_eq!(response?.body, b"secure");
+            Ok(())
+        }
+
+        #[test]
+        fn test_https_self_signed() {
+            let (port, _) = tls_server();
+            let url = format!("https://localhost:{}/", port);
+            let mut client = Client::new();
+            client.set_tls_config(TlsConfig {
+                ca_file: None,
+                insecure: false,
+                alpn: None,
+            });
+            client.get(&url).unwrap();
+            assert!(matches!(client.execute(), Err(RequestError::Tls(_))));
+
+            client.set_tls_config(TlsConfig {
+                ca_file: None,
+                insecure: true,
+                alpn: None,
+            });
+            client.get(&url).unwrap();
+            assert_eq!(client.execute().unwrap().body, b"secure");
+        }
+    }
 }
@@ -0,0 +1,637 @@
+//! RFC 6265 cookie 存储，可以读写 curl 使用的 Netscape cookie 文件
+use super::url::Url;
+use log::debug;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::net::IpAddr;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// Netscape cookie 文件中 HttpOnly cookie 的前缀
+const HTTP_ONLY_PREFIX: &str = "#HttpOnly_";
+// 内置的多级公共后缀，只包含常见的国家二级域名和托管服务域名，并不是完整的公共后缀列表
+// (https://publicsuffix.org/)。单级域名(顶级域名)总是被当作公共后缀
+const PUBLIC_SUFFIXES: &[&str] = &[
+    "ac.uk",
+    "co.uk",
+    "gov.uk",
+    "ltd.uk",
+    "me.uk",
+    "net.uk",
+    "org.uk",
+    "plc.uk",
+    "sch.uk",
+    "com.au",
+    "edu.au",
+    "gov.au",
+    "net.au",
+    "org.au",
+    "ac.jp",
+    "co.jp",
+    "go.jp",
+    "ne.jp",
+    "or.jp",
+    "com.cn",
+    "edu.cn",
+    "gov.cn",
+    "net.cn",
+    "org.cn",
+    "com.hk",
+    "org.hk",
+    "com.tw",
+    "org.tw",
+    "co.kr",
+    "or.kr",
+    "co.nz",
+    "org.nz",
+    "co.za",
+    "org.za",
+    "co.in",
+    "net.in",
+    "org.in",
+    "com.br",
+    "net.br",
+    "org.br",
+    "com.mx",
+    "com.sg",
+    "com.tr",
+    "co.il",
+    "com.ar",
+    "github.io",
+    "gitlab.io",
+    "herokuapp.com",
+    "appspot.com",
+    "vercel.app",
+    "netlify.app",
+    "pages.dev",
+    "workers.dev",
+    "blogspot.com",
+    "cloudfront.net",
+    "azurewebsites.net",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SameSite {
+    Strict,
+    Lax,
+    None,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cookie {
+    pub name: String,
+    pub value: String,
+    /// 小写且不带前导点的域名
+    pub domain: String,
+    /// 为 true 时只发送给 domain 本身，否则也发送给它的子域名
+    pub host_only: bool,
+    pub path: String,
+    /// 过期时间，None 表示会话 cookie
+    pub expires: Option<SystemTime>,
+    pub secure: bool,
+    pub http_only: bool,
+    pub same_site: Option<SameSite>,
+}
+
+impl Cookie {
+    /// 按 RFC 6265 5.2 解析 Set-Cookie 的值，url 为返回该响应的请求地址
+    ///
+    /// 格式错误或者 Domain 与请求地址不匹配时返回 None
+    pub fn parse(set_cookie: &str, url: &Url) -> Option<Cookie> {
+        let mut parts = set_cookie.split(';');
+        let (name, value) = parts.next()?.split_once('=')?;
+        let name = name.trim();
+        if name.is_empty() {
+            return None;
+        }
+        let host = url.host.to_ascii_lowercase();
+        let mut cookie = Cookie {
+            name: name.to_string(),
+            value: value.trim().to_string(),
+            domain: host.clone(),
+            host_only: true,
+            path: default_path(&url.path),
+            expires: None,
+            secure: false,
+            http_only: false,
+            same_site: None,
+        };
+        let mut max_age = None;
+        for attr in parts {
+            let (key, value) = match attr.split_once('=') {
+                Some((key, value)) => (key.trim(), value.trim()),
+                None => (attr.trim(), ""),
+            };
+            match key.to_ascii_lowercase().as_str() {
+                "expires" => {
+                    if let Some(time) = parse_cookie_date(value) {
+                        cookie.expires = Some(time);
+                    }
+                }
+                "max-age" => {
+                    if let Ok(secs) = value.parse::<i64>() {
+                        // 小于等于 0 表示立即过期
+                        max_age = Some(match u64::try_from(secs) {
+                            Ok(secs) if secs > 0 => SystemTime::now()
+                                .checked_add(Duration::from_secs(secs))
+                                .unwrap_or_else(far_future),
+                            _ => UNIX_EPOCH,
+                        });
+                    }
+                }
+                "domain" => {
+                    let domain = value.trim_start_matches('.').to_ascii_lowercase();
+                    if domain.is_empty() {
+                        continue;
+                    }
+                    // 只能为当前主机或其上级域名设置 cookie
+                    if !domain_match(&host, &domain) {
+                        debug!("忽略 Domain 不匹配的 cookie: {} (主机 {})", name, host);
+                        return None;
+                    }
+                    // 公共后缀(RFC 6265 5.3 第 5 步): 与主机相同时当作只发给该主机的 cookie，否则拒绝
+                    if is_public_suffix(&domain) {
+                        if domain != host {
+                            debug!("忽略 Domain 为公共后缀的 cookie: {} ({})", name, domain);
+                            return None;
+                        }
+                        continue;
+                    }
+                    cookie.domain = domain;
+                    cookie.host_only = false;
+                }
+                // 不以 / 开头的 Path 使用默认路径
+                "path" if value.starts_with('/') => cookie.path = value.to_string(),
+                "secure" => cookie.secure = true,
+                "httponly" => cookie.http_only = true,
+                "samesite" => {
+                    cookie.same_site = match value.to_ascii_lowercase().as_str() {
+                        "strict" => Some(SameSite::Strict),
+                        "lax" => Some(SameSite::Lax),
+                        "none" => Some(SameSite::None),
+                        _ => None,
+                    }
+                }
+                _ => {}
+            }
+        }
+        // Max-Age 优先于 Expires
+        if max_age.is_some() {
+            cookie.expires = max_age;
+        }
+        // 非安全连接不能设置 Secure cookie (RFC 6265bis)
+        if cookie.secure && !is_secure_scheme(&url.scheme) {
+            return None;
+        }
+        Some(cookie)
+    }
+
+    pub fn is_expired(&self, now: SystemTime) -> bool {
+        self.expires.is_some_and(|expires| expires <= now)
+    }
+
+    /// 是否应该随发往 url 的请求发送
+    pub fn matches(&self, url: &Url) -> bool {
+        let host = url.host.to_ascii_lowercase();
+        let host_matches = if self.host_only {
+            host == self.domain
+        } else {
+            domain_match(&host, &self.domain)
+        };
+        host_matches
+            && path_match(&url.path, &self.path)
+            && (!self.secure || is_secure_scheme(&url.scheme))
+    }
+
+    // 同名、同域名、同路径的 cookie 会相互覆盖
+    fn same_identity(&self, other: &Cookie) -> bool {
+        self.name == other.name && self.domain == other.domain && self.path == other.path
+    }
+}
+
+/// cookie 存储
+#[derive(Debug, Clone, Default)]
+pub struct CookieJar {
+    // 按创建顺序排列
+    cookies: Vec<Cookie>,
+}
+
+impl CookieJar {
+    pub fn new() -> Self {
+        CookieJar::default()
+    }
+
+    /// 从 Netscape 格式的 cookie 文件加载
+    pub fn load(path: &Path) -> io::Result<Self> {
+        Self::read_netscape(BufReader::new(File::open(path)?))
+    }
+
+    /// 保存为 Netscape 格式的 cookie 文件
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write_netscape(&mut writer)?;
+        writer.flush()
+    }
+
+    /// 读取 Netscape 格式: 每行为 domain、include_subdomains、path、secure、expires、name、value，
+    /// 以 Tab 分隔，HttpOnly cookie 的域名带 `#HttpOnly_` 前缀
+    pub fn read_netscape<R: BufRead>(reader: R) -> io::Result<Self> {
+        let mut jar = CookieJar::new();
+        let now = SystemTime::now();
+        for line in reader.lines() {
+            let line = line?;
+            let line = line.trim_end_matches(['\r', '\n']);
+            let (line, http_only) = match line.strip_prefix(HTTP_ONLY_PREFIX) {
+                Some(rest) => (rest, true),
+                None => (line, false),
+            };
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let fields: Vec<&str> = line.split('\t').collect();
+            if fields.len() < 6 {
+                debug!("忽略格式错误的 cookie 行: {}", line);
+                continue;
+            }
+            let expires = match fields[4].parse::<u64>() {
+                Ok(0) => None,
+                Ok(secs) => Some(UNIX_EPOCH + Duration::from_secs(secs)),
+                Err(_) => {
+                    debug!("忽略过期时间错误的 cookie 行: {}", line);
+                    continue;
+                }
+            };
+            let domain = fields[0];
+            let cookie = Cookie {
+                name: fields[5].to_string(),
+                value: fields.get(6).copied().unwrap_or_default().to_string(),
+                domain: domain.trim_start_matches('.').to_ascii_lowercase(),
+                host_only: !fields[1].eq_ignore_ascii_case("TRUE") && !domain.starts_with('.'),
+                path: fields[2].to_string(),
+                expires,
+                secure: fields[3].eq_ignore_ascii_case("TRUE"),
+                http_only,
+                same_site: None,
+            };
+            if !cookie.is_expired(now) {
+                jar.insert(cookie);
+            }
+        }
+        Ok(jar)
+    }
+
+    /// 按 curl 的格式写出所有未过期的 cookie，会话 cookie 的过期时间写为 0
+    pub fn write_netscape<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writeln!(writer, "# Netscape HTTP Cookie File")?;
+        writeln!(writer, "# https://curl.se/docs/http-cookies.html")?;
+        writeln!(
+            writer,
+            "# This file was generated by rcurl! Edit at your own risk."
+        )?;
+        writeln!(writer)?;
+        let now = SystemTime::now();
+        for cookie in self.cookies.iter().filter(|c| !c.is_expired(now)) {
+            let expires = cookie
+                .expires
+                .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+                .map_or(0, |d| d.as_secs());
+            writeln!(
+                writer,
+                "{}{}{}\t{}\t{}\t{}\t{}\t{}\t{}",
+                if cookie.http_only {
+                    HTTP_ONLY_PREFIX
+                } else {
+                    ""
+                },
+                if cookie.host_only { "" } else { "." },
+                cookie.domain,
+                if cookie.host_only { "FALSE" } else { "TRUE" },
+                cookie.path,
+                if cookie.secure { "TRUE" } else { "FALSE" },
+                expires,
+                cookie.name,
+                cookie.value
+            )?;
+        }
+        Ok(())
+    }
+
+    /// 保存响应中的一个 Set-Cookie，过期的 cookie 会删除已有的同名 cookie
+    pub fn store(&mut self, url: &Url, set_cookie: &str) {
+        match Cookie::parse(set_cookie, url) {
+            Some(cookie) => {
+                debug!(
+                    "保存 cookie: {}={} ({})",
+                    cookie.name, cookie.value, cookie.domain
+                );
+                self.insert(cookie);
+            }
+            None => debug!("忽略无效的 Set-Cookie: {}", set_cookie),
+        }
+    }
+
+    /// 添加或替换 cookie，替换时保留原来的位置(创建顺序)
+    pub fn insert(&mut self, cookie: Cookie) {
+        let existing = self.cookies.iter().position(|c| c.same_identity(&cookie));
+        if cookie.is_expired(SystemTime::now()) {
+            if let Some(pos) = existing {
+                self.cookies.remove(pos);
+            }
+            return;
+        }
+        match existing {
+            Some(pos) => self.cookies[pos] = cookie,
+            None => self.cookies.push(cookie),
+        }
+    }
+
+    /// 发往 url 的请求应该携带的 Cookie 头，路径更长的 cookie 排在前面
+    pub fn cookie_header(&self, url: &Url) -> Option<String> {
+        let now = SystemTime::now();
+        let mut cookies: Vec<&Cookie> = self
+            .cookies
+            .iter()
+            .filter(|c| !c.is_expired(now) && c.matches(url))
+            .collect();
+        if cookies.is_empty() {
+            return None;
+        }
+        // 稳定排序，路径长度相同时按创建顺序
+        cookies.sort_by_key(|c| std::cmp::Reverse(c.path.len()));
+        let pairs: Vec<String> = cookies
+            .iter()
+            .map(|c| format!("{}={}", c.name, c.value))
+            .collect();
+        Some(pairs.join("; "))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Cookie> {
+        self.cookies.iter()
+    }
+}
+
+fn is_secure_scheme(scheme: &str) -> bool {
+    scheme.eq_ignore_ascii_case("https") || scheme.eq_ignore_ascii_case("wss")
+}
+
+fn far_future() -> SystemTime {
+    UNIX_EPOCH + Duration::from_secs(i32::MAX as u64)
+}
+
+// RFC 6265 5.1.3: host 与 domain 相同，或者是 domain 的子域名(IP 地址只能完全相同)
+fn domain_match(host: &str, domain: &str) -> bool {
+    if host == domain {
+        return true;
+    }
+    host.parse::<IpAddr>().is_err()
+        && host.len() > domain.len()
+        && host.ends_with(domain)
+        && host.as_bytes()[host.len() - domain.len() - 1] == b'.'
+}
+
+// 不能作为 cookie Domain 的公共后缀
+fn is_public_suffix(domain: &str) -> bool {
+    !domain.contains('.') || PUBLIC_SUFFIXES.contains(&domain)
+}
+
+// RFC 6265 5.1.4: 请求路径的默认 cookie 路径
+fn default_path(path: &str) -> String {
+    if !path.starts_with('/') {
+        return "/".to_string();
+    }
+    match path.rfind('/') {
+        Some(0) | None => "/".to_string(),
+        Some(pos) => path[..pos].to_string(),
+    }
+}
+
+// RFC 6265 5.1.4: 请求路径是否在 cookie 路径下
+fn path_match(request_path: &str, cookie_path: &str) -> bool {
+    let request_path = if request_path.is_empty() {
+        "/"
+    } else {
+        request_path
+    };
+    request_path == cookie_path
+        || (request_path.starts_with(cookie_path)
+            && (cookie_path.ends_with('/') || request_path.as_bytes()[cookie_path.len()] == b'/'))
+}
+
+/// 按 RFC 6265 5.1.1 解析 Expires 中的日期，兼容 `Wed, 21-Oct-2015 07:28:00 GMT` 等各种写法
+pub fn parse_cookie_date(value: &str) -> Option<SystemTime> {
+    let is_delimiter = |c: char| matches!(c, '\t' | ' '..='/' | ';'..='@' | '['..='`' | '{'..='~');
+    let mut time = None;
+    let mut day = None;
+    let mut month = None;
+    let mut year = None;
+    for token in value.split(is_delimiter).filter(|t| !t.is_empty()) {
+        if time.is_none()
+            && let Some(t) = parse_time(token)
+        {
+            time = Some(t);
+        } else if day.is_none()
+            && let Some(d) = leading_digits(token, 1, 2)
+        {
+            day = Some(d);
+        } else if month.is_none()
+            && let Some(m) = parse_month(token)
+        {
+            month = Some(m);
+        } else if year.is_none()
+            && let Some(y) = leading_digits(token, 2, 4)
+        {
+            year = Some(y);
+        }
+    }
+    let (hour, minute, second) = time?;
+    let (day, month, mut year) = (day?, month?, year?);
+    match year {
+        70..=99 => year += 1900,
+        0..=69 => year += 2000,
+        _ => {}
+    }
+    if !(1..=31).contains(&day) || year < 1601 || hour > 23 || minute > 59 || second > 59 {
+        return None;
+    }
+    let secs = days_from_civil(year as i64, month, day as i64) * 86400
+        + (hour * 3600 + minute * 60 + second) as i64;
+    // 1970 年之前的日期当作已经过期
+    Some(UNIX_EPOCH + Duration::from_secs(u64::try_from(secs).unwrap_or(0)))
+}
+
+// 开头的 min..=max 位数字，数字后面不能紧跟数字
+fn leading_digits(token: &str, min: usize, max: usize) -> Option<u32> {
+    let len = token.bytes().take_while(u8::is_ascii_digit).count();
+    if len < min || len > max {
+        return None;
+    }
+    token[..len].parse().ok()
+}
+
+// hh:mm:ss，每个字段 1 到 2 位数字
+fn parse_time(token: &str) -> Option<(u32, u32, u32)> {
+    let mut fields = token.splitn(3, ':');
+    let hour = fields.next()?;
+    let minute = fields.next()?;
+    let second = fields.next()?;
+    let exact = |s: &str| (1..=2).contains(&s.len()).then(|| s.parse::<u32>().ok())?;
+    Some((exact(hour)?, exact(minute)?, leading_digits(second, 1, 2)?))
+}
+
+fn parse_month(token: &str) -> Option<u32> {
+    const MONTHS: [&str; 12] = [
+        "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+    ];
+    let prefix = token.get(..3)?.to_ascii_lowercase();
+    MONTHS
+        .iter()
+        .position(|m| *m == prefix)
+        .map(|i| i as u32 + 1)
+}
+
+// 公历日期到 1970-01-01 的天数
+fn days_from_civil(year: i64, month: u32, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year - era * 400;
+    let month = month as i64;
+    let doy = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn url(value: &str) -> Url {
+        Url::parse(value).unwrap()
+    }
+
+    #[test]
+    fn test_parse_cookie_date() {
+        let expected = UNIX_EPOCH + Duration::from_secs(1445412480);
+        for date in [
+            "Wed, 21 Oct 2015 07:28:00 GMT",
+            "Wed, 21-Oct-2015 07:28:00 GMT",
+            "Wednesday, 21-Oct-15 07:28:00 GMT",
+            "Wed Oct 21 07:28:00 2015",
+        ] {
+            assert_eq!(parse_cookie_date(date), Some(expected), "{date}");
+        }
+        assert_eq!(
+            parse_cookie_date("Thu, 01 Jan 1970 00:00:00 GMT"),
+            Some(UNIX_EPOCH)
+        );
+        assert_eq!(parse_cookie_date("Wed, 32 Oct 2015 07:28:00 GMT"), None);
+        assert_eq!(parse_cookie_date("tomorrow"), None);
+    }
+
+    #[test]
+    fn test_parse_set_cookie() {
+        let base = url("http://www.example.com/account/login");
+        let cookie = Cookie::parse(
+            "sid=abc123; Domain=.Example.com; Path=/; Max-Age=3600; HttpOnly; SameSite=Lax",
+            &base,
+        )
+        .unwrap();
+        assert_eq!(cookie.name, "sid");
+        assert_eq!(cookie.value, "abc123");
+        assert_eq!(cookie.domain, "example.com");
+        assert!(!cookie.host_only);
+        assert_eq!(cookie.path, "/");
+        assert!(cookie.http_only);
+        assert_eq!(cookie.same_site, Some(SameSite::Lax));
+        assert!(cookie.expires.unwrap() > SystemTime::now());
+
+        let cookie = Cookie::parse("lang=zh", &base).unwrap();
+        assert!(cookie.host_only);
+        assert_eq!(cookie.domain, "www.example.com");
+        assert_eq!(cookie.path, "/account");
+        assert_eq!(cookie.expires, None);
+
+        // Max-Age 优先于 Expires
+        let cookie = Cookie::parse(
+            "a=1; Max-Age=0; Expires=Wed, 21 Oct 2099 07:28:00 GMT",
+            &base,
+        )
+        .unwrap();
+        assert!(cookie.is_expired(SystemTime::now()));
+
+        assert!(Cookie::parse("a=1; Domain=other.com", &base).is_none());
+        assert!(Cookie::parse("a=1; Domain=com", &base).is_none());
+        // 公共后缀不能作为 Domain，与主机相同时只发给该主机
+        let uk = url("http://a.co.uk/");
+        assert!(Cookie::parse("x=1; Domain=co.uk", &uk).is_none());
+        assert!(Cookie::parse("x=1; Domain=a.co.uk", &uk).is_some_and(|c| !c.host_only));
+        let cookie = Cookie::parse("x=1; Domain=github.io", &url("https://github.io/")).unwrap();
+        assert!(cookie.host_only);
+        assert_eq!(cookie.domain, "github.io");
+        assert!(Cookie::parse("x=1; Domain=github.io", &url("https://me.github.io/")).is_none());
+        assert!(Cookie::parse("a=1; Secure", &base).is_none());
+        assert!(Cookie::parse("novalue", &base).is_none());
+        assert!(Cookie::parse("=1", &base).is_none());
+    }
+
+    #[test]
+    fn test_cookie_header() {
+        let mut jar = CookieJar::new();
+        let login = url("https://example.com/app/login");
+        jar.store(&login, "a=1; Path=/");
+        jar.store(&login, "b=2");
+        jar.store(&login, "c=3; Secure; Path=/");
+        jar.store(&login, "d=4; Domain=example.com; Path=/");
+
+        assert_eq!(
+            jar.cookie_header(&url("https://example.com/app/home")),
+            Some("b=2; a=1; c=3; d=4".to_string())
+        );
+        assert_eq!(
+            jar.cookie_header(&url("http://example.com/application")),
+            Some("a=1; d=4".to_string())
+        );
+        assert_eq!(
+            jar.cookie_header(&url("http://www.example.com/")),
+            Some("d=4".to_string())
+        );
+        assert_eq!(jar.cookie_header(&url("http://example.org/")), None);
+
+        // 覆盖和删除
+        jar.store(&login, "a=changed; Path=/");
+        jar.store(&login, "b=; Max-Age=0");
+        assert_eq!(
+            jar.cookie_header(&url("http://example.com/app/x")),
+            Some("a=changed; d=4".to_string())
+        );
+    }
+
+    #[test]
+    fn test_netscape_round_trip() -> io::Result<()> {
+        let file = "# Netscape HTTP Cookie File\n\
+                    \n\
+                    .example.com\tTRUE\t/\tFALSE\t0\tsession\tyes\n\
+                    #HttpOnly_example.com\tFALSE\t/app\tTRUE\t4102444800\tsid\tabc\n\
+                    example.com\tFALSE\t/\tFALSE\t1\texpired\tgone\n\
+                    broken line\n";
+        let jar = CookieJar::read_netscape(file.as_bytes())?;
+        let cookies: Vec<_> = jar.iter().collect();
+        assert_eq!(cookies.len(), 2);
+        assert_eq!(cookies[0].name, "session");
+        assert!(!cookies[0].host_only);
+        assert_eq!(cookies[0].expires, None);
+        assert!(cookies[1].http_only && cookies[1].secure && cookies[1].host_only);
+        assert_eq!(
+            cookies[1].expires,
+            Some(UNIX_EPOCH + Duration::from_secs(4102444800))
+        );
+
+        let mut out = Vec::new();
+        jar.write_netscape(&mut out)?;
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.starts_with("# Netscape HTTP Cookie File\n"));
+        assert!(out.contains("\n.example.com\tTRUE\t/\tFALSE\t0\tsession\tyes\n"));
+        assert!(out.contains("\n#HttpOnly_example.com\tFALSE\t/app\tTRUE\t4102444800\tsid\tabc\n"));
+        assert_eq!(CookieJar::read_netscape(out.as_bytes())?.iter().count(), 2);
+        Ok(())
+    }
+}
@@ -0,0 +1,321 @@
+//! 按 Content-Encoding 解码消息体，每种压缩格式由同名的 cargo feature 控制
+use std::io::{Read, Result as IoResult};
+
+/// 支持的内容编码
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    #[cfg(feature = "gzip")]
+    Gzip,
+    #[cfg(feature = "deflate")]
+    Deflate,
+    #[cfg(feature = "brotli")]
+    Brotli,
+    #[cfg(feature = "zstd")]
+    Zstd,
+}
+
+impl Encoding {
+    fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            #[cfg(feature = "gzip")]
+            "gzip" | "x-gzip" => Some(Encoding::Gzip),
+            #[cfg(feature = "deflate")]
+            "deflate" => Some(Encoding::Deflate),
+            #[cfg(feature = "brotli")]
+            "br" => Some(Encoding::Brotli),
+            #[cfg(feature = "zstd")]
+            "zstd" => Some(Encoding::Zstd),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            #[cfg(feature = "gzip")]
+            Encoding::Gzip => "gzip",
+            #[cfg(feature = "deflate")]
+            Encoding::Deflate => "deflate",
+            #[cfg(feature = "brotli")]
+            Encoding::Brotli => "br",
+            #[cfg(feature = "zstd")]
+            Encoding::Zstd => "zstd",
+        }
+    }
+}
+
+/// 编译时启用的所有编码
+const SUPPORTED: &[Encoding] = &[
+    #[cfg(feature = "gzip")]
+    Encoding::Gzip,
+    #[cfg(feature = "deflate")]
+    Encoding::Deflate,
+    #[cfg(feature = "brotli")]
+    Encoding::Brotli,
+    #[cfg(feature = "zstd")]
+    Encoding::Zstd,
+];
+
+/// 请求时发送的 Accept-Encoding，没有启用任何编码时返回 None
+pub fn accept_encoding() -> Option<String> {
+    if SUPPORTED.is_empty() {
+        return None;
+    }
+    let names: Vec<&str> = SUPPORTED.iter().map(|e| e.name()).collect();
+    Some(names.join(", "))
+}
+
+/// 解析 Content-Encoding 头，按编码的应用顺序返回；遇到不支持的编码时返回其名称
+pub fn parse_content_encoding(value: &str) -> Result<Vec<Encoding>, String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty() && !name.eq_ignore_ascii_case("identity"))
+        .map(|name| Encoding::from_name(name).ok_or_else(|| name.to_string()))
+        .collect()
+}
+
+/// 解码消息体的读取器，多个编码时逐层嵌套，最内层是原始数据
+pub enum Decoder<R: Read> {
+    Identity(R),
+    #[cfg(feature = "gzip")]
+    Gzip(Box<flate2::read::MultiGzDecoder<Decoder<R>>>),
+    #[cfg(feature = "deflate")]
+    Deflate(Box<flate2::read::ZlibDecoder<Decoder<R>>>),
+    #[cfg(feature = "brotli")]
+    Brotli(Box<brotli::Decompressor<Decoder<R>>>),
+    #[cfg(feature = "zstd")]
+    Zstd(Box<zstd::ZstdDecoder<Decoder<R>>>),
+}
+
+impl<R: Read> Decoder<R> {
+    /// `encodings` 为 Content-Encoding 中的顺序，解码时从最后应用的编码开始
+    pub fn new(inner: R, encodings: &[Encoding]) -> Self {
+        encodings
+            .iter()
+            .rev()
+            .fold(Decoder::Identity(inner), |decoder, encoding| {
+                decoder.wrap(*encoding)
+            })
+    }
+
+    // 在当前读取器外层再套一层解码器
+    fn wrap(self, encoding: Encoding) -> Self {
+        match encoding {
+            #[cfg(feature = "gzip")]
+            Encoding::Gzip => Decoder::Gzip(Box::new(flate2::read::MultiGzDecoder::new(self))),
+            #[cfg(feature = "deflate")]
+            Encoding::Deflate => Decoder::Deflate(Box::new(flate2::read::ZlibDecoder::new(self))),
+            #[cfg(feature = "brotli")]
+            Encoding::Brotli => Decoder::Brotli(Box::new(brotli::Decompressor::new(self, 4096))),
+            #[cfg(feature = "zstd")]
+            Encoding::Zstd => Decoder::Zstd(Box::new(zstd::ZstdDecoder::new(self))),
+        }
+    }
+
+    /// 最内层的原始数据读取器
+    pub fn get_ref(&self) -> &R {
+        match self {
+            Decoder::Identity(inner) => inner,
+            #[cfg(feature = "gzip")]
+            Decoder::Gzip(d) => d.get_ref().get_ref(),
+            #[cfg(feature = "deflate")]
+            Decoder::Deflate(d) => d.get_ref().get_ref(),
+            #[cfg(feature = "brotli")]
+            Decoder::Brotli(d) => d.get_ref().get_ref(),
+            #[cfg(feature = "zstd")]
+            Decoder::Zstd(d) => d.get_ref().get_ref(),
+        }
+    }
+
+    pub fn get_mut(&mut self) -> &mut R {
+        match self {
+            Decoder::Identity(inner) => inner,
+            #[cfg(feature = "gzip")]
+            Decoder::Gzip(d) => d.get_mut().get_mut(),
+            #[cfg(feature = "deflate")]
+            Decoder::Deflate(d) => d.get_mut().get_mut(),
+            #[cfg(feature = "brotli")]
+            Decoder::Brotli(d) => d.get_mut().get_mut(),
+            #[cfg(feature = "zstd")]
+            Decoder::Zstd(d) => d.get_mut().get_mut(),
+        }
+    }
+}
+
+impl<R: Read> Read for Decoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
+        match self {
+            Decoder::Identity(inner) => inner.read(buf),
+            #[cfg(feature = "gzip")]
+            Decoder::Gzip(d) => d.read(buf),
+            #[cfg(feature = "deflate")]
+            Decoder::Deflate(d) => d.read(buf),
+            #[cfg(feature = "brotli")]
+            Decoder::Brotli(d) => d.read(buf),
+            #[cfg(feature = "zstd")]
+            Decoder::Zstd(d) => d.read(buf),
+        }
+    }
+}
+
+#[cfg(feature = "zstd")]
+mod zstd {
+    use ruzstd::decoding::{BlockDecodingStrategy, FrameDecoder};
+    use std::io::{self, Read};
+
+    /// ruzstd 的 StreamingDecoder 在创建时就会读取帧头，
+    /// 这里推迟到第一次读取时再初始化，避免在读取响应头时阻塞
+    pub struct ZstdDecoder<R: Read> {
+        source: R,
+        frame: FrameDecoder,
+        initialized: bool,
+    }
+
+    impl<R: Read> ZstdDecoder<R> {
+        pub fn new(source: R) -> Self {
+            ZstdDecoder {
+                source,
+                frame: FrameDecoder::new(),
+                initialized: false,
+            }
+        }
+
+        pub fn get_ref(&self) -> &R {
+            &self.source
+        }
+
+        pub fn get_mut(&mut self) -> &mut R {
+            &mut self.source
+        }
+    }
+
+    impl<R: Read> Read for ZstdDecoder<R> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if !self.initialized {
+                self.frame
+                    .init(&mut self.source)
+                    .map_err(io::Error::other)?;
+                self.initialized = true;
+            }
+            if self.frame.is_finished() && self.frame.can_collect() == 0 {
+                return Ok(0);
+            }
+            // 解码出的数据要攒够一个块才能取出，所以需要循环解码
+            while self.frame.can_collect() < buf.len() && !self.frame.is_finished() {
+                let needed = buf.len() - self.frame.can_collect();
+                self.frame
+                    .decode_blocks(&mut self.source, BlockDecodingStrategy::UptoBytes(needed))
+                    .map_err(io::Error::other)?;
+            }
+            self.frame.read(buf)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const TEXT: &[u8] = b"hello hello hello compressed world";
+
+    #[cfg(feature = "gzip")]
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        use std::io::Write;
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[cfg(feature = "brotli")]
+    fn brotli(data: &[u8]) -> Vec<u8> {
+        use std::io::Write;
+        let mut out = Vec::new();
+        {
+            let mut encoder = brotli::CompressorWriter::new(&mut out, 4096, 5, 22);
+            encoder.write_all(data).unwrap();
+        }
+        out
+    }
+
+    fn decode(data: &[u8], content_encoding: &str) -> Vec<u8> {
+        let encodings = parse_content_encoding(content_encoding).unwrap();
+        let mut decoder = Decoder::new(data, &encodings);
+        let mut out = Vec::new();
+        decoder.read_to_end(&mut out).unwrap();
+        assert!(decoder.get_ref().is_empty());
+        out
+    }
+
+    #[test]
+    fn test_parse_content_encoding() {
+        assert_eq!(parse_content_encoding(""), Ok(vec![]));
+        assert_eq!(parse_content_encoding("identity"), Ok(vec![]));
+        assert_eq!(decode(TEXT, "identity"), TEXT);
+        assert_eq!(
+            parse_content_encoding("compress"),
+            Err("compress".to_string())
+        );
+        #[cfg(all(feature = "gzip", feature = "brotli"))]
+        assert_eq!(
+            parse_content_encoding("X-Gzip, br"),
+            Ok(vec![Encoding::Gzip, Encoding::Brotli])
+        );
+    }
+
+    #[test]
+    fn test_accept_encoding() {
+        #[cfg(all(
+            feature = "gzip",
+            feature = "deflate",
+            feature = "brotli",
+            feature = "zstd"
+        ))]
+        assert_eq!(
+            accept_encoding().as_deref(),
+            Some("gzip, deflate, br, zstd")
+        );
+        assert_eq!(accept_encoding().is_some(), !SUPPORTED.is_empty());
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn test_gzip() {
+        assert_eq!(decode(&gzip(TEXT), "gzip"), TEXT);
+        // 多个 gzip 成员拼接在一起
+        let mut data = gzip(b"hello ");
+        data.extend(gzip(b"world"));
+        assert_eq!(decode(&data, "gzip"), b"hello world");
+    }
+
+    #[cfg(feature = "deflate")]
+    #[test]
+    fn test_deflate() {
+        use std::io::Write;
+        let mut encoder =
+            flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(TEXT).unwrap();
+        assert_eq!(decode(&encoder.finish().unwrap(), "deflate"), TEXT);
+    }
+
+    #[cfg(feature = "brotli")]
+    #[test]
+    fn test_brotli() {
+        assert_eq!(decode(&brotli(TEXT), "br"), TEXT);
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn test_zstd() {
+        use ruzstd::encoding::{CompressionLevel, compress_to_vec};
+        let data = compress_to_vec(TEXT, CompressionLevel::Fastest);
+        assert_eq!(decode(&data, "zstd"), TEXT);
+    }
+
+    #[cfg(all(feature = "gzip", feature = "brotli"))]
+    #[test]
+    fn test_stacked_encodings() {
+        // 先 gzip 再 br
+        let data = brotli(&gzip(TEXT));
+        assert_eq!(decode(&data, "gzip, br"), TEXT);
+    }
+}
@@ -1,203 +1,215 @@
+mod cache;
+mod message;
+mod resolv_conf;
+mod secure;
+
+pub use cache::{Cached, DnsCache};
+use log::debug;
+pub use message::{Header, Message, RData, Record, RecordType, class_name, fqdn};
+pub use resolv_conf::ResolvConf;
+pub use secure::{DohMethod, DohServer, DotServer};
 use std::collections::HashMap;
+use std::fmt;
 use std::fs::File;
-use std::io::{BufRead, BufReader};
-use std::net::{Ipv4Addr, UdpSocket};
-use std::time::Duration;
-
-// DNS 响应码
-enum ResponseCode {
-    NoError = 0,
-    FormatError = 1,
-    ServerFailure = 2,
-    NameEroor = 3,
-    NotImplemented = 4,
-    Refused = 5,
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{IpAddr, SocketAddr, TcpStream, UdpSocket};
+use std::sync::OnceLock;
+use std::thread;
+use std::time::{Duration, Instant};
+
+// 跟随 CNAME 链的最大长度
+const MAX_CNAME_CHAIN: usize = 8;
+
+// EDNS0 声明的 UDP 报文大小，DNS Flag Day 2020 推荐的值，可以避免 IP 分片
+const EDNS_PAYLOAD_SIZE: u16 = 1232;
+
+// 使用操作系统的安全随机数生成查询 ID，使伪造的响应难以猜中
+fn random_id() -> Result<u16, String> {
+    let mut id = [0u8; 2];
+    getrandom::getrandom(&mut id).map_err(|e| format!("生成随机数失败: {}", e))?;
+    Ok(u16::from_be_bytes(id))
 }
 
-// 创建随机查询 ID
-fn random_id() -> u16 {
-    use std::time::{SystemTime, UNIX_EPOCH};
-    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
-    (now.as_secs() as u16) ^ (now.subsec_nanos() as u16)
+/// 构建DNS查询包，使用随机的查询 ID，edns 为 true 时加入 EDNS0 OPT 记录
+pub fn build_query(domain: &str, qtype: RecordType, edns: bool) -> Result<Message, String> {
+    let mut query = Message::query(random_id()?, domain, qtype);
+    if edns {
+        query.set_edns(EDNS_PAYLOAD_SIZE);
+    }
+    Ok(query)
 }
 
-// 构建DNS查询包
-fn build_query(domain: &str, id: u16) -> Vec<u8> {
-    let mut query = Vec::new();
-
-    // 头部
-    query.extend_from_slice(&id.to_be_bytes()); // ID
-    query.extend_from_slice(&[0x01, 0x00]); // 标志：标准查询，递归期望
-    query.extend_from_slice(&[0x00, 0x01]); // 问题数量：1
-    query.extend_from_slice(&[0x00, 0x00]); // 回答数量：0
-    query.extend_from_slice(&[0x00, 0x00]); // 权威回答数量：0
-    query.extend_from_slice(&[0x00, 0x00]); // 附加记录数量：0
+/// 查询发往的上游服务器
+#[derive(Debug, Clone)]
+pub enum Upstream {
+    /// 普通 DNS: 先用 UDP，响应被截断时改用 TCP
+    Plain(SocketAddr),
+    /// DNS-over-HTTPS(RFC 8484)
+    Https(DohServer),
+    /// DNS-over-TLS(RFC 7858)
+    Tls(DotServer),
+}
 
-    // 问题部分 - 域名
-    for part in domain.split('.') {
-        query.push(part.len() as u8);
-        query.extend_from_slice(part.as_bytes());
+impl fmt::Display for Upstream {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Upstream::Plain(addr) => write!(f, "{}", addr),
+            Upstream::Https(server) => write!(f, "{}", server.url),
+            Upstream::Tls(server) => write!(f, "tls://{}", server.addr()),
+        }
     }
-    query.push(0x00); // 域名结束标记
-
-    // 问题部分 - 查询类型和类
-    query.extend_from_slice(&[0x00, 0x01]); // 类型：A记录
-    query.extend_from_slice(&[0x00, 0x01]); // 类：IN (Internet)
+}
 
-    query
+/// 一次查询的结果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Answer {
+    /// 查询到的地址及其 TTL(秒)
+    Records(Vec<(IpAddr, u32)>),
+    /// 域名不存在(NXDOMAIN)或者没有该类型的记录，
+    /// ttl 为 SOA 记录给出的否定缓存时间，没有 SOA 时为 0
+    Negative { nxdomain: bool, ttl: u32 },
 }
 
 // 解析DNS响应
-fn parse_response(response: &[u8], expected_id: u16) -> Result<Vec<Ipv4Addr>, String> {
-    if response.len() < 12 {
-        return Err("响应太短".to_string());
-    }
-
-    // 解析ID
-    let id = u16::from_be_bytes([response[0], response[1]]);
-    if id != expected_id {
-        return Err(format!("ID不匹配：期望 {}, 收到 {}", expected_id, id));
-    }
-
-    // 解析标志
-    let flags = u16::from_be_bytes([response[2], response[3]]);
-    let qr = (flags >> 15) & 0x1; // 响应位
-    let opcode = (flags >> 11) & 0xF; // 操作码
-    let rcode = flags & 0xF; // 响应码
-
-    if qr != 1 {
-        return Err("不是响应包".to_string());
-    }
-
-    if opcode != 0 {
-        return Err(format!("未知操作码: {}", opcode));
-    }
-
-    match rcode {
+fn parse_response(response: &[u8], query: &Message) -> Result<Answer, String> {
+    let message = Message::parse(response).map_err(|e| e.to_string())?;
+    verify_response(&message, query)?;
+    let header = &message.header;
+    match header.rcode {
         0 => {} // 没有错误
         1 => return Err("DNS格式错误".to_string()),
         2 => return Err("DNS服务器失败".to_string()),
-        3 => return Err("域名不存在".to_string()),
+        3 => {} // 域名不存在，从权威部分读取否定缓存时间
         4 => return Err("DNS服务器不支持请求类型".to_string()),
         5 => return Err("DNS查询被拒绝".to_string()),
-        _ => return Err(format!("未知DNS错误码: {}", rcode)),
+        rcode => return Err(format!("未知DNS错误码: {}", rcode)),
     }
 
-    // 获取问题和回答数量
-    let qdcount = u16::from_be_bytes([response[4], response[5]]);
-    let ancount = u16::from_be_bytes([response[6], response[7]]);
-
-    if ancount == 0 {
-        return Err("没有找到任何答案".to_string());
+    if header.rcode == 0
+        && let Some(question) = message.questions.first()
+    {
+        let records = follow_cname(&message.answers, &question.name)?;
+        if !records.is_empty() {
+            return Ok(Answer::Records(records));
+        }
     }
 
-    // 跳过问题部分
-    let mut pos = 12;
-    for _ in 0..qdcount {
-        // 跳过域名
-        while pos < response.len() {
-            let len = response[pos] as usize;
-            if len == 0 {
-                pos += 1;
-                break;
-            }
-
-            // 检查是否是压缩指针
-            if (len & 0xC0) == 0xC0 {
-                pos += 2;
-                break;
-            }
+    // RFC 2308: 否定缓存时间取 SOA 记录的 TTL 和 MINIMUM 字段中较小的一个
+    let negative_ttl = message
+        .authorities
+        .iter()
+        .find_map(|record| match record.data {
+            RData::SOA { minimum, .. } => Some(record.ttl.min(minimum)),
+            _ => None,
+        })
+        .unwrap_or(0);
+    Ok(Answer::Negative {
+        nxdomain: header.rcode == 3,
+        ttl: negative_ttl,
+    })
+}
 
-            pos += len + 1;
-        }
+// 检查响应是否对应查询: ID、QR 标志、操作码和问题部分。
+// 问题部分必须与查询相同，防止伪造或者错配的响应，只有带错误码的响应可以省略问题部分
+fn verify_response(response: &Message, query: &Message) -> Result<(), String> {
+    let header = &response.header;
+    if header.id != query.header.id {
+        return Err(format!(
+            "ID不匹配：期望 {}, 收到 {}",
+            query.header.id, header.id
+        ));
+    }
+    if !header.qr {
+        return Err("不是响应包".to_string());
+    }
+    if header.opcode != 0 {
+        return Err(format!("未知操作码: {}", header.opcode));
+    }
+    if response.questions.is_empty() && header.rcode != 0 {
+        return Ok(());
+    }
+    if !same_question(response, query) {
+        return Err("响应的问题部分与查询不一致".to_string());
+    }
+    Ok(())
+}
 
-        // 跳过类型和类
-        pos += 4;
-    }
-
-    // 解析回答部分
-    let mut ips = Vec::new();
-    for _ in 0..ancount {
-        // 跳过域名（可能是压缩指针）
-        if (response[pos] & 0xC0) == 0xC0 {
-            pos += 2;
-        } else {
-            while pos < response.len() {
-                let len = response[pos] as usize;
-                if len == 0 {
-                    pos += 1;
-                    break;
-                }
-                pos += len + 1;
-            }
+fn same_question(response: &Message, query: &Message) -> bool {
+    match (response.questions.as_slice(), query.questions.as_slice()) {
+        ([answered], [asked]) => {
+            answered.name.eq_ignore_ascii_case(&asked.name)
+                && answered.qtype == asked.qtype
+                && answered.qclass == asked.qclass
         }
+        _ => false,
+    }
+}
 
-        // 读取记录类型
-        if pos + 10 > response.len() {
-            return Err("响应数据不完整".to_string());
+// 从查询的域名开始沿 CNAME 链查找地址记录，只接受链上域名的记录，
+// 地址的 TTL 不超过链上任何一条 CNAME 记录的 TTL
+fn follow_cname(answers: &[Record], qname: &str) -> Result<Vec<(IpAddr, u32)>, String> {
+    let mut name = qname;
+    let mut chain_ttl = u32::MAX;
+    for _ in 0..=MAX_CNAME_CHAIN {
+        let mut owned = answers
+            .iter()
+            .filter(|record| record.name.eq_ignore_ascii_case(name));
+        let records: Vec<(IpAddr, u32)> = owned
+            .clone()
+            .filter_map(|record| match record.data {
+                RData::A(ip) => Some((IpAddr::V4(ip), record.ttl.min(chain_ttl))),
+                RData::AAAA(ip) => Some((IpAddr::V6(ip), record.ttl.min(chain_ttl))),
+                _ => None,
+            })
+            .collect();
+        if !records.is_empty() {
+            return Ok(records);
         }
-
-        let rec_type = u16::from_be_bytes([response[pos], response[pos + 1]]);
-        let data_len = u16::from_be_bytes([response[pos + 8], response[pos + 9]]) as usize;
-
-        pos += 10;
-
-        // 检查是否是A记录
-        if rec_type == 1 && data_len == 4 && pos + 4 <= response.len() {
-            let ip = Ipv4Addr::new(
-                response[pos],
-                response[pos + 1],
-                response[pos + 2],
-                response[pos + 3],
-            );
-            ips.push(ip);
+        match owned.find_map(|record| match &record.data {
+            RData::CNAME(target) => Some((target, record.ttl)),
+            _ => None,
+        }) {
+            Some((target, ttl)) => {
+                debug!("{} 是 {} 的别名", name, target);
+                name = target;
+                chain_ttl = chain_ttl.min(ttl);
+            }
+            None => return Ok(Vec::new()),
         }
-
-        pos += data_len;
-    }
-
-    if ips.is_empty() {
-        Err("未找到IPv4地址".to_string())
-    } else {
-        Ok(ips)
     }
+    Err(format!("CNAME 链过长: {}", qname))
 }
 
-// 获取系统的 DNS 服务器地址
-fn get_system_dns_servers() -> Result<Vec<String>, String> {
-    let mut servers = Vec::new();
-    let file =
-        File::open("/etc/resolv.conf").map_err(|e| format!("无法打开 /etc/resolv.conf: {}", e))?;
-    let reader = BufReader::new(file);
+// 从 hosts 文件中解析域名，文件只读取一次，不存在时视为没有记录
+fn resolve_from_hosts(domain: &str) -> Result<Option<Vec<IpAddr>>, String> {
+    static HOSTS: OnceLock<Result<HashMap<String, Vec<IpAddr>>, String>> = OnceLock::new();
+    let hosts_map = HOSTS
+        .get_or_init(read_hosts)
+        .as_ref()
+        .map_err(Clone::clone)?;
 
-    for line in reader.lines() {
-        let line = line.map_err(|e| format!("读取 /etc/resolv.conf 失败: {}", e))?;
-        if line.starts_with("nameserver") {
-            if let Some(server) = line.split_whitespace().nth(1) {
-                servers.push(server.to_string());
-            }
-        }
-    }
+    // 查找域名(不区分大小写)
+    Ok(hosts_map.get(&domain.to_ascii_lowercase()).cloned())
+}
 
-    if servers.is_empty() {
-        Err("未找到任何 DNS 服务器".to_string())
-    } else {
-        Ok(servers)
-    }
+fn read_hosts() -> Result<HashMap<String, Vec<IpAddr>>, String> {
+    let file = match File::open("/etc/hosts") {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(HashMap::new()),
+        Err(e) => return Err(format!("无法打开 /etc/hosts: {}", e)),
+    };
+    parse_hosts(BufReader::new(file)).map_err(|e| format!("读取 /etc/hosts 失败: {}", e))
 }
 
-// 从 hosts 文件中解析域名
-fn resolve_from_hosts(domain: &str) -> Result<Option<Vec<Ipv4Addr>>, String> {
-    let file = File::open("/etc/hosts").map_err(|e| format!("无法打开 /etc/hosts: {}", e))?;
-    let reader = BufReader::new(file);
-    let mut hosts_map: HashMap<String, Vec<Ipv4Addr>> = HashMap::new();
+// 解析 hosts 文件内容，同时支持 IPv4 和 IPv6 地址
+fn parse_hosts<R: BufRead>(reader: R) -> std::io::Result<HashMap<String, Vec<IpAddr>>> {
+    let mut hosts_map: HashMap<String, Vec<IpAddr>> = HashMap::new();
 
     for line in reader.lines() {
-        let line = line.map_err(|e| format!("读取 /etc/hosts 失败: {}", e))?;
-        let line = line.trim();
-
-        // 跳过注释和空行
-        if line.is_empty() || line.starts_with('#') {
+        let line = line?;
+        // 去掉行内注释
+        let line = line.split('#').next().unwrap_or_default().trim();
+        if line.is_empty() {
             continue;
         }
 
@@ -206,81 +218,338 @@ fn resolve_from_hosts(domain: &str) -> Result<Option<Vec<Ipv4Addr>>, String> {
             continue;
         }
 
-        // 解析 IP 和域名
-        if let Ok(ip) = parts[0].parse::<Ipv4Addr>() {
+        // 解析 IP 和域名，带区域标识的链路本地地址(fe80::1%eth0)无法直接使用，跳过
+        if let Ok(ip) = parts[0].parse::<IpAddr>() {
             for &host in &parts[1..] {
                 hosts_map
-                    .entry(host.to_string())
-                    .or_insert_with(Vec::new)
+                    .entry(host.to_ascii_lowercase())
+                    .or_default()
                     .push(ip);
             }
         }
     }
 
-    // 查找域名
-    Ok(hosts_map.get(domain).cloned())
+    Ok(hosts_map)
 }
 
-pub fn resolve_domain(domain: &str) -> Result<Vec<Ipv4Addr>, String> {
-    // 优先从 hosts 文件中查找
-    // if let Some(ips) = resolve_from_hosts(domain)?
-    //     .unwrap_or_default()
-    //     .into_iter()
-    //     .collect::<Vec<_>>()
-    // {
-    //     return Ok(ips);
-    // }
+/// 使用系统配置查询域名，结果保存在进程内共享的缓存中
+pub fn resolve_domain(domain: &str) -> Result<Vec<IpAddr>, String> {
+    static CACHE: OnceLock<DnsCache> = OnceLock::new();
+    resolve_with(
+        domain,
+        ResolvConf::system(),
+        None,
+        CACHE.get_or_init(DnsCache::default),
+    )
+}
+
+/// 查询域名的地址: 优先从 hosts 文件中查找，否则按 conf 中的搜索域依次查询每个候选域名，
+/// 直到有一个候选域名查询到地址。指定了 upstream 时只向它查询，否则使用 conf 中的服务器
+pub fn resolve_with(
+    domain: &str,
+    conf: &ResolvConf,
+    upstream: Option<&Upstream>,
+    cache: &DnsCache,
+) -> Result<Vec<IpAddr>, String> {
     if let Some(ips) = resolve_from_hosts(domain)? {
         return Ok(ips);
     }
+    // 有候选域名因为网络等原因查询失败时，报告该错误而不是域名不存在
+    let mut failure = None;
+    let mut nxdomain = true;
+    for name in conf.candidates(domain) {
+        match lookup(&name, conf, upstream, cache) {
+            Ok(ips) => return Ok(ips),
+            Err(Lookup::Negative { nxdomain: n }) => nxdomain &= n,
+            Err(Lookup::Failed(e)) => {
+                failure.get_or_insert(e);
+            }
+        }
+    }
+    Err(failure.unwrap_or_else(|| negative_message(nxdomain)))
+}
 
-    // 获取系统的 DNS 服务器
-    let dns_servers = get_system_dns_servers()?;
-    let dns_server = dns_servers.get(0).ok_or("没有可用的 DNS 服务器")?;
+// 一个候选域名查询失败的原因
+enum Lookup {
+    Negative { nxdomain: bool },
+    Failed(String),
+}
 
-    // 创建UDP套接字
-    let socket = match UdpSocket::bind("0.0.0.0:0") {
-        Ok(s) => s,
-        Err(e) => return Err(format!("绑定套接字失败: {}", e)),
+// 查询一个完整域名: 先查缓存，再按顺序询问每个服务器，所有服务器都失败时重试 attempts 轮。
+// A 和 AAAA 记录同时查询，IPv6 地址排在前面
+fn lookup(
+    name: &str,
+    conf: &ResolvConf,
+    upstream: Option<&Upstream>,
+    cache: &DnsCache,
+) -> Result<Vec<IpAddr>, Lookup> {
+    if let Some(cached) = cache.get(name) {
+        debug!("使用缓存的DNS结果: {} {:?}", name, cached);
+        return match cached {
+            Cached::Found(ips) => Ok(ips),
+            Cached::NotFound { nxdomain } => Err(Lookup::Negative { nxdomain }),
+        };
+    }
+    let servers: Vec<Upstream> = match upstream {
+        Some(upstream) => vec![upstream.clone()],
+        None => conf
+            .server_order()
+            .into_iter()
+            .map(Upstream::Plain)
+            .collect(),
     };
+    let mut last_error = "没有可用的 DNS 服务器".to_string();
+    for _ in 0..conf.attempts.max(1) {
+        for server in &servers {
+            let (v6, v4) = thread::scope(|scope| {
+                let v6 = scope.spawn(|| query(name, server, conf.timeout, RecordType::AAAA));
+                let v4 = query(name, server, conf.timeout, RecordType::A);
+                (
+                    v6.join()
+                        .unwrap_or_else(|_| Err("查询线程异常退出".to_string())),
+                    v4,
+                )
+            });
+            match combine(v6, v4) {
+                Ok(Answer::Records(records)) => {
+                    let ttl = records.iter().map(|(_, ttl)| *ttl).min().unwrap_or(0);
+                    let ips: Vec<IpAddr> = records.into_iter().map(|(ip, _)| ip).collect();
+                    cache.insert(name, ips.clone(), ttl);
+                    return Ok(ips);
+                }
+                Ok(Answer::Negative { nxdomain, ttl }) => {
+                    cache.insert_negative(name, nxdomain, ttl);
+                    return Err(Lookup::Negative { nxdomain });
+                }
+                Err(e) => {
+                    debug!("DNS服务器 {} 查询 {} 失败: {}", server, name, e);
+                    last_error = format!("{}: {}", server, e);
+                }
+            }
+        }
+    }
+    Err(Lookup::Failed(last_error))
+}
 
-    // 设置超时
-    if let Err(e) = socket.set_read_timeout(Some(Duration::from_secs(5))) {
-        return Err(format!("设置超时失败: {}", e));
+// 合并同时发出的 AAAA 和 A 查询的结果: 任一查询有地址即为成功；
+// 都没有地址时，只有两个查询都得到了确定的否定结果(或者域名不存在)才可以缓存
+fn combine(v6: Result<Answer, String>, v4: Result<Answer, String>) -> Result<Answer, String> {
+    use Answer::{Negative, Records};
+    match (v6, v4) {
+        (Ok(Records(mut v6)), Ok(Records(v4))) => {
+            v6.extend(v4);
+            Ok(Records(v6))
+        }
+        (Ok(Records(records)), _) | (_, Ok(Records(records))) => Ok(Records(records)),
+        (
+            Ok(Negative {
+                nxdomain: a,
+                ttl: t1,
+            }),
+            Ok(Negative {
+                nxdomain: b,
+                ttl: t2,
+            }),
+        ) => Ok(Negative {
+            nxdomain: a || b,
+            ttl: t1.min(t2),
+        }),
+        (Ok(negative @ Negative { nxdomain: true, .. }), _)
+        | (_, Ok(negative @ Negative { nxdomain: true, .. })) => Ok(negative),
+        (_, Err(e)) | (Err(e), _) => Err(e),
     }
+}
 
-    // 连接到系统的 DNS 服务器
-    if let Err(e) = socket.connect(format!("{}:53", dns_server)) {
-        return Err(format!("连接DNS服务器失败: {}", e));
+fn negative_message(nxdomain: bool) -> String {
+    if nxdomain {
+        "域名不存在".to_string()
+    } else {
+        "未找到IP地址".to_string()
     }
+}
 
-    // 生成随机ID
-    let query_id = random_id();
+// 向一个 DNS 服务器发送查询。不支持 EDNS0 的旧服务器返回 FORMERR 时，
+// 去掉 OPT 记录重新查询(RFC 6891 7)
+fn query(
+    domain: &str,
+    upstream: &Upstream,
+    timeout: Duration,
+    qtype: RecordType,
+) -> Result<Answer, String> {
+    let query = upstream_query(domain, qtype, true, upstream)?;
+    let (response, _) = exchange(&query, upstream, timeout, false)?;
+    if Header::parse(&response).is_ok_and(|header| header.rcode == 1) {
+        debug!("DNS服务器 {} 不支持 EDNS0，重新查询", upstream);
+        let query = upstream_query(domain, qtype, false, upstream)?;
+        let (response, _) = exchange(&query, upstream, timeout, false)?;
+        return parse_response(&response, &query);
+    }
+    parse_response(&response, &query)
+}
+
+// DoH 的查询 ID 固定为 0，使相同的查询可以被 HTTP 缓存(RFC 8484 4.1)，
+// HTTPS 本身可以防止伪造的响应
+fn upstream_query(
+    domain: &str,
+    qtype: RecordType,
+    edns: bool,
+    upstream: &Upstream,
+) -> Result<Message, String> {
+    let mut query = build_query(domain, qtype, edns)?;
+    if let Upstream::Https(_) = upstream {
+        query.header.id = 0;
+    }
+    Ok(query)
+}
+
+/// 一次查询收到的完整响应
+#[derive(Debug, Clone)]
+pub struct Reply {
+    pub message: Message,
+    /// 响应报文的字节数
+    pub size: usize,
+    /// 是否通过 TCP 收到
+    pub tcp: bool,
+}
+
+/// 向 DNS 服务器发送任意查询，返回经过校验的完整响应，响应码不作为错误处理。
+/// 与解析器使用相同的传输方式: 先用 UDP，被截断时改用 TCP；tcp 为 true 时直接使用 TCP
+pub fn send(
+    query: &Message,
+    dns_server: SocketAddr,
+    timeout: Duration,
+    tcp: bool,
+) -> Result<Reply, String> {
+    let (response, tcp) = exchange(query, &Upstream::Plain(dns_server), timeout, tcp)?;
+    let message = Message::parse(&response).map_err(|e| e.to_string())?;
+    verify_response(&message, query)?;
+    Ok(Reply {
+        message,
+        size: response.len(),
+        tcp,
+    })
+}
+
+// 向上游服务器发送查询，返回响应和是否通过面向连接的传输收到。
+// 普通 DNS 先通过 UDP 查询，响应被截断(TC)时改用 TCP 重新查询
+fn exchange(
+    query: &Message,
+    upstream: &Upstream,
+    timeout: Duration,
+    tcp: bool,
+) -> Result<(Vec<u8>, bool), String> {
+    let bytes = query.to_bytes().map_err(|e| e.to_string())?;
+    let dns_server = match upstream {
+        Upstream::Plain(addr) => *addr,
+        Upstream::Https(server) => return Ok((server.exchange(&bytes, timeout)?, true)),
+        Upstream::Tls(server) => return Ok((server.exchange(&bytes, timeout)?, true)),
+    };
+    if !tcp && let Some(response) = query_udp(&bytes, query.header.id, dns_server, timeout)? {
+        return Ok((response, false));
+    }
+    if !tcp {
+        debug!("DNS服务器 {} 的响应被截断，使用 TCP 重新查询", dns_server);
+    }
+    Ok((query_tcp(&bytes, dns_server, timeout)?, true))
+}
+
+// 通过 UDP 查询，返回 None 表示响应被截断。
+// ID 不匹配的报文(过期或者伪造的响应)被忽略，继续等待到超时
+fn query_udp(
+    query: &[u8],
+    id: u16,
+    dns_server: SocketAddr,
+    timeout: Duration,
+) -> Result<Option<Vec<u8>>, String> {
+    // 创建UDP套接字
+    let bind_addr = if dns_server.is_ipv6() {
+        "[::]:0"
+    } else {
+        "0.0.0.0:0"
+    };
+    let socket = match UdpSocket::bind(bind_addr) {
+        Ok(s) => s,
+        Err(e) => return Err(format!("绑定套接字失败: {}", e)),
+    };
 
-    // 构建DNS查询
-    let query = build_query(domain, query_id);
+    // 连接到 DNS 服务器，只接收该服务器发来的报文
+    if let Err(e) = socket.connect(dns_server) {
+        return Err(format!("连接DNS服务器失败: {}", e));
+    }
 
     // 发送查询
-    if let Err(e) = socket.send(&query) {
+    if let Err(e) = socket.send(query) {
         return Err(format!("发送查询失败: {}", e));
     }
 
-    // 接收响应
-    let mut buf = [0u8; 512];
-    let size = match socket.recv(&mut buf) {
-        Ok(n) => n,
-        Err(e) => return Err(format!("接收响应失败: {}", e)),
-    };
+    // 接收响应，服务器可能不遵守声明的大小，使用 UDP 报文的最大长度
+    let deadline = Instant::now() + timeout;
+    let mut buf = vec![0u8; u16::MAX as usize];
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return Err("接收响应超时".to_string());
+        }
+        if let Err(e) = socket.set_read_timeout(Some(remaining)) {
+            return Err(format!("设置超时失败: {}", e));
+        }
+        let size = match socket.recv(&mut buf) {
+            Ok(n) => n,
+            Err(e) => return Err(format!("接收响应失败: {}", e)),
+        };
+        match Header::parse(&buf[..size]) {
+            Ok(header) if header.id == id => {
+                return Ok((!header.tc).then(|| buf[..size].to_vec()));
+            }
+            _ => debug!("忽略ID不匹配的DNS响应"),
+        }
+    }
+}
 
-    // 解析响应
-    parse_response(&buf[0..size], query_id)
+// 通过 TCP 查询，报文前加两个字节的长度(RFC 1035 4.2.2)
+fn query_tcp(query: &[u8], dns_server: SocketAddr, timeout: Duration) -> Result<Vec<u8>, String> {
+    let mut stream = TcpStream::connect_timeout(&dns_server, timeout)
+        .map_err(|e| format!("连接DNS服务器失败: {}", e))?;
+    if let Err(e) = stream
+        .set_read_timeout(Some(timeout))
+        .and_then(|_| stream.set_write_timeout(Some(timeout)))
+    {
+        return Err(format!("设置超时失败: {}", e));
+    }
+
+    exchange_framed(&mut stream, query)
+}
+
+// 在面向连接的传输上收发一个报文，报文前加两个字节的长度，DoT 使用相同的格式(RFC 7858 3.3)
+fn exchange_framed<S: Read + Write + ?Sized>(
+    stream: &mut S,
+    query: &[u8],
+) -> Result<Vec<u8>, String> {
+    let len = u16::try_from(query.len()).map_err(|_| "查询报文过大".to_string())?;
+    let mut request = len.to_be_bytes().to_vec();
+    request.extend_from_slice(query);
+    stream
+        .write_all(&request)
+        .map_err(|e| format!("发送查询失败: {}", e))?;
+
+    let mut len = [0u8; 2];
+    stream
+        .read_exact(&mut len)
+        .map_err(|e| format!("接收响应失败: {}", e))?;
+    let mut response = vec![0u8; u16::from_be_bytes(len) as usize];
+    stream
+        .read_exact(&mut response)
+        .map_err(|e| format!("接收响应失败: {}", e))?;
+    Ok(response)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::net::Ipv4Addr;
 
     #[test]
+    #[ignore = "需要访问外网"]
     fn test_reslove_domain() {
         println!("baidu.com:{:#?}", resolve_domain("baidu.com").unwrap());
         println!(
@@ -294,4 +563,291 @@ mod tests {
         );
         println!("mylinux.org: {:#?}", resolve_domain("mylinux.org").unwrap());
     }
+
+    #[test]
+    fn test_parse_hosts() {
+        let hosts = "127.0.0.1 localhost\n\
+                     ::1 localhost ip6-localhost # loopback\n\
+                     # 192.0.2.1 commented.example\n\
+                     2001:db8::10 Dual.Example\n\
+                     192.0.2.10 dual.example\n";
+        let map = parse_hosts(hosts.as_bytes()).unwrap();
+        assert_eq!(
+            map["localhost"],
+            [
+                "127.0.0.1".parse::<IpAddr>().unwrap(),
+                "::1".parse().unwrap()
+            ]
+        );
+        assert_eq!(map["ip6-localhost"], ["::1".parse::<IpAddr>().unwrap()]);
+        assert_eq!(
+            map["dual.example"],
+            [
+                "2001:db8::10".parse::<IpAddr>().unwrap(),
+                "192.0.2.10".parse().unwrap()
+            ]
+        );
+        assert!(!map.contains_key("commented.example"));
+    }
+
+    // 查询对应的响应，附加部分的 OPT 记录不带回
+    fn reply_to(query: &Message, rcode: u8) -> Message {
+        let mut reply = query.clone();
+        reply.header.qr = true;
+        reply.header.ra = true;
+        reply.header.rcode = rcode;
+        reply.additionals.clear();
+        reply
+    }
+
+    #[test]
+    fn test_parse_aaaa_response() {
+        let query = build_query("example.com", RecordType::AAAA, true).unwrap();
+        assert_eq!(query.edns_payload_size(), Some(EDNS_PAYLOAD_SIZE));
+        let mut response = reply_to(&query, 0);
+        response.answers = vec![
+            // 指向自身的 CNAME 记录不影响同名的地址记录
+            Record::new("example.com", 60, RData::CNAME("example.com".to_string())),
+            Record::new(
+                "example.com",
+                60,
+                RData::AAAA("2001:db8::1".parse().unwrap()),
+            ),
+        ];
+        let bytes = response.to_bytes().unwrap();
+        assert_eq!(
+            parse_response(&bytes, &query),
+            Ok(Answer::Records(vec![("2001:db8::1".parse().unwrap(), 60)]))
+        );
+        let other = build_query("example.com", RecordType::AAAA, true).unwrap();
+        if other.header.id != query.header.id {
+            assert!(parse_response(&bytes, &other).is_err());
+        }
+
+        // 问题部分与查询不一致的响应被拒绝
+        for (name, qtype) in [
+            ("example.org", RecordType::AAAA),
+            ("example.com", RecordType::A),
+        ] {
+            let mut forged = response.clone();
+            forged.questions[0].name = name.to_string();
+            forged.questions[0].qtype = qtype;
+            assert_eq!(
+                parse_response(&forged.to_bytes().unwrap(), &query),
+                Err("响应的问题部分与查询不一致".to_string())
+            );
+        }
+        // 问题部分的大小写可以不同(DNS 0x20)
+        response.questions[0].name = "ExAmPle.COM".to_string();
+        assert!(parse_response(&response.to_bytes().unwrap(), &query).is_ok());
+    }
+
+    #[test]
+    fn test_negative_answer() {
+        let query = Message::query(7, "missing.example", RecordType::A);
+        // NXDOMAIN，权威部分一条 SOA 记录
+        let mut response = reply_to(&query, 3);
+        response.authorities = vec![Record::new(
+            "example",
+            3600,
+            RData::SOA {
+                mname: "ns.example".to_string(),
+                rname: "hostmaster.example".to_string(),
+                serial: 1,
+                refresh: 7200,
+                retry: 900,
+                expire: 1209600,
+                minimum: 300,
+            },
+        )];
+        assert_eq!(
+            parse_response(&response.to_bytes().unwrap(), &query),
+            Ok(Answer::Negative {
+                nxdomain: true,
+                ttl: 300
+            })
+        );
+
+        let v4 = Ok(Answer::Records(vec![("192.0.2.1".parse().unwrap(), 30)]));
+        let nodata = Ok(Answer::Negative {
+            nxdomain: false,
+            ttl: 60,
+        });
+        assert_eq!(combine(nodata.clone(), v4.clone()), v4);
+        assert_eq!(
+            combine(nodata.clone(), Err("超时".to_string())),
+            Err("超时".to_string())
+        );
+        assert_eq!(combine(nodata.clone(), nodata.clone()), nodata);
+    }
+
+    #[test]
+    fn test_follow_cname() {
+        let cname = |target: &str| RData::CNAME(target.to_string());
+        let query = Message::query(9, "www.example.com", RecordType::A);
+        let mut response = reply_to(&query, 0);
+        response.answers = vec![
+            Record::new("www.example.com", 600, cname("cdn.example.net")),
+            // 与链无关的地址记录被忽略
+            Record::new(
+                "other.example",
+                60,
+                RData::A(Ipv4Addr::new(198, 51, 100, 1)),
+            ),
+            Record::new("cdn.example.net", 120, cname("edge.example.net")),
+            Record::new(
+                "EDGE.example.net",
+                300,
+                RData::A(Ipv4Addr::new(192, 0, 2, 9)),
+            ),
+        ];
+        assert_eq!(
+            parse_response(&response.to_bytes().unwrap(), &query),
+            Ok(Answer::Records(vec![("192.0.2.9".parse().unwrap(), 120)]))
+        );
+
+        // 链断开时没有地址
+        response.answers.truncate(2);
+        assert_eq!(
+            parse_response(&response.to_bytes().unwrap(), &query),
+            Ok(Answer::Negative {
+                nxdomain: false,
+                ttl: 0
+            })
+        );
+
+        // CNAME 循环
+        response.answers = vec![
+            Record::new("www.example.com", 60, cname("a.example.com")),
+            Record::new("a.example.com", 60, cname("www.example.com")),
+        ];
+        assert!(parse_response(&response.to_bytes().unwrap(), &query).is_err());
+    }
+
+    // 回答 n 个查询: name 的 A 查询返回 ip，其他域名返回 NXDOMAIN
+    fn fake_server(name: &'static str, ip: Ipv4Addr, n: usize) -> SocketAddr {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let addr = socket.local_addr().unwrap();
+        thread::spawn(move || {
+            for _ in 0..n {
+                let mut buf = [0u8; 512];
+                let (len, peer) = socket.recv_from(&mut buf).unwrap();
+                let query = Message::parse(&buf[..len]).unwrap();
+                let question = &query.questions[0];
+                let reply = if question.name != name {
+                    reply_to(&query, 3)
+                } else {
+                    let mut reply = reply_to(&query, 0);
+                    if question.qtype == RecordType::A {
+                        reply.answers = vec![Record::new(name, 60, RData::A(ip))];
+                    }
+                    reply
+                };
+                socket.send_to(&reply.to_bytes().unwrap(), peer).unwrap();
+            }
+        });
+        addr
+    }
+
+    #[test]
+    fn test_search_and_failover() {
+        // 绑定后立即关闭，发往该端口的查询会失败
+        let dead = UdpSocket::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let server = fake_server("intranet.corp.example", Ipv4Addr::new(192, 0, 2, 8), 6);
+        let conf = ResolvConf {
+            nameservers: vec![dead, server],
+            search: vec!["corp.example".to_string()],
+            timeout: Duration::from_secs(1),
+            attempts: 1,
+            ..Default::default()
+        };
+        let cache = DnsCache::default();
+        assert_eq!(
+            resolve_with("rcurl-intranet-test", &conf, None, &cache),
+            Err("域名不存在".to_string())
+        );
+        assert_eq!(
+            resolve_with("intranet", &conf, None, &cache),
+            Ok(vec!["192.0.2.8".parse().unwrap()])
+        );
+        assert!(cache.get("intranet.corp.example").is_some());
+    }
+
+    #[test]
+    fn test_edns_and_tcp_fallback() {
+        use std::net::TcpListener;
+        // UDP 和 TCP 使用同一个端口
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let addr = socket.local_addr().unwrap();
+        let listener = TcpListener::bind(addr).unwrap();
+        let ips: Vec<Ipv4Addr> = (1..=200).map(|i| Ipv4Addr::new(10, 0, 0, i)).collect();
+        let expected = ips.clone();
+        thread::spawn(move || {
+            let mut buf = [0u8; 512];
+            // 带 EDNS0 的查询: 先发送一个 ID 错误的伪造响应，再返回 FORMERR
+            let (len, peer) = socket.recv_from(&mut buf).unwrap();
+            let query = Message::parse(&buf[..len]).unwrap();
+            assert_eq!(query.edns_payload_size(), Some(EDNS_PAYLOAD_SIZE));
+            let mut forged = reply_to(&query, 0);
+            forged.header.id = forged.header.id.wrapping_add(1);
+            forged.answers = vec![Record::new(
+                "big.example",
+                60,
+                RData::A(Ipv4Addr::LOCALHOST),
+            )];
+            socket.send_to(&forged.to_bytes().unwrap(), peer).unwrap();
+            socket
+                .send_to(&reply_to(&query, 1).to_bytes().unwrap(), peer)
+                .unwrap();
+
+            // 不带 EDNS0 的查询: 地址太多，返回截断的响应
+            let (len, peer) = socket.recv_from(&mut buf).unwrap();
+            let query = Message::parse(&buf[..len]).unwrap();
+            assert_eq!(query.edns_payload_size(), None);
+            let mut truncated = reply_to(&query, 0);
+            truncated.header.tc = true;
+            socket
+                .send_to(&truncated.to_bytes().unwrap(), peer)
+                .unwrap();
+
+            // 通过 TCP 返回完整的响应
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut len = [0u8; 2];
+            stream.read_exact(&mut len).unwrap();
+            let mut request = vec![0u8; u16::from_be_bytes(len) as usize];
+            stream.read_exact(&mut request).unwrap();
+            let query = Message::parse(&request).unwrap();
+            let mut reply = reply_to(&query, 0);
+            reply.answers = ips
+                .into_iter()
+                .map(|ip| Record::new("big.example", 60, RData::A(ip)))
+                .collect();
+            let reply = reply.to_bytes().unwrap();
+            assert!(reply.len() > EDNS_PAYLOAD_SIZE as usize);
+            stream
+                .write_all(&(reply.len() as u16).to_be_bytes())
+                .unwrap();
+            stream.write_all(&reply).unwrap();
+        });
+
+        let upstream = Upstream::Plain(addr);
+        let answer = query(
+            "big.example",
+            &upstream,
+            Duration::from_secs(2),
+            RecordType::A,
+        )
+        .unwrap();
+        let Answer::Records(records) = answer else {
+            panic!("没有查询到地址: {:?}", answer);
+        };
+        let ips: Vec<IpAddr> = records.into_iter().map(|(ip, _)| ip).collect();
+        assert_eq!(
+            ips,
+            expected.into_iter().map(IpAddr::V4).collect::<Vec<_>>()
+        );
+    }
 }
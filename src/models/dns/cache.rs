@@ -0,0 +1,274 @@
+//! 按 TTL 缓存 DNS 查询结果，可以保存到文件中在多次运行之间复用
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::net::IpAddr;
+use std::path::Path;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// 默认最多缓存的域名数量
+pub const DEFAULT_MAX_ENTRIES: usize = 1024;
+// 记录的 TTL 上限，避免错误配置的超长 TTL 让过期地址一直留在缓存中
+const MAX_TTL: u32 = 24 * 3600;
+// RFC 2308 建议否定缓存不超过几个小时
+const MAX_NEGATIVE_TTL: u32 = 3 * 3600;
+
+/// 缓存的查询结果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Cached {
+    Found(Vec<IpAddr>),
+    /// 否定缓存: 域名不存在或者没有地址记录
+    NotFound {
+        nxdomain: bool,
+    },
+}
+
+#[derive(Debug, Clone)]
+struct Entry {
+    value: Cached,
+    expires: SystemTime,
+}
+
+/// 线程安全的 DNS 缓存，域名不区分大小写，超过 max_entries 时淘汰最早过期的记录
+#[derive(Debug)]
+pub struct DnsCache {
+    entries: Mutex<HashMap<String, Entry>>,
+    max_entries: usize,
+}
+
+impl DnsCache {
+    pub fn new(max_entries: usize) -> Self {
+        DnsCache {
+            entries: Mutex::new(HashMap::new()),
+            max_entries,
+        }
+    }
+
+    /// 查找未过期的记录
+    pub fn get(&self, name: &str) -> Option<Cached> {
+        self.get_at(name, SystemTime::now())
+    }
+
+    /// 缓存查询到的地址，ttl 为 0 时不缓存
+    pub fn insert(&self, name: &str, ips: Vec<IpAddr>, ttl: u32) {
+        self.put_at(
+            name,
+            Cached::Found(ips),
+            ttl.min(MAX_TTL),
+            SystemTime::now(),
+        );
+    }
+
+    /// 缓存域名不存在或者没有地址的结果，ttl 为 0 时不缓存
+    pub fn insert_negative(&self, name: &str, nxdomain: bool, ttl: u32) {
+        self.put_at(
+            name,
+            Cached::NotFound { nxdomain },
+            ttl.min(MAX_NEGATIVE_TTL),
+            SystemTime::now(),
+        );
+    }
+
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    /// 从文件中读取缓存，已经过期的记录被忽略
+    pub fn load(&self, path: &Path) -> io::Result<()> {
+        self.read_from(BufReader::new(File::open(path)?))
+    }
+
+    /// 将未过期的记录写入文件
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write_to(&mut writer)?;
+        writer.flush()
+    }
+
+    // 每行一条记录: 域名 过期时间(UNIX 秒) 地址列表|NXDOMAIN|NODATA
+    fn read_from<R: BufRead>(&self, reader: R) -> io::Result<()> {
+        let now = SystemTime::now();
+        let mut entries = self.lock();
+        for line in reader.lines() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut fields = line.split_whitespace();
+            let (Some(name), Some(expires), Some(value)) =
+                (fields.next(), fields.next(), fields.next())
+            else {
+                continue;
+            };
+            let Ok(expires) = expires.parse::<u64>() else {
+                continue;
+            };
+            let expires = UNIX_EPOCH + Duration::from_secs(expires);
+            let value = match value {
+                "NXDOMAIN" => Cached::NotFound { nxdomain: true },
+                "NODATA" => Cached::NotFound { nxdomain: false },
+                ips => match ips.split(',').map(str::parse).collect() {
+                    Ok(ips) => Cached::Found(ips),
+                    Err(_) => continue,
+                },
+            };
+            if expires > now && entries.len() < self.max_entries {
+                entries.insert(name.to_ascii_lowercase(), Entry { value, expires });
+            }
+        }
+        Ok(())
+    }
+
+    fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        let now = SystemTime::now();
+        writeln!(writer, "# rcurl DNS cache")?;
+        let entries = self.lock();
+        let mut names: Vec<&String> = entries.keys().collect();
+        names.sort();
+        for name in names {
+            let entry = &entries[name];
+            if entry.expires <= now {
+                continue;
+            }
+            let expires = entry
+                .expires
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs();
+            let value = match &entry.value {
+                Cached::Found(ips) => ips
+                    .iter()
+                    .map(|ip| ip.to_string())
+                    .collect::<Vec<_>>()
+                    .join(","),
+                Cached::NotFound { nxdomain: true } => "NXDOMAIN".to_string(),
+                Cached::NotFound { nxdomain: false } => "NODATA".to_string(),
+            };
+            writeln!(writer, "{} {} {}", name, expires, value)?;
+        }
+        Ok(())
+    }
+
+    fn get_at(&self, name: &str, now: SystemTime) -> Option<Cached> {
+        let name = name.to_ascii_lowercase();
+        let mut entries = self.lock();
+        match entries.get(&name) {
+            Some(entry) if entry.expires > now => Some(entry.value.clone()),
+            Some(_) => {
+                entries.remove(&name);
+                None
+            }
+            None => None,
+        }
+    }
+
+    fn put_at(&self, name: &str, value: Cached, ttl: u32, now: SystemTime) {
+        if ttl == 0 || self.max_entries == 0 {
+            return;
+        }
+        let name = name.to_ascii_lowercase();
+        let mut entries = self.lock();
+        if !entries.contains_key(&name) && entries.len() >= self.max_entries {
+            entries.retain(|_, entry| entry.expires > now);
+            if entries.len() >= self.max_entries
+                && let Some(oldest) = entries
+                    .iter()
+                    .min_by_key(|(_, entry)| entry.expires)
+                    .map(|(name, _)| name.clone())
+            {
+                entries.remove(&oldest);
+            }
+        }
+        let expires = now + Duration::from_secs(ttl as u64);
+        entries.insert(name, Entry { value, expires });
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<String, Entry>> {
+        self.entries.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Default for DnsCache {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_ENTRIES)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn ip(value: &str) -> IpAddr {
+        value.parse().unwrap()
+    }
+
+    #[test]
+    fn test_ttl_and_eviction() {
+        let cache = DnsCache::new(2);
+        let now = SystemTime::now();
+        cache.put_at("A.example", Cached::Found(vec![ip("192.0.2.1")]), 60, now);
+        cache.put_at("b.example", Cached::NotFound { nxdomain: true }, 10, now);
+        assert_eq!(
+            cache.get_at("a.EXAMPLE", now),
+            Some(Cached::Found(vec![ip("192.0.2.1")]))
+        );
+        assert_eq!(
+            cache.get_at("b.example", now + Duration::from_secs(5)),
+            Some(Cached::NotFound { nxdomain: true })
+        );
+        // 过期后被删除
+        assert_eq!(
+            cache.get_at("b.example", now + Duration::from_secs(10)),
+            None
+        );
+        assert_eq!(cache.len(), 1);
+
+        // 超过上限时淘汰最早过期的记录
+        cache.put_at("c.example", Cached::Found(vec![ip("::1")]), 30, now);
+        cache.put_at("d.example", Cached::Found(vec![ip("::2")]), 90, now);
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.get_at("c.example", now), None);
+        assert!(cache.get_at("a.example", now).is_some());
+
+        // TTL 为 0 的记录不缓存
+        cache.insert("e.example", vec![ip("192.0.2.5")], 0);
+        assert_eq!(cache.get("e.example"), None);
+    }
+
+    #[test]
+    fn test_read_write() {
+        let cache = DnsCache::default();
+        cache.insert(
+            "dual.example",
+            vec![ip("2001:db8::1"), ip("192.0.2.1")],
+            300,
+        );
+        cache.insert_negative("missing.example", true, 60);
+        cache.insert_negative("v4only.example", false, 60);
+        let mut out = Vec::new();
+        cache.write_to(&mut out).unwrap();
+
+        let text = String::from_utf8(out.clone()).unwrap();
+        assert!(text.contains("dual.example "));
+        assert!(text.contains(" 2001:db8::1,192.0.2.1\n"));
+        assert!(text.contains(" NXDOMAIN\n"));
+
+        let loaded = DnsCache::default();
+        loaded
+            .read_from(&b"expired.example 1 192.0.2.9\nbroken line\n"[..])
+            .unwrap();
+        loaded.read_from(&out[..]).unwrap();
+        assert_eq!(loaded.len(), 3);
+        assert_eq!(
+            loaded.get("dual.example"),
+            Some(Cached::Found(vec![ip("2001:db8::1"), ip("192.0.2.1")]))
+        );
+        assert_eq!(
+            loaded.get("v4only.example"),
+            Some(Cached::NotFound { nxdomain: false })
+        );
+        assert_eq!(loaded.get("expired.example"), None);
+    }
+}
@@ -0,0 +1,1132 @@
+//! DNS 报文(RFC 1035)的编码和解码，支持域名压缩
+use std::collections::HashMap;
+use std::fmt;
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::str::FromStr;
+use thiserror::Error;
+
+/// IN 类
+pub const CLASS_IN: u16 = 1;
+// 域名编码后的最大长度和单个标签的最大长度
+const MAX_NAME_LEN: usize = 255;
+const MAX_LABEL_LEN: usize = 63;
+// 解压一个域名时最多跟随的压缩指针数量
+const MAX_POINTERS: usize = 64;
+
+/// 报文编解码错误
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum MessageError {
+    #[error("报文数据不完整")]
+    Truncated,
+    #[error("无效的域名压缩指针: {0}")]
+    InvalidPointer(usize),
+    #[error("域名压缩指针形成循环")]
+    PointerLoop,
+    #[error("不支持的标签类型: {0:#04x}")]
+    InvalidLabel(u8),
+    #[error("域名过长: {0}")]
+    NameTooLong(String),
+    #[error("无效的域名: {0}")]
+    InvalidName(String),
+    #[error("{0} 记录的数据格式不正确")]
+    InvalidRdata(RecordType),
+    #[error("报文过大")]
+    TooLarge,
+}
+
+type Result<T> = std::result::Result<T, MessageError>;
+
+/// 记录类型，名称与 RFC 中的助记符相同
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RecordType {
+    A,
+    NS,
+    CNAME,
+    SOA,
+    PTR,
+    MX,
+    TXT,
+    AAAA,
+    SRV,
+    /// EDNS0 伪记录(RFC 6891)，只出现在附加部分
+    OPT,
+    CAA,
+    Other(u16),
+}
+
+impl From<u16> for RecordType {
+    fn from(value: u16) -> Self {
+        match value {
+            1 => RecordType::A,
+            2 => RecordType::NS,
+            5 => RecordType::CNAME,
+            6 => RecordType::SOA,
+            12 => RecordType::PTR,
+            15 => RecordType::MX,
+            16 => RecordType::TXT,
+            28 => RecordType::AAAA,
+            33 => RecordType::SRV,
+            41 => RecordType::OPT,
+            257 => RecordType::CAA,
+            other => RecordType::Other(other),
+        }
+    }
+}
+
+impl From<RecordType> for u16 {
+    fn from(value: RecordType) -> Self {
+        match value {
+            RecordType::A => 1,
+            RecordType::NS => 2,
+            RecordType::CNAME => 5,
+            RecordType::SOA => 6,
+            RecordType::PTR => 12,
+            RecordType::MX => 15,
+            RecordType::TXT => 16,
+            RecordType::AAAA => 28,
+            RecordType::SRV => 33,
+            RecordType::OPT => 41,
+            RecordType::CAA => 257,
+            RecordType::Other(other) => other,
+        }
+    }
+}
+
+impl fmt::Display for RecordType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RecordType::Other(value) => write!(f, "TYPE{}", value),
+            other => write!(f, "{:?}", other),
+        }
+    }
+}
+
+impl FromStr for RecordType {
+    type Err = String;
+
+    /// 记录类型名称(不区分大小写)，或者 RFC 3597 的 TYPE数字 形式
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let upper = s.to_ascii_uppercase();
+        if let Some(value) = upper.strip_prefix("TYPE")
+            && let Ok(value) = value.parse::<u16>()
+        {
+            return Ok(RecordType::from(value));
+        }
+        let types = [
+            RecordType::A,
+            RecordType::NS,
+            RecordType::CNAME,
+            RecordType::SOA,
+            RecordType::PTR,
+            RecordType::MX,
+            RecordType::TXT,
+            RecordType::AAAA,
+            RecordType::SRV,
+            RecordType::OPT,
+            RecordType::CAA,
+        ];
+        types
+            .into_iter()
+            .find(|t| t.to_string() == upper)
+            .ok_or_else(|| format!("未知的记录类型: {}", s))
+    }
+}
+
+/// 报文头部
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Header {
+    pub id: u16,
+    /// 是否是响应
+    pub qr: bool,
+    pub opcode: u8,
+    /// 权威回答
+    pub aa: bool,
+    /// 报文被截断
+    pub tc: bool,
+    /// 期望递归查询
+    pub rd: bool,
+    /// 服务器支持递归查询
+    pub ra: bool,
+    /// DNSSEC 验证通过
+    pub ad: bool,
+    /// 不进行 DNSSEC 验证
+    pub cd: bool,
+    pub rcode: u8,
+}
+
+impl Header {
+    /// 响应码的名称，例如 NOERROR、NXDOMAIN
+    pub fn status(&self) -> String {
+        let name = match self.rcode {
+            0 => "NOERROR",
+            1 => "FORMERR",
+            2 => "SERVFAIL",
+            3 => "NXDOMAIN",
+            4 => "NOTIMP",
+            5 => "REFUSED",
+            other => return format!("RCODE{}", other),
+        };
+        name.to_string()
+    }
+
+    /// 已设置的标志位名称，顺序与 dig 相同
+    pub fn flag_names(&self) -> Vec<&'static str> {
+        [
+            (self.qr, "qr"),
+            (self.aa, "aa"),
+            (self.tc, "tc"),
+            (self.rd, "rd"),
+            (self.ra, "ra"),
+            (self.ad, "ad"),
+            (self.cd, "cd"),
+        ]
+        .into_iter()
+        .filter_map(|(set, name)| set.then_some(name))
+        .collect()
+    }
+
+    /// 只解码头部，报文其余部分不完整(例如被截断)时也可以读取标志
+    pub fn parse(data: &[u8]) -> Result<Header> {
+        let mut reader = Reader { buf: data, pos: 0 };
+        let id = reader.u16()?;
+        Ok(Header::from_flags(id, reader.u16()?))
+    }
+
+    fn flags(&self) -> u16 {
+        (self.qr as u16) << 15
+            | ((self.opcode & 0x0F) as u16) << 11
+            | (self.aa as u16) << 10
+            | (self.tc as u16) << 9
+            | (self.rd as u16) << 8
+            | (self.ra as u16) << 7
+            | (self.ad as u16) << 5
+            | (self.cd as u16) << 4
+            | (self.rcode & 0x0F) as u16
+    }
+
+    fn from_flags(id: u16, flags: u16) -> Header {
+        Header {
+            id,
+            qr: flags & 0x8000 != 0,
+            opcode: ((flags >> 11) & 0x0F) as u8,
+            aa: flags & 0x0400 != 0,
+            tc: flags & 0x0200 != 0,
+            rd: flags & 0x0100 != 0,
+            ra: flags & 0x0080 != 0,
+            ad: flags & 0x0020 != 0,
+            cd: flags & 0x0010 != 0,
+            rcode: (flags & 0x0F) as u8,
+        }
+    }
+}
+
+/// 问题部分的一项
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Question {
+    /// 不带结尾 '.' 的域名，根域为空字符串
+    pub name: String,
+    pub qtype: RecordType,
+    pub qclass: u16,
+}
+
+/// 资源记录
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Record {
+    pub name: String,
+    pub class: u16,
+    pub ttl: u32,
+    pub data: RData,
+}
+
+impl Record {
+    /// IN 类的记录
+    #[cfg(test)]
+    pub fn new(name: &str, ttl: u32, data: RData) -> Record {
+        Record {
+            name: name.to_string(),
+            class: CLASS_IN,
+            ttl,
+            data,
+        }
+    }
+
+    pub fn rtype(&self) -> RecordType {
+        self.data.rtype()
+    }
+}
+
+/// 与 dig 相同的区域文件格式: 域名 TTL 类 类型 数据
+impl fmt::Display for Record {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}\t{}\t{}\t{}\t{}",
+            fqdn(&self.name),
+            self.ttl,
+            class_name(self.class),
+            self.rtype(),
+            self.data
+        )
+    }
+}
+
+/// 按类型解析后的记录数据
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RData {
+    A(Ipv4Addr),
+    AAAA(Ipv6Addr),
+    CNAME(String),
+    NS(String),
+    PTR(String),
+    MX {
+        preference: u16,
+        exchange: String,
+    },
+    TXT(Vec<Vec<u8>>),
+    SRV {
+        priority: u16,
+        weight: u16,
+        port: u16,
+        target: String,
+    },
+    SOA {
+        mname: String,
+        rname: String,
+        serial: u32,
+        refresh: u32,
+        retry: u32,
+        expire: u32,
+        minimum: u32,
+    },
+    /// EDNS0 选项: 选项代码和数据
+    OPT(Vec<(u16, Vec<u8>)>),
+    CAA {
+        flags: u8,
+        tag: String,
+        value: Vec<u8>,
+    },
+    /// 不认识的类型保留原始数据
+    Unknown {
+        rtype: u16,
+        data: Vec<u8>,
+    },
+}
+
+impl RData {
+    pub fn rtype(&self) -> RecordType {
+        match self {
+            RData::A(_) => RecordType::A,
+            RData::AAAA(_) => RecordType::AAAA,
+            RData::CNAME(_) => RecordType::CNAME,
+            RData::NS(_) => RecordType::NS,
+            RData::PTR(_) => RecordType::PTR,
+            RData::MX { .. } => RecordType::MX,
+            RData::TXT(_) => RecordType::TXT,
+            RData::SRV { .. } => RecordType::SRV,
+            RData::SOA { .. } => RecordType::SOA,
+            RData::OPT(_) => RecordType::OPT,
+            RData::CAA { .. } => RecordType::CAA,
+            RData::Unknown { rtype, .. } => RecordType::from(*rtype),
+        }
+    }
+
+    fn decode(rtype: RecordType, reader: &mut Reader, len: usize) -> Result<RData> {
+        let end = reader.pos + len;
+        if end > reader.buf.len() {
+            return Err(MessageError::Truncated);
+        }
+        let data = match rtype {
+            RecordType::A => RData::A(Ipv4Addr::from(reader.array::<4>()?)),
+            RecordType::AAAA => RData::AAAA(Ipv6Addr::from(reader.array::<16>()?)),
+            RecordType::CNAME => RData::CNAME(reader.name()?),
+            RecordType::NS => RData::NS(reader.name()?),
+            RecordType::PTR => RData::PTR(reader.name()?),
+            RecordType::MX => RData::MX {
+                preference: reader.u16()?,
+                exchange: reader.name()?,
+            },
+            RecordType::TXT => {
+                let mut strings = Vec::new();
+                while reader.pos < end {
+                    let len = reader.u8()? as usize;
+                    strings.push(reader.bytes(len)?.to_vec());
+                }
+                RData::TXT(strings)
+            }
+            RecordType::SRV => RData::SRV {
+                priority: reader.u16()?,
+                weight: reader.u16()?,
+                port: reader.u16()?,
+                target: reader.name()?,
+            },
+            RecordType::SOA => RData::SOA {
+                mname: reader.name()?,
+                rname: reader.name()?,
+                serial: reader.u32()?,
+                refresh: reader.u32()?,
+                retry: reader.u32()?,
+                expire: reader.u32()?,
+                minimum: reader.u32()?,
+            },
+            RecordType::OPT => {
+                let mut options = Vec::new();
+                while reader.pos < end {
+                    let code = reader.u16()?;
+                    let len = reader.u16()? as usize;
+                    options.push((code, reader.bytes(len)?.to_vec()));
+                }
+                RData::OPT(options)
+            }
+            RecordType::CAA => {
+                let flags = reader.u8()?;
+                let tag_len = reader.u8()? as usize;
+                let tag = String::from_utf8_lossy(reader.bytes(tag_len)?).into_owned();
+                let value = reader.bytes(end.saturating_sub(reader.pos))?.to_vec();
+                RData::CAA { flags, tag, value }
+            }
+            RecordType::Other(rtype) => RData::Unknown {
+                rtype,
+                data: reader.bytes(len)?.to_vec(),
+            },
+        };
+        // 数据长度必须与 RDLENGTH 一致
+        if reader.pos != end {
+            return Err(MessageError::InvalidRdata(rtype));
+        }
+        Ok(data)
+    }
+
+    // RFC 3597: 只有 RFC 1035 中定义的类型可以压缩数据中的域名
+    fn encode(&self, writer: &mut Writer) -> Result<()> {
+        match self {
+            RData::A(ip) => writer.buf.extend_from_slice(&ip.octets()),
+            RData::AAAA(ip) => writer.buf.extend_from_slice(&ip.octets()),
+            RData::CNAME(name) | RData::NS(name) | RData::PTR(name) => writer.name(name, true)?,
+            RData::MX {
+                preference,
+                exchange,
+            } => {
+                writer.u16(*preference);
+                writer.name(exchange, true)?;
+            }
+            RData::TXT(strings) => {
+                for string in strings {
+                    let len = u8::try_from(string.len())
+                        .map_err(|_| MessageError::InvalidRdata(RecordType::TXT))?;
+                    writer.buf.push(len);
+                    writer.buf.extend_from_slice(string);
+                }
+            }
+            RData::SRV {
+                priority,
+                weight,
+                port,
+                target,
+            } => {
+                writer.u16(*priority);
+                writer.u16(*weight);
+                writer.u16(*port);
+                writer.name(target, false)?;
+            }
+            RData::SOA {
+                mname,
+                rname,
+                serial,
+                refresh,
+                retry,
+                expire,
+                minimum,
+            } => {
+                writer.name(mname, true)?;
+                writer.name(rname, true)?;
+                for value in [serial, refresh, retry, expire, minimum] {
+                    writer.buf.extend_from_slice(&value.to_be_bytes());
+                }
+            }
+            RData::OPT(options) => {
+                for (code, data) in options {
+                    let len = u16::try_from(data.len())
+                        .map_err(|_| MessageError::InvalidRdata(RecordType::OPT))?;
+                    writer.u16(*code);
+                    writer.u16(len);
+                    writer.buf.extend_from_slice(data);
+                }
+            }
+            RData::CAA { flags, tag, value } => {
+                let len = u8::try_from(tag.len())
+                    .map_err(|_| MessageError::InvalidRdata(RecordType::CAA))?;
+                writer.buf.push(*flags);
+                writer.buf.push(len);
+                writer.buf.extend_from_slice(tag.as_bytes());
+                writer.buf.extend_from_slice(value);
+            }
+            RData::Unknown { data, .. } => writer.buf.extend_from_slice(data),
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for RData {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RData::A(ip) => write!(f, "{}", ip),
+            RData::AAAA(ip) => write!(f, "{}", ip),
+            RData::CNAME(name) | RData::NS(name) | RData::PTR(name) => {
+                write!(f, "{}", fqdn(name))
+            }
+            RData::MX {
+                preference,
+                exchange,
+            } => write!(f, "{} {}", preference, fqdn(exchange)),
+            RData::TXT(strings) => {
+                let strings: Vec<String> = strings.iter().map(|s| quoted(s)).collect();
+                write!(f, "{}", strings.join(" "))
+            }
+            RData::SRV {
+                priority,
+                weight,
+                port,
+                target,
+            } => write!(f, "{} {} {} {}", priority, weight, port, fqdn(target)),
+            RData::SOA {
+                mname,
+                rname,
+                serial,
+                refresh,
+                retry,
+                expire,
+                minimum,
+            } => write!(
+                f,
+                "{} {} {} {} {} {} {}",
+                fqdn(mname),
+                fqdn(rname),
+                serial,
+                refresh,
+                retry,
+                expire,
+                minimum
+            ),
+            RData::OPT(options) => {
+                let options: Vec<String> = options
+                    .iter()
+                    .map(|(code, data)| format!("{}:{}", code, hex(data)))
+                    .collect();
+                write!(f, "{}", options.join(" "))
+            }
+            RData::CAA { flags, tag, value } => write!(f, "{} {} {}", flags, tag, quoted(value)),
+            // RFC 3597 的未知类型格式
+            RData::Unknown { data, .. } if data.is_empty() => write!(f, "\\# 0"),
+            RData::Unknown { data, .. } => write!(f, "\\# {} {}", data.len(), hex(data)),
+        }
+    }
+}
+
+/// 带结尾 '.' 的完整域名，根域为 "."
+pub fn fqdn(name: &str) -> String {
+    if name.is_empty() || name.ends_with('.') {
+        format!("{}.", name.trim_end_matches('.'))
+    } else {
+        format!("{}.", name)
+    }
+}
+
+/// 类的助记符，IN 以外的类很少使用
+pub fn class_name(class: u16) -> String {
+    match class {
+        CLASS_IN => "IN".to_string(),
+        3 => "CH".to_string(),
+        4 => "HS".to_string(),
+        255 => "ANY".to_string(),
+        other => format!("CLASS{}", other),
+    }
+}
+
+// 带引号的字符串，转义引号、反斜杠和不可打印字符
+fn quoted(bytes: &[u8]) -> String {
+    let mut out = String::from("\"");
+    for &b in bytes {
+        match b {
+            b'"' | b'\\' => {
+                out.push('\\');
+                out.push(b as char);
+            }
+            0x20..=0x7E => out.push(b as char),
+            _ => out.push_str(&format!("\\{:03}", b)),
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02X}", b)).collect()
+}
+
+/// DNS 报文
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Message {
+    pub header: Header,
+    pub questions: Vec<Question>,
+    pub answers: Vec<Record>,
+    pub authorities: Vec<Record>,
+    pub additionals: Vec<Record>,
+}
+
+impl Message {
+    /// 期望递归的标准查询
+    pub fn query(id: u16, name: &str, qtype: RecordType) -> Message {
+        Message {
+            header: Header {
+                id,
+                rd: true,
+                ..Default::default()
+            },
+            questions: vec![Question {
+                name: name.to_string(),
+                qtype,
+                qclass: CLASS_IN,
+            }],
+            ..Default::default()
+        }
+    }
+
+    /// 在附加部分加入 EDNS0 OPT 记录，声明可以接收的 UDP 报文大小
+    pub fn set_edns(&mut self, payload_size: u16) {
+        self.additionals
+            .retain(|record| record.rtype() != RecordType::OPT);
+        self.additionals.push(Record {
+            name: String::new(),
+            class: payload_size,
+            ttl: 0,
+            data: RData::OPT(Vec::new()),
+        });
+    }
+
+    /// OPT 记录声明的 UDP 报文大小，没有 OPT 记录时为 None
+    pub fn edns_payload_size(&self) -> Option<u16> {
+        self.additionals
+            .iter()
+            .find(|record| record.rtype() == RecordType::OPT)
+            .map(|record| record.class)
+    }
+
+    /// 解码报文，所有读取都检查边界
+    pub fn parse(data: &[u8]) -> Result<Message> {
+        let header = Header::parse(data)?;
+        let mut reader = Reader { buf: data, pos: 4 };
+        let counts = [reader.u16()?, reader.u16()?, reader.u16()?, reader.u16()?];
+        let mut message = Message {
+            header,
+            ..Default::default()
+        };
+        for _ in 0..counts[0] {
+            message.questions.push(Question {
+                name: reader.name()?,
+                qtype: RecordType::from(reader.u16()?),
+                qclass: reader.u16()?,
+            });
+        }
+        message.answers = reader.records(counts[1])?;
+        message.authorities = reader.records(counts[2])?;
+        message.additionals = reader.records(counts[3])?;
+        Ok(message)
+    }
+
+    /// 编码报文，相同的域名后缀使用压缩指针
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let mut writer = Writer::default();
+        writer.u16(self.header.id);
+        writer.u16(self.header.flags());
+        for count in [
+            self.questions.len(),
+            self.answers.len(),
+            self.authorities.len(),
+            self.additionals.len(),
+        ] {
+            writer.u16(u16::try_from(count).map_err(|_| MessageError::TooLarge)?);
+        }
+        for question in &self.questions {
+            writer.name(&question.name, true)?;
+            writer.u16(question.qtype.into());
+            writer.u16(question.qclass);
+        }
+        for record in self
+            .answers
+            .iter()
+            .chain(&self.authorities)
+            .chain(&self.additionals)
+        {
+            writer.name(&record.name, true)?;
+            writer.u16(record.rtype().into());
+            writer.u16(record.class);
+            writer.buf.extend_from_slice(&record.ttl.to_be_bytes());
+            let len_pos = writer.buf.len();
+            writer.u16(0);
+            record.data.encode(&mut writer)?;
+            let len = u16::try_from(writer.buf.len() - len_pos - 2)
+                .map_err(|_| MessageError::TooLarge)?;
+            writer.buf[len_pos..len_pos + 2].copy_from_slice(&len.to_be_bytes());
+        }
+        Ok(writer.buf)
+    }
+}
+
+struct Reader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8]> {
+        let bytes = self
+            .buf
+            .get(self.pos..self.pos + len)
+            .ok_or(MessageError::Truncated)?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N]> {
+        Ok(self.bytes(N)?.try_into().unwrap())
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16> {
+        Ok(u16::from_be_bytes(self.array()?))
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_be_bytes(self.array()?))
+    }
+
+    fn records(&mut self, count: u16) -> Result<Vec<Record>> {
+        let mut records = Vec::new();
+        for _ in 0..count {
+            let name = self.name()?;
+            let rtype = RecordType::from(self.u16()?);
+            let class = self.u16()?;
+            let ttl = self.u32()?;
+            let len = self.u16()? as usize;
+            let data = RData::decode(rtype, self, len)?;
+            records.push(Record {
+                name,
+                class,
+                ttl,
+                data,
+            });
+        }
+        Ok(records)
+    }
+
+    // 读取域名并展开压缩指针。指针只能指向它之前的数据，并且限制跟随的次数，
+    // 防止恶意构造的报文形成循环
+    fn name(&mut self) -> Result<String> {
+        let mut name = String::new();
+        let mut pos = self.pos;
+        let mut wire_len = 1;
+        let mut pointers = 0;
+        loop {
+            let len = *self.buf.get(pos).ok_or(MessageError::Truncated)?;
+            match len & 0xC0 {
+                0x00 if len == 0 => {
+                    if pointers == 0 {
+                        self.pos = pos + 1;
+                    }
+                    return Ok(name);
+                }
+                0x00 => {
+                    let len = len as usize;
+                    let label = self
+                        .buf
+                        .get(pos + 1..pos + 1 + len)
+                        .ok_or(MessageError::Truncated)?;
+                    wire_len += len + 1;
+                    if wire_len > MAX_NAME_LEN {
+                        return Err(MessageError::NameTooLong(name));
+                    }
+                    if !name.is_empty() {
+                        name.push('.');
+                    }
+                    push_label(&mut name, label);
+                    pos += 1 + len;
+                }
+                0xC0 => {
+                    let low = *self.buf.get(pos + 1).ok_or(MessageError::Truncated)?;
+                    let target = ((len & 0x3F) as usize) << 8 | low as usize;
+                    if target >= pos {
+                        return Err(MessageError::InvalidPointer(target));
+                    }
+                    pointers += 1;
+                    if pointers > MAX_POINTERS {
+                        return Err(MessageError::PointerLoop);
+                    }
+                    if pointers == 1 {
+                        self.pos = pos + 2;
+                    }
+                    pos = target;
+                }
+                _ => return Err(MessageError::InvalidLabel(len)),
+            }
+        }
+    }
+}
+
+// 与 dig 一样转义标签中的 '.'、'\' 和不可打印字符
+fn push_label(name: &mut String, label: &[u8]) {
+    for &b in label {
+        match b {
+            b'.' | b'\\' => {
+                name.push('\\');
+                name.push(b as char);
+            }
+            0x21..=0x7E => name.push(b as char),
+            _ => name.push_str(&format!("\\{:03}", b)),
+        }
+    }
+}
+
+// 把域名拆分为标签，支持 push_label 产生的转义
+fn parse_labels(name: &str) -> Result<Vec<Vec<u8>>> {
+    let invalid = || MessageError::InvalidName(name.to_string());
+    let trimmed = name.strip_suffix('.').unwrap_or(name);
+    if trimmed.is_empty() {
+        return Ok(Vec::new());
+    }
+    let mut labels = Vec::new();
+    let mut label = Vec::new();
+    let mut chars = trimmed.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '.' => {
+                if label.is_empty() {
+                    return Err(invalid());
+                }
+                labels.push(std::mem::take(&mut label));
+            }
+            '\\' => {
+                let next = chars.next().ok_or_else(invalid)?;
+                if next.is_ascii_digit() {
+                    let digits: String = std::iter::once(next)
+                        .chain(chars.by_ref().take(2))
+                        .collect();
+                    label.push(digits.parse::<u8>().map_err(|_| invalid())?);
+                } else {
+                    let mut buf = [0; 4];
+                    label.extend_from_slice(next.encode_utf8(&mut buf).as_bytes());
+                }
+            }
+            c => {
+                let mut buf = [0; 4];
+                label.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+            }
+        }
+        if label.len() > MAX_LABEL_LEN {
+            return Err(invalid());
+        }
+    }
+    if label.is_empty() {
+        return Err(invalid());
+    }
+    labels.push(label);
+    let wire_len: usize = labels.iter().map(|l| l.len() + 1).sum::<usize>() + 1;
+    if wire_len > MAX_NAME_LEN {
+        return Err(MessageError::NameTooLong(name.to_string()));
+    }
+    Ok(labels)
+}
+
+#[derive(Default)]
+struct Writer {
+    buf: Vec<u8>,
+    // 已经写入的域名后缀(小写)及其位置
+    names: HashMap<Vec<Vec<u8>>, u16>,
+}
+
+impl Writer {
+    fn u16(&mut self, value: u16) {
+        self.buf.extend_from_slice(&value.to_be_bytes());
+    }
+
+    fn name(&mut self, name: &str, compress: bool) -> Result<()> {
+        let labels = parse_labels(name)?;
+        for i in 0..labels.len() {
+            let suffix: Vec<Vec<u8>> = labels[i..].iter().map(|l| l.to_ascii_lowercase()).collect();
+            if compress && let Some(&offset) = self.names.get(&suffix) {
+                self.u16(0xC000 | offset);
+                return Ok(());
+            }
+            // 指针只有 14 位
+            if self.buf.len() < 0x4000 {
+                self.names.entry(suffix).or_insert(self.buf.len() as u16);
+            }
+            self.buf.push(labels[i].len() as u8);
+            self.buf.extend_from_slice(&labels[i]);
+        }
+        self.buf.push(0);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // www.example.com 的 A 记录查询
+    const QUERY: &[u8] = b"\xbe\xef\x01\x00\x00\x01\x00\x00\x00\x00\x00\x00\
+        \x03www\x07example\x03com\x00\x00\x01\x00\x01";
+
+    // 上面查询的响应: www.example.com 是 example.com 的别名
+    const RESPONSE: &[u8] = b"\xbe\xef\x81\x80\x00\x01\x00\x02\x00\x00\x00\x00\
+        \x03www\x07example\x03com\x00\x00\x01\x00\x01\
+        \xc0\x0c\x00\x05\x00\x01\x00\x00\x0e\x10\x00\x02\xc0\x10\
+        \xc0\x10\x00\x01\x00\x01\x00\x00\x01\x2c\x00\x04\x5d\xb8\xd8\x22";
+
+    fn record(name: &str, ttl: u32, data: RData) -> Record {
+        Record {
+            name: name.to_string(),
+            class: CLASS_IN,
+            ttl,
+            data,
+        }
+    }
+
+    #[test]
+    fn test_golden_query() {
+        let query = Message::query(0xBEEF, "www.example.com", RecordType::A);
+        assert_eq!(query.to_bytes().unwrap(), QUERY);
+        assert_eq!(Message::parse(QUERY).unwrap(), query);
+    }
+
+    #[test]
+    fn test_golden_edns() {
+        let mut query = Message::query(0xBEEF, "www.example.com", RecordType::A);
+        query.set_edns(1232);
+        query.set_edns(1232);
+        let bytes = query.to_bytes().unwrap();
+        // 附加部分只有一条 OPT 记录: 根域名、类型 41、UDP 报文大小 1232
+        assert_eq!(&bytes[10..12], b"\x00\x01");
+        assert_eq!(
+            &bytes[QUERY.len()..],
+            b"\x00\x00\x29\x04\xd0\x00\x00\x00\x00\x00\x00"
+        );
+        let parsed = Message::parse(&bytes).unwrap();
+        assert_eq!(parsed.edns_payload_size(), Some(1232));
+        assert_eq!(parsed, query);
+        assert!(Header::parse(&bytes[..4]).is_ok_and(|header| !header.tc && header.rd));
+    }
+
+    #[test]
+    fn test_golden_response() {
+        let response = Message::parse(RESPONSE).unwrap();
+        assert!(response.header.qr && response.header.rd && response.header.ra);
+        assert_eq!(response.header.rcode, 0);
+        assert_eq!(
+            response.answers,
+            [
+                record(
+                    "www.example.com",
+                    3600,
+                    RData::CNAME("example.com".to_string())
+                ),
+                record(
+                    "example.com",
+                    300,
+                    RData::A(Ipv4Addr::new(93, 184, 216, 34))
+                ),
+            ]
+        );
+        // 重新编码得到相同的压缩结果
+        assert_eq!(response.to_bytes().unwrap(), RESPONSE);
+    }
+
+    #[test]
+    fn test_record_types() {
+        let mut message = Message::query(1, "example.com", RecordType::Other(255));
+        message.header.qr = true;
+        message.answers = vec![
+            record(
+                "example.com",
+                60,
+                RData::MX {
+                    preference: 10,
+                    exchange: "mail.example.com".to_string(),
+                },
+            ),
+            record(
+                "example.com",
+                60,
+                RData::TXT(vec![b"v=spf1 -all".to_vec(), Vec::new()]),
+            ),
+            record(
+                "_sip._tcp.example.com",
+                60,
+                RData::SRV {
+                    priority: 1,
+                    weight: 5,
+                    port: 5060,
+                    target: "sip.example.com".to_string(),
+                },
+            ),
+            record(
+                "example.com",
+                60,
+                RData::AAAA("2001:db8::1".parse().unwrap()),
+            ),
+            record("example.com", 60, RData::NS("ns1.example.com".to_string())),
+            record(
+                "1.2.0.192.in-addr.arpa",
+                60,
+                RData::PTR("host.example.com".to_string()),
+            ),
+            record(
+                "example.com",
+                60,
+                RData::CAA {
+                    flags: 0,
+                    tag: "issue".to_string(),
+                    value: b"letsencrypt.org".to_vec(),
+                },
+            ),
+            record(
+                "example.com",
+                60,
+                RData::Unknown {
+                    rtype: 99,
+                    data: vec![1, 2, 3],
+                },
+            ),
+        ];
+        message.authorities = vec![record(
+            "example.com",
+            3600,
+            RData::SOA {
+                mname: "ns1.example.com".to_string(),
+                rname: "hostmaster.example.com".to_string(),
+                serial: 2024010101,
+                refresh: 7200,
+                retry: 900,
+                expire: 1209600,
+                minimum: 300,
+            },
+        )];
+        let bytes = message.to_bytes().unwrap();
+        // MX 的交换域名压缩为 mail + 指向 example.com 的指针
+        let mx = &bytes[29..29 + 21];
+        assert_eq!(
+            mx,
+            b"\xc0\x0c\x00\x0f\x00\x01\x00\x00\x00\x3c\x00\x09\x00\x0a\x04mail\xc0\x0c"
+        );
+        // SRV 的目标域名不压缩
+        assert!(
+            bytes
+                .windows(17)
+                .any(|w| w == b"\x03sip\x07example\x03com\x00")
+        );
+        assert_eq!(Message::parse(&bytes).unwrap(), message);
+    }
+
+    #[test]
+    fn test_escaped_names() {
+        let message = Message::query(2, "a\\.b.c\\255d", RecordType::A);
+        let bytes = message.to_bytes().unwrap();
+        assert_eq!(&bytes[12..21], b"\x03a.b\x03c\xffd\x00");
+        assert_eq!(Message::parse(&bytes).unwrap(), message);
+        for name in [
+            "a..b",
+            ".a",
+            &"x".repeat(64),
+            &vec!["y".repeat(63); 4].join("."),
+        ] {
+            assert!(Message::query(3, name, RecordType::A).to_bytes().is_err());
+        }
+    }
+
+    #[test]
+    fn test_malformed() {
+        let header = b"\x00\x01\x81\x80\x00\x01\x00\x00\x00\x00\x00\x00".to_vec();
+        // 指针指向自身之前的标签，形成循环
+        let mut looped = header.clone();
+        looped.extend_from_slice(b"\x01a\xc0\x0c\x00\x01\x00\x01");
+        assert_eq!(Message::parse(&looped), Err(MessageError::PointerLoop));
+        // 指针指向后面的数据
+        let mut forward = header.clone();
+        forward.extend_from_slice(b"\xc0\x20\x00\x01\x00\x01");
+        assert_eq!(
+            Message::parse(&forward),
+            Err(MessageError::InvalidPointer(0x20))
+        );
+        // 指针超出报文
+        let mut outside = header.clone();
+        outside.extend_from_slice(b"\xc0");
+        assert_eq!(Message::parse(&outside), Err(MessageError::Truncated));
+        // 记录数量多于实际数据
+        assert_eq!(Message::parse(&header), Err(MessageError::Truncated));
+        assert_eq!(Message::parse(&header[..5]), Err(MessageError::Truncated));
+        // 扩展标签类型
+        let mut extended = header.clone();
+        extended.extend_from_slice(b"\x41\x00");
+        assert_eq!(
+            Message::parse(&extended),
+            Err(MessageError::InvalidLabel(0x41))
+        );
+        // A 记录的长度不是 4
+        let mut bad_rdata = RESPONSE.to_vec();
+        let len = bad_rdata.len();
+        bad_rdata[len - 5] = 5;
+        bad_rdata.push(0);
+        assert_eq!(
+            Message::parse(&bad_rdata),
+            Err(MessageError::InvalidRdata(RecordType::A))
+        );
+    }
+
+    #[test]
+    fn test_display() {
+        let soa = RData::SOA {
+            mname: "ns1.example.com".to_string(),
+            rname: "hostmaster.example.com".to_string(),
+            serial: 2024010101,
+            refresh: 7200,
+            retry: 900,
+            expire: 1209600,
+            minimum: 300,
+        };
+        assert_eq!(
+            Record::new("example.com", 3600, soa).to_string(),
+            "example.com.\t3600\tIN\tSOA\tns1.example.com. hostmaster.example.com. 2024010101 7200 900 1209600 300"
+        );
+        let txt = RData::TXT(vec![b"a \"b\"".to_vec(), b"\x01".to_vec()]);
+        assert_eq!(txt.to_string(), "\"a \\\"b\\\"\" \"\\001\"");
+        let caa = RData::CAA {
+            flags: 128,
+            tag: "issue".to_string(),
+            value: b"ca.example".to_vec(),
+        };
+        assert_eq!(caa.to_string(), "128 issue \"ca.example\"");
+        let unknown = RData::Unknown {
+            rtype: 99,
+            data: vec![0xAB, 0x01],
+        };
+        assert_eq!(unknown.to_string(), "\\# 2 AB01");
+        assert_eq!(fqdn(""), ".");
+        assert_eq!(fqdn("example.com."), "example.com.");
+        let header = Header::from_flags(1, 0x8183);
+        assert_eq!(header.status(), "NXDOMAIN");
+        assert_eq!(header.flag_names(), ["qr", "rd", "ra"]);
+    }
+
+    #[test]
+    fn test_record_type_names() {
+        assert_eq!("aaaa".parse(), Ok(RecordType::AAAA));
+        assert_eq!("TYPE257".parse(), Ok(RecordType::CAA));
+        assert_eq!("type65".parse(), Ok(RecordType::Other(65)));
+        assert_eq!(RecordType::Other(65).to_string(), "TYPE65");
+        assert!("BOGUS".parse::<RecordType>().is_err());
+    }
+}
@@ -0,0 +1,245 @@
+//! 按 glibc 的规则解析 /etc/resolv.conf，参见 resolv.conf(5)
+use log::debug;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, SocketAddrV6};
+use std::sync::OnceLock;
+use std::time::Duration;
+
+// glibc 最多使用三个 DNS 服务器(MAXNS)
+const MAX_NAMESERVERS: usize = 3;
+// options 中各项的上限，与 glibc 相同
+const MAX_NDOTS: u32 = 15;
+const MAX_TIMEOUT: u64 = 30;
+const MAX_ATTEMPTS: u32 = 5;
+
+/// 解析器配置
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResolvConf {
+    pub nameservers: Vec<SocketAddr>,
+    /// 搜索域，短域名依次加上这些后缀查询
+    pub search: Vec<String>,
+    /// 域名中的点少于 ndots 个时先尝试搜索域
+    pub ndots: u32,
+    /// 等待一个服务器响应的时间
+    pub timeout: Duration,
+    /// 所有服务器都失败后重新尝试的轮数
+    pub attempts: u32,
+    /// 每次查询轮流从不同的服务器开始
+    pub rotate: bool,
+}
+
+impl Default for ResolvConf {
+    // 没有配置文件时 glibc 使用本机的 DNS 服务器
+    fn default() -> Self {
+        ResolvConf {
+            nameservers: vec![SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 53)],
+            search: Vec::new(),
+            ndots: 1,
+            timeout: Duration::from_secs(5),
+            attempts: 2,
+            rotate: false,
+        }
+    }
+}
+
+impl ResolvConf {
+    /// 系统配置，/etc/resolv.conf 只读取一次，
+    /// 与 glibc 一样可以用 LOCALDOMAIN 和 RES_OPTIONS 环境变量覆盖
+    pub fn system() -> &'static ResolvConf {
+        static CONF: OnceLock<ResolvConf> = OnceLock::new();
+        CONF.get_or_init(|| {
+            let mut conf = match File::open("/etc/resolv.conf") {
+                Ok(file) => ResolvConf::parse(BufReader::new(file)).unwrap_or_else(|e| {
+                    debug!("读取 /etc/resolv.conf 失败: {}", e);
+                    ResolvConf::default()
+                }),
+                Err(e) => {
+                    debug!("无法打开 /etc/resolv.conf: {}", e);
+                    ResolvConf::default()
+                }
+            };
+            if let Ok(domains) = std::env::var("LOCALDOMAIN") {
+                conf.search = domains.split_whitespace().map(normalize_domain).collect();
+            }
+            if let Ok(options) = std::env::var("RES_OPTIONS") {
+                conf.apply_options(options.split_whitespace());
+            }
+            conf
+        })
+    }
+
+    /// 解析配置文件内容，无法识别的行被忽略
+    pub fn parse<R: BufRead>(reader: R) -> io::Result<ResolvConf> {
+        let mut conf = ResolvConf {
+            nameservers: Vec::new(),
+            ..Default::default()
+        };
+        for line in reader.lines() {
+            let line = line?;
+            // '#' 和 ';' 开头的是注释
+            let line = line.split(['#', ';']).next().unwrap_or_default();
+            let mut fields = line.split_whitespace();
+            match fields.next() {
+                Some("nameserver") => {
+                    if conf.nameservers.len() < MAX_NAMESERVERS
+                        && let Some(addr) = fields.next().and_then(parse_nameserver)
+                    {
+                        conf.nameservers.push(addr);
+                    }
+                }
+                // domain 和 search 互相覆盖，以最后一个为准
+                Some("domain") => {
+                    conf.search = fields.next().map(normalize_domain).into_iter().collect();
+                }
+                Some("search") => conf.search = fields.map(normalize_domain).collect(),
+                Some("options") => conf.apply_options(fields),
+                _ => {}
+            }
+        }
+        if conf.nameservers.is_empty() {
+            conf.nameservers = ResolvConf::default().nameservers;
+        }
+        Ok(conf)
+    }
+
+    fn apply_options<'a>(&mut self, options: impl Iterator<Item = &'a str>) {
+        for option in options {
+            let (name, value) = match option.split_once(':') {
+                Some((name, value)) => (name, value.parse::<u32>().ok()),
+                None => (option, None),
+            };
+            match (name, value) {
+                ("ndots", Some(n)) => self.ndots = n.min(MAX_NDOTS),
+                ("timeout", Some(n)) => {
+                    self.timeout = Duration::from_secs((n as u64).clamp(1, MAX_TIMEOUT))
+                }
+                ("attempts", Some(n)) => self.attempts = n.clamp(1, MAX_ATTEMPTS),
+                ("rotate", _) => self.rotate = true,
+                _ => {}
+            }
+        }
+    }
+
+    /// 按 ndots 和搜索域生成依次查询的完整域名:
+    /// 以 '.' 结尾的域名只查询本身；点的数量不少于 ndots 时先查询本身，否则最后查询
+    pub fn candidates(&self, name: &str) -> Vec<String> {
+        if let Some(absolute) = name.strip_suffix('.') {
+            return vec![absolute.to_string()];
+        }
+        let searched = self
+            .search
+            .iter()
+            .map(|domain| format!("{}.{}", name, domain));
+        let dots = name.matches('.').count() as u32;
+        if dots >= self.ndots {
+            std::iter::once(name.to_string()).chain(searched).collect()
+        } else {
+            searched.chain(std::iter::once(name.to_string())).collect()
+        }
+    }
+
+    /// 本次查询使用服务器的顺序，开启 rotate 时每次从下一个服务器开始
+    pub fn server_order(&self) -> Vec<SocketAddr> {
+        let mut servers = self.nameservers.clone();
+        if self.rotate && !servers.is_empty() {
+            use std::sync::atomic::{AtomicUsize, Ordering};
+            static NEXT: AtomicUsize = AtomicUsize::new(0);
+            let start = NEXT.fetch_add(1, Ordering::Relaxed) % servers.len();
+            servers.rotate_left(start);
+        }
+        servers
+    }
+}
+
+// nameserver 只能写 IP 地址；IPv6 链路本地地址可以带数字形式的区域标识(fe80::1%2)
+fn parse_nameserver(value: &str) -> Option<SocketAddr> {
+    if let Ok(ip) = value.parse::<IpAddr>() {
+        return Some(SocketAddr::new(ip, 53));
+    }
+    let (ip, scope) = value.split_once('%')?;
+    Some(SocketAddr::V6(SocketAddrV6::new(
+        ip.parse().ok()?,
+        53,
+        0,
+        scope.parse().ok()?,
+    )))
+}
+
+fn normalize_domain(domain: &str) -> String {
+    domain.trim_end_matches('.').to_ascii_lowercase()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const CONF: &str = "\
+# 由 NetworkManager 生成
+domain old.example
+search corp.example lab.example.
+nameserver 10.0.0.2
+nameserver 2001:db8::53 ; 注释
+nameserver fe80::1%2
+nameserver 10.0.0.4
+nameserver dns.example
+options ndots:2 timeout:3 attempts:9 rotate edns0
+";
+
+    #[test]
+    fn test_parse() {
+        let conf = ResolvConf::parse(CONF.as_bytes()).unwrap();
+        assert_eq!(
+            conf.nameservers,
+            [
+                "10.0.0.2:53".parse().unwrap(),
+                "[2001:db8::53]:53".parse().unwrap(),
+                "[fe80::1%2]:53".parse().unwrap(),
+            ]
+        );
+        assert_eq!(conf.search, ["corp.example", "lab.example"]);
+        assert_eq!(conf.ndots, 2);
+        assert_eq!(conf.timeout, Duration::from_secs(3));
+        assert_eq!(conf.attempts, MAX_ATTEMPTS);
+        assert!(conf.rotate);
+
+        let conf = ResolvConf::parse("search a.example\ndomain b.example\n".as_bytes()).unwrap();
+        assert_eq!(conf.search, ["b.example"]);
+        assert_eq!(
+            conf,
+            ResolvConf {
+                search: vec!["b.example".to_string()],
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn test_candidates() {
+        let conf = ResolvConf::parse(CONF.as_bytes()).unwrap();
+        assert_eq!(
+            conf.candidates("intranet"),
+            ["intranet.corp.example", "intranet.lab.example", "intranet"]
+        );
+        assert_eq!(
+            conf.candidates("www.example.com"),
+            [
+                "www.example.com",
+                "www.example.com.corp.example",
+                "www.example.com.lab.example"
+            ]
+        );
+        assert_eq!(conf.candidates("host.only."), ["host.only"]);
+    }
+
+    #[test]
+    fn test_rotate() {
+        let conf = ResolvConf::parse(CONF.as_bytes()).unwrap();
+        let first = conf.server_order();
+        let second = conf.server_order();
+        assert_eq!(first.len(), 3);
+        assert_ne!(first[0], second[0]);
+        let conf = ResolvConf::default();
+        assert_eq!(conf.server_order(), conf.nameservers);
+    }
+}
@@ -0,0 +1,402 @@
+//! 加密的 DNS 传输: DNS-over-HTTPS(RFC 8484) 和 DNS-over-TLS(RFC 7858)，
+//! 报文的编码与普通 DNS 相同
+use super::exchange_framed;
+use crate::models::Method;
+use crate::models::client::Client;
+use crate::models::proxy::ProxyConfig;
+use crate::models::tls::{self, TlsConfig};
+use crate::models::url::Url;
+use crate::models::utils::base64url_encode;
+use clap::ValueEnum;
+use log::debug;
+use std::net::{IpAddr, SocketAddr, TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+// DoH 请求和响应的媒体类型
+const DNS_MESSAGE: &str = "application/dns-message";
+// DoT 的默认端口
+const DOT_PORT: u16 = 853;
+
+/// DoH 发送查询的请求方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum DohMethod {
+    /// 查询放在 URL 的 dns 参数中，响应可以被 HTTP 缓存
+    Get,
+    /// 查询作为请求体发送
+    #[default]
+    Post,
+}
+
+/// DoH 服务器
+#[derive(Debug, Clone)]
+pub struct DohServer {
+    pub url: String,
+    pub method: DohMethod,
+    pub tls: TlsConfig,
+    /// 访问 DoH 服务器使用的代理
+    pub proxy: ProxyConfig,
+}
+
+impl DohServer {
+    /// 解析 --doh-url，只接受 https 和 http 地址，http 只应在本机或者测试中使用
+    pub fn parse(value: &str) -> Result<DohServer, String> {
+        let url = Url::parse(value).map_err(|e| format!("无效的DoH地址 {}: {}", value, e))?;
+        if url.scheme != "https" && url.scheme != "http" {
+            return Err(format!("DoH地址必须使用https: {}", value));
+        }
+        Ok(DohServer {
+            url: value.trim().to_string(),
+            method: DohMethod::default(),
+            tls: TlsConfig::default(),
+            proxy: ProxyConfig::default(),
+        })
+    }
+
+    // 使用 rcurl 自己的客户端发送查询。客户端使用系统解析器解析 DoH 服务器的域名，
+    // 避免查询 DoH 服务器的地址时再次使用 DoH
+    pub(super) fn exchange(&self, query: &[u8], timeout: Duration) -> Result<Vec<u8>, String> {
+        let mut client = Client::new();
+        client.set_timeout(timeout.as_secs().max(1));
+        client.set_tls_config(self.tls.clone());
+        client.set_proxy(self.proxy.clone());
+        let request = match self.method {
+            // 查询追加到已有的查询参数之后，片段不属于请求目标
+            DohMethod::Get => {
+                let mut url = Url::parse(&self.url).map_err(|e| e.to_string())?;
+                let dns = format!("dns={}", base64url_encode(query));
+                url.query = Some(match url.query.take().filter(|q| !q.is_empty()) {
+                    Some(existing) => format!("{}&{}", existing, dns),
+                    None => dns,
+                });
+                url.fragment = None;
+                client.request(&url.to_string(), Method::GET)
+            }
+            DohMethod::Post => client.request(&self.url, Method::POST),
+        }
+        .map_err(|e| e.to_string())?;
+        {
+            let mut request = request.borrow_mut();
+            request.set("Accept".to_string(), DNS_MESSAGE.to_string());
+            if self.method == DohMethod::Post {
+                request.set("Content-Type".to_string(), DNS_MESSAGE.to_string());
+                request.set_body(query);
+            }
+        }
+        let response = client.execute().map_err(|e| e.to_string())?;
+        if response.status != 200 {
+            return Err(format!("DoH服务器返回状态码 {}", response.status));
+        }
+        let content_type = response
+            .headers
+            .get("Content-Type")
+            .map(|value| value.split(';').next().unwrap_or_default().trim())
+            .unwrap_or_default();
+        if !content_type.eq_ignore_ascii_case(DNS_MESSAGE) {
+            return Err(format!(
+                "DoH响应的类型不是{}: {}",
+                DNS_MESSAGE, content_type
+            ));
+        }
+        Ok(response.body)
+    }
+}
+
+/// DoT 服务器，证书按主机名(或 IP 地址)校验
+#[derive(Debug, Clone)]
+pub struct DotServer {
+    pub host: String,
+    pub port: u16,
+    pub tls: TlsConfig,
+}
+
+impl DotServer {
+    /// 解析 --dot-server: 主机名或 IP，可以带端口，IPv6 带端口时写在方括号中，默认端口为 853
+    pub fn parse(value: &str) -> Result<DotServer, String> {
+        let value = value.trim();
+        let invalid = || format!("无效的DoT服务器地址: {}", value);
+        let (host, port) = if let Ok(ip) = value.parse::<IpAddr>() {
+            (ip.to_string(), DOT_PORT)
+        } else if let Ok(addr) = value.parse::<SocketAddr>() {
+            (addr.ip().to_string(), addr.port())
+        } else {
+            let (host, port) = match value.rsplit_once(':') {
+                Some((host, port)) => (host, port.parse().map_err(|_| invalid())?),
+                None => (value, DOT_PORT),
+            };
+            if host.is_empty() || host.contains(['/', '[', ']', ':', ' ']) {
+                return Err(invalid());
+            }
+            (host.to_string(), port)
+        };
+        Ok(DotServer {
+            host,
+            port,
+            tls: TlsConfig::default(),
+        })
+    }
+
+    /// 显示用的地址
+    pub fn addr(&self) -> String {
+        if self.host.contains(':') {
+            format!("[{}]:{}", self.host, self.port)
+        } else {
+            format!("{}:{}", self.host, self.port)
+        }
+    }
+
+    // 建立 TLS 连接后按 TCP 的格式发送查询，ALPN 协商 dot 协议(RFC 7858 3.1)
+    pub(super) fn exchange(&self, query: &[u8], timeout: Duration) -> Result<Vec<u8>, String> {
+        let connector = tls::default_connector(&TlsConfig {
+            alpn: Some("dot".to_string()),
+            ..self.tls.clone()
+        })
+        .map_err(|e| e.to_string())?;
+        let addrs = (self.host.as_str(), self.port)
+            .to_socket_addrs()
+            .map_err(|e| format!("解析DoT服务器 {} 失败: {}", self.host, e))?;
+        let mut last_error = format!("DoT服务器 {} 没有可用的地址", self.host);
+        for addr in addrs {
+            let stream = match TcpStream::connect_timeout(&addr, timeout) {
+                Ok(stream) => stream,
+                Err(e) => {
+                    debug!("连接DoT服务器 {} 失败: {}", addr, e);
+                    last_error = format!("连接DoT服务器失败: {}", e);
+                    continue;
+                }
+            };
+            if let Err(e) = stream
+                .set_read_timeout(Some(timeout))
+                .and_then(|_| stream.set_write_timeout(Some(timeout)))
+            {
+                return Err(format!("设置超时失败: {}", e));
+            }
+            let mut stream = connector
+                .connect(&self.host, stream)
+                .map_err(|e| e.to_string())?;
+            return exchange_framed(&mut *stream, query);
+        }
+        Err(last_error)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::models::dns::{DnsCache, Message, RData, Record, RecordType, ResolvConf};
+    use crate::models::dns::{Upstream, resolve_with};
+    use crate::models::proxy::Proxy;
+    use crate::models::test_server::{TestRequest, response, serve, serve_proxy};
+    use std::net::Ipv4Addr;
+
+    // 测试服务器使用的 base64url 解码
+    fn base64url_decode(text: &str) -> Vec<u8> {
+        let value = |c: u8| match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'-' => 62,
+            b'_' => 63,
+            _ => panic!("无效的base64url字符: {}", c as char),
+        };
+        let mut bits = 0u32;
+        let mut count = 0;
+        let mut data = Vec::new();
+        for c in text.bytes() {
+            bits = bits << 6 | value(c) as u32;
+            count += 6;
+            if count >= 8 {
+                count -= 8;
+                data.push((bits >> count) as u8);
+            }
+        }
+        data
+    }
+
+    // 回答查询: A 查询返回固定的地址，其他类型返回空的成功响应
+    fn answer(query: &[u8]) -> Vec<u8> {
+        let query = Message::parse(query).unwrap();
+        let mut reply = query.clone();
+        reply.header.qr = true;
+        reply.additionals.clear();
+        if query.questions[0].qtype == RecordType::A {
+            let name = &query.questions[0].name;
+            reply.answers = vec![Record::new(
+                name,
+                300,
+                RData::A(Ipv4Addr::new(192, 0, 2, 53)),
+            )];
+        }
+        reply.to_bytes().unwrap()
+    }
+
+    // 本地的 DoH 服务器，只接受符合 RFC 8484 的请求
+    fn doh_handler(request: &TestRequest) -> Vec<u8> {
+        let query = match request.method.as_str() {
+            "GET" => request
+                .target
+                .split_once('?')
+                .and_then(|(_, query)| query.split('&').find_map(|p| p.strip_prefix("dns=")))
+                .map(base64url_decode),
+            "POST" if request.header("Content-Type") == Some(DNS_MESSAGE) => {
+                Some(request.body.clone())
+            }
+            _ => None,
+        };
+        match query {
+            Some(query)
+                if request.header("Accept") == Some(DNS_MESSAGE)
+                    && Message::parse(&query).is_ok_and(|m| m.header.id == 0) =>
+            {
+                response("200 OK", &[("Content-Type", DNS_MESSAGE)], &answer(&query))
+            }
+            _ => response("400 Bad Request", &[], b""),
+        }
+    }
+
+    #[test]
+    fn test_doh() {
+        let server = serve(doh_handler);
+        // 已有的查询参数保留，片段不会吞掉 dns 参数
+        for (method, path) in [
+            (DohMethod::Get, "/dns-query"),
+            (DohMethod::Get, "/dns-query?ct#frag"),
+            (DohMethod::Post, "/dns-query"),
+        ] {
+            let mut doh = DohServer::parse(&format!("{}{}", server.url, path)).unwrap();
+            doh.method = method;
+            let upstream = Upstream::Https(doh);
+            let cache = DnsCache::default();
+            assert_eq!(
+                resolve_with(
+                    "doh.example",
+                    &ResolvConf::default(),
+                    Some(&upstream),
+                    &cache
+                ),
+                Ok(vec!["192.0.2.53".parse().unwrap()]),
+                "{:?}",
+                method
+            );
+        }
+
+        // 通过代理访问 DoH 服务器，DoH 服务器的域名由代理解析
+        let proxy = serve_proxy(|req| match req.target.starts_with("http://doh.invalid/") {
+            true => doh_handler(req),
+            false => response("502 Bad Gateway", &[], b""),
+        });
+        for method in [DohMethod::Get, DohMethod::Post] {
+            let mut doh = DohServer::parse("http://doh.invalid/dns-query").unwrap();
+            doh.method = method;
+            doh.proxy.set_proxy(Proxy::parse(&proxy.url).unwrap());
+            let upstream = Upstream::Https(doh);
+            let result = resolve_with(
+                "doh.example",
+                &ResolvConf::default(),
+                Some(&upstream),
+                &DnsCache::default(),
+            );
+            assert_eq!(result, Ok(vec!["192.0.2.53".parse().unwrap()]));
+        }
+
+        // 响应类型错误
+        let server = serve(|_| response("200 OK", &[("Content-Type", "text/html")], b"<html>"));
+        let doh = DohServer::parse(&server.url).unwrap();
+        let upstream = Upstream::Https(doh);
+        let result = resolve_with(
+            "doh.example",
+            &ResolvConf {
+                attempts: 1,
+                ..Default::default()
+            },
+            Some(&upstream),
+            &DnsCache::default(),
+        );
+        assert!(result.unwrap_err().contains("text/html"));
+
+        assert!(DohServer::parse("ftp://dns.example/").is_err());
+    }
+
+    #[test]
+    fn test_parse_dot_server() {
+        let addr = |value: &str| DotServer::parse(value).map(|server| server.addr());
+        assert_eq!(addr("1.1.1.1"), Ok("1.1.1.1:853".to_string()));
+        assert_eq!(addr("9.9.9.9:8853"), Ok("9.9.9.9:8853".to_string()));
+        assert_eq!(addr("dns.google"), Ok("dns.google:853".to_string()));
+        assert_eq!(addr("dns.google:853"), Ok("dns.google:853".to_string()));
+        assert_eq!(
+            addr("2606:4700::1111"),
+            Ok("[2606:4700::1111]:853".to_string())
+        );
+        assert_eq!(addr("[::1]:8853"), Ok("[::1]:8853".to_string()));
+        assert!(addr("dns.google:port").is_err());
+        assert!(addr("").is_err());
+        assert!(addr("https://dns.google/").is_err());
+    }
+
+    #[cfg(feature = "rustls")]
+    #[test]
+    fn test_dot() {
+        use rustls::pki_types::PrivateKeyDer;
+        use rustls::{ServerConfig, ServerConnection, StreamOwned};
+        use std::io::{Read, Write};
+        use std::net::TcpListener;
+        use std::sync::Arc;
+        use std::thread;
+
+        let cert = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let mut config = ServerConfig::builder_with_provider(provider)
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_no_client_auth()
+            .with_single_cert(
+                vec![cert.cert.der().clone()],
+                PrivateKeyDer::Pkcs8(cert.key_pair.serialize_der().into()),
+            )
+            .unwrap();
+        // 只接受 dot 协议，客户端协商其他协议时握手失败
+        config.alpn_protocols = vec![b"dot".to_vec()];
+        let config = Arc::new(config);
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let conn = ServerConnection::new(Arc::clone(&config)).unwrap();
+                let mut tls = StreamOwned::new(conn, stream.unwrap());
+                let mut len = [0u8; 2];
+                if tls.read_exact(&mut len).is_err() {
+                    continue;
+                }
+                let mut query = vec![0u8; u16::from_be_bytes(len) as usize];
+                tls.read_exact(&mut query).unwrap();
+                let reply = answer(&query);
+                tls.write_all(&(reply.len() as u16).to_be_bytes()).unwrap();
+                tls.write_all(&reply).unwrap();
+                tls.flush().unwrap();
+            }
+        });
+
+        let mut dot = DotServer::parse(&format!("localhost:{}", port)).unwrap();
+        dot.tls.insecure = true;
+        let upstream = Upstream::Tls(dot.clone());
+        assert_eq!(
+            resolve_with(
+                "dot.example",
+                &ResolvConf::default(),
+                Some(&upstream),
+                &DnsCache::default()
+            ),
+            Ok(vec!["192.0.2.53".parse().unwrap()])
+        );
+
+        // 自签名证书默认校验失败
+        dot.tls.insecure = false;
+        let upstream = Upstream::Tls(dot);
+        let conf = ResolvConf {
+            attempts: 1,
+            ..Default::default()
+        };
+        let result = resolve_with("dot.example", &conf, Some(&upstream), &DnsCache::default());
+        assert!(result.unwrap_err().contains("TLS"));
+    }
+}
@@ -1,3 +1,4 @@
+use super::url::UrlError;
 use std::io;
 use thiserror::Error;
 
@@ -9,8 +10,26 @@ pub enum RequestError {
     Io(#[from] io::Error),
     #[error("其他错误:{0}")]
     Other(#[from] anyhow::Error),
+    #[error("TLS错误:{0}")]
+    Tls(String),
+    #[error("超过最大重定向次数: {0}")]
+    TooManyRedirects(usize),
+    #[error("域名解析失败: {0}")]
+    Resolve(String),
+    #[error("连接服务器失败: {0}")]
+    Connect(io::Error),
+    #[error("不支持的内容编码: {0}")]
+    UnsupportedEncoding(String),
+    #[error("无效的URL: {0}")]
+    InvalidUrl(#[from] UrlError),
+    #[error("无效的头部字段: {0}")]
+    InvalidHeader(String),
     #[error("发送请求失败")]
     SendRquestError(String),
+    #[error("代理错误: {0}")]
+    Proxy(String),
+    #[error("OAuth2错误: {0}")]
+    OAuth2(String),
 }
 
 pub type Result<T> = std::result::Result<T, RequestError>;
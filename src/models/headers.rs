@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use super::error::{self, RequestError};
 
 pub enum HeaderKey {
     Accept,
@@ -44,62 +44,158 @@ impl TryFrom<&str> for HeaderKey {
     }
 }
 
-impl Into<&str> for HeaderKey {
-    fn into(self) -> &'static str {
-        self.as_str()
+impl From<HeaderKey> for &'static str {
+    fn from(key: HeaderKey) -> Self {
+        key.as_str()
     }
 }
 
+/// 头部字段列表: 保持插入顺序，字段名不区分大小写，同名字段可以有多个值
 #[derive(Clone, Debug)]
 pub struct Headers {
-    headers: HashMap<String, String>,
+    entries: Vec<(String, String)>,
 }
 
 impl Headers {
     pub fn new() -> Self {
         Headers {
-            headers: HashMap::new(),
+            entries: Vec::new(),
         }
     }
 
     pub fn keys(&self) -> impl Iterator<Item = &String> {
-        self.headers.keys()
+        self.entries.iter().map(|(key, _)| key)
     }
 
     pub fn values(&self) -> impl Iterator<Item = &String> {
-        self.headers.values()
+        self.entries.iter().map(|(_, value)| value)
+    }
+
+    /// 按插入顺序遍历所有字段
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &String)> {
+        self.into_iter()
     }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
     /// 添加请求头
     /// 如果存在则不添加
     pub fn add(&mut self, key: String, value: String) {
-        if !self.headers.contains_key(&key) {
-            self.headers.insert(key, value.to_string());
+        if !self.contains(&key) {
+            self.entries.push((key, value));
         }
     }
 
     /// 不管是否存在都添加请求头
-    /// 如果存在则覆盖
+    /// 如果存在则覆盖: 替换第一个同名字段的值并删除其余同名字段，位置保持不变
     pub fn set(&mut self, key: String, value: String) {
-        self.headers.insert(key, value.to_string());
+        match self.position(&key) {
+            Some(pos) => {
+                let mut index = 0;
+                self.entries.retain(|(name, _)| {
+                    let keep = index <= pos || !name.eq_ignore_ascii_case(&key);
+                    index += 1;
+                    keep
+                });
+                self.entries[pos] = (key, value);
+            }
+            None => self.entries.push((key, value)),
+        }
+    }
+
+    /// 追加一个字段，保留已有的同名字段(如多个 Set-Cookie)
+    pub fn append(&mut self, key: String, value: String) {
+        self.entries.push((key, value));
+    }
+
+    /// 校验字段名和值后再 set，用于来自用户输入的字段
+    pub fn try_set(&mut self, key: &str, value: &str) -> error::Result<()> {
+        validate(key, value)?;
+        self.set(key.to_string(), value.to_string());
+        Ok(())
     }
 
-    // 获取请求头
+    /// 校验字段名和值后再 append
+    pub fn try_append(&mut self, key: &str, value: &str) -> error::Result<()> {
+        validate(key, value)?;
+        self.append(key.to_string(), value.to_string());
+        Ok(())
+    }
+
+    // 获取第一个同名字段的值
     pub fn get(&self, key: &str) -> Option<&String> {
-        self.headers.get(key)
+        self.position(key).map(|pos| &self.entries[pos].1)
     }
 
-    // 移除请求头
+    /// 获取所有同名字段的值，按出现顺序排列
+    pub fn get_all<'a>(&'a self, key: &'a str) -> impl Iterator<Item = &'a String> + 'a {
+        self.entries
+            .iter()
+            .filter(move |(name, _)| name.eq_ignore_ascii_case(key))
+            .map(|(_, value)| value)
+    }
+
+    pub fn contains(&self, key: &str) -> bool {
+        self.position(key).is_some()
+    }
+
+    // 移除所有同名字段
     pub fn remove(&mut self, key: &str) {
-        self.headers.remove(key);
+        self.entries
+            .retain(|(name, _)| !name.eq_ignore_ascii_case(key));
+    }
+
+    fn position(&self, key: &str) -> Option<usize> {
+        self.entries
+            .iter()
+            .position(|(name, _)| name.eq_ignore_ascii_case(key))
     }
+}
+
+/// 字段名是否为 RFC 9110 5.1 中的 token
+pub fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b))
+}
 
-    // to_string
-    pub fn to_string(&self) -> String {
-        let mut result = String::new();
-        for (key, value) in &self.headers {
-            result.push_str(&format!("{}: {}\r\n", key, value));
+/// 字段值是否符合 RFC 9110 5.5: 只能包含可见字符、空格、制表符和 obs-text，
+/// 不能包含 CR、LF、NUL 等控制字符
+pub fn is_valid_value(value: &str) -> bool {
+    value
+        .bytes()
+        .all(|b| b == b'\t' || b == b' ' || b.is_ascii_graphic() || b >= 0x80)
+}
+
+fn validate(name: &str, value: &str) -> error::Result<()> {
+    if !is_valid_name(name) {
+        return Err(RequestError::InvalidHeader(format!(
+            "无效的字段名 {:?}",
+            name
+        )));
+    }
+    if !is_valid_value(value) {
+        return Err(RequestError::InvalidHeader(format!(
+            "{} 的值包含非法字符: {:?}",
+            name, value
+        )));
+    }
+    Ok(())
+}
+
+impl std::fmt::Display for Headers {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (key, value) in self {
+            write!(f, "{}: {}\r\n", key, value)?;
         }
-        result
+        Ok(())
     }
 }
 
@@ -108,7 +204,6 @@ impl Default for Headers {
         let mut header = Headers::new();
         header.add("User-Agent".to_string(), "rcurl/1.0".to_string());
         header.add("Accept".to_string(), "*/*".to_string());
-        header.add("Connection".to_string(), "close".to_string());
         header.add(
             "Accept-Language".to_string(),
             "zh-CN,zh;q=0.9,en-US;q=0.8,en;q=0.7,en-GB;q=0.6".to_string(),
@@ -120,15 +215,20 @@ impl Default for Headers {
 
 impl<'a> IntoIterator for &'a Headers {
     type Item = (&'a String, &'a String);
-    type IntoIter = std::collections::hash_map::Iter<'a, String, String>;
+    type IntoIter = std::iter::Map<
+        std::slice::Iter<'a, (String, String)>,
+        fn(&'a (String, String)) -> (&'a String, &'a String),
+    >;
 
     fn into_iter(self) -> Self::IntoIter {
-        self.headers.iter()
+        self.entries.iter().map(|(key, value)| (key, value))
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use super::*;
+
     // 自定义迭代器实现
     struct MyIterator {
         current: usize,
@@ -151,9 +251,61 @@ mod tests {
 
     #[test]
     fn test_my_iterator() {
-        let mut iter = MyIterator { current: 0, max: 5 };
-        while let Some(value) = iter.next() {
+        let iter = MyIterator { current: 0, max: 5 };
+        for value in iter {
             println!("Value: {}", value);
         }
     }
+
+    #[test]
+    fn test_multi_value() {
+        let mut headers = Headers::new();
+        headers.append("Set-Cookie".to_string(), "a=1".to_string());
+        headers.append("Content-Type".to_string(), "text/html".to_string());
+        headers.append("set-cookie".to_string(), "b=2".to_string());
+        assert_eq!(headers.len(), 3);
+        assert_eq!(headers.get("SET-COOKIE").unwrap(), "a=1");
+        assert_eq!(
+            headers.get_all("Set-Cookie").collect::<Vec<_>>(),
+            ["a=1", "b=2"]
+        );
+        // 保持插入顺序
+        assert_eq!(
+            headers.keys().collect::<Vec<_>>(),
+            ["Set-Cookie", "Content-Type", "set-cookie"]
+        );
+        assert_eq!(
+            headers.to_string(),
+            "Set-Cookie: a=1\r\nContent-Type: text/html\r\nset-cookie: b=2\r\n"
+        );
+
+        // set 替换第一个位置的值并删除其余同名字段
+        headers.set("SET-COOKIE".to_string(), "c=3".to_string());
+        assert_eq!(headers.len(), 2);
+        assert_eq!(headers.keys().next().unwrap(), "SET-COOKIE");
+        assert_eq!(headers.get_all("set-cookie").collect::<Vec<_>>(), ["c=3"]);
+
+        headers.add("content-type".to_string(), "text/plain".to_string());
+        assert_eq!(headers.get("Content-Type").unwrap(), "text/html");
+        headers.remove("CONTENT-TYPE");
+        assert!(!headers.contains("Content-Type"));
+    }
+
+    #[test]
+    fn test_validate() {
+        let mut headers = Headers::new();
+        assert!(headers.try_set("X-Token", "a b\tc").is_ok());
+        assert!(headers.try_append("X-Token", "").is_ok());
+        assert!(matches!(
+            headers.try_set("X-Token", "a\r\nHost: evil"),
+            Err(RequestError::InvalidHeader(_))
+        ));
+        assert!(headers.try_set("Bad Name", "x").is_err());
+        assert!(headers.try_set("", "x").is_err());
+        assert!(headers.try_append("X:Y", "x").is_err());
+        assert_eq!(
+            headers.get_all("x-token").collect::<Vec<_>>(),
+            ["a b\tc", ""]
+        );
+    }
 }
@@ -31,4 +31,4 @@ impl std::fmt::Display for HttpVersion {
             HttpVersion::Http2_0 => write!(f, "HTTP/2.0"),
         }
     }
-}
\ No newline at end of file
+}
@@ -1,6 +1,7 @@
 use clap::{ValueEnum, builder::PossibleValue};
 use std::fmt::Display;
 
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub enum Method {
     GET,
@@ -1,13 +1,27 @@
+pub mod auth;
+mod chunked;
 pub mod client;
-mod dns;
+pub mod cookie;
+pub mod decoder;
+pub mod dns;
 pub mod error;
-pub mod http_version;
 mod headers;
+pub mod http_version;
 mod method;
+pub mod netrc;
+pub mod oauth2;
+mod pool;
+pub mod proxy;
 mod request;
+pub mod resolver;
 mod response;
+pub mod retry;
+#[cfg(test)]
+mod test_server;
+pub mod tls;
 pub mod url;
-mod utils;
+pub mod utils;
 
 pub use headers::Headers;
 pub use method::Method;
+pub use response::Response;
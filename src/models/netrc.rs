@@ -0,0 +1,209 @@
+//! .netrc 文件: 按主机保存登录用户名和密码
+use super::auth::Credentials;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+// machine 或 default 条目
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct Entry {
+    // default 条目为 None
+    machine: Option<String>,
+    login: Option<String>,
+    password: Option<String>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Netrc {
+    entries: Vec<Entry>,
+}
+
+impl Netrc {
+    /// 用户主目录下的 .netrc
+    pub fn default_path() -> Option<PathBuf> {
+        let home = std::env::var_os("HOME").filter(|home| !home.is_empty())?;
+        Some(Path::new(&home).join(".netrc"))
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        Ok(Self::parse(&fs::read_to_string(path)?))
+    }
+
+    /// 解析 .netrc 的内容。支持 machine、default、login、password，
+    /// 跳过 account、macdef 定义的宏和 # 开头的注释；值可以用双引号括起来
+    pub fn parse(content: &str) -> Self {
+        let mut netrc = Netrc::default();
+        let mut tokens = Tokens { rest: content };
+        while let Some(token) = tokens.next() {
+            match token.as_str() {
+                "machine" => netrc.entries.push(Entry {
+                    machine: tokens.next(),
+                    ..Entry::default()
+                }),
+                "default" => netrc.entries.push(Entry::default()),
+                "login" | "password" | "account" => {
+                    let value = tokens.next();
+                    let Some(entry) = netrc.entries.last_mut() else {
+                        continue;
+                    };
+                    match token.as_str() {
+                        "login" => entry.login = value,
+                        "password" => entry.password = value,
+                        _ => {}
+                    }
+                }
+                "macdef" => tokens.skip_macro(),
+                _ => {}
+            }
+        }
+        netrc
+    }
+
+    /// 查找主机的认证信息，指定 login 时只匹配该用户。没有匹配的 machine 时使用 default 条目，
+    /// 没有用户名的条目不会匹配
+    pub fn find(&self, host: &str, login: Option<&str>) -> Option<Credentials> {
+        let matches = |entry: &&Entry| {
+            entry.login.is_some() && login.is_none_or(|login| entry.login.as_deref() == Some(login))
+        };
+        let entry = self
+            .entries
+            .iter()
+            .filter(|entry| {
+                entry
+                    .machine
+                    .as_deref()
+                    .is_some_and(|machine| machine.eq_ignore_ascii_case(host))
+            })
+            .find(matches)
+            .or_else(|| {
+                self.entries
+                    .iter()
+                    .filter(|entry| entry.machine.is_none())
+                    .find(matches)
+            })?;
+        Some(Credentials {
+            username: entry.login.clone()?,
+            password: entry.password.clone().unwrap_or_default(),
+        })
+    }
+}
+
+// 按空白分隔的记号
+struct Tokens<'a> {
+    rest: &'a str,
+}
+
+impl Tokens<'_> {
+    fn next(&mut self) -> Option<String> {
+        loop {
+            self.rest = self.rest.trim_start();
+            if !self.rest.starts_with('#') {
+                break;
+            }
+            // 注释到行尾
+            let end = self.rest.find('\n').unwrap_or(self.rest.len());
+            self.rest = &self.rest[end..];
+        }
+        if self.rest.is_empty() {
+            return None;
+        }
+        let Some(quoted) = self.rest.strip_prefix('"') else {
+            let end = self
+                .rest
+                .find(char::is_whitespace)
+                .unwrap_or(self.rest.len());
+            let token = &self.rest[..end];
+            self.rest = &self.rest[end..];
+            return Some(token.to_string());
+        };
+        let mut token = String::new();
+        let mut chars = quoted.char_indices();
+        self.rest = "";
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '"' => {
+                    self.rest = &quoted[i + 1..];
+                    break;
+                }
+                '\\' => match chars.next() {
+                    Some((_, 'n')) => token.push('\n'),
+                    Some((_, 'r')) => token.push('\r'),
+                    Some((_, 't')) => token.push('\t'),
+                    Some((_, c)) => token.push(c),
+                    None => {}
+                },
+                c => token.push(c),
+            }
+        }
+        Some(token)
+    }
+
+    // 宏定义从 macdef 的下一行开始，到空行结束
+    fn skip_macro(&mut self) {
+        let mut lines = self.rest.split_inclusive('\n');
+        let mut skipped = lines.next().map_or(0, str::len);
+        for line in lines {
+            skipped += line.len();
+            if line.trim().is_empty() {
+                break;
+            }
+        }
+        self.rest = &self.rest[skipped..];
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const NETRC: &str = r#"
+# 注释 machine ignored login x password y
+machine example.com login alice password secret
+machine Example.com
+    login bob
+    password "pass word\"1"
+
+macdef init
+machine evil.com login mallory password stolen
+cd /pub
+
+machine api.example.com login carol password p3 account ignored
+default login anonymous password guest@
+"#;
+
+    #[test]
+    fn test_parse_and_find() {
+        let netrc = Netrc::parse(NETRC);
+        assert_eq!(
+            netrc.find("EXAMPLE.com", None),
+            Some(Credentials::parse("alice:secret"))
+        );
+        assert_eq!(
+            netrc.find("example.com", Some("bob")),
+            Some(Credentials::parse("bob:pass word\"1"))
+        );
+        assert_eq!(
+            netrc.find("api.example.com", None),
+            Some(Credentials::parse("carol:p3"))
+        );
+        // macdef 中的内容和注释不是条目
+        assert_eq!(
+            netrc.find("evil.com", None),
+            Some(Credentials::parse("anonymous:guest@"))
+        );
+        assert_eq!(
+            netrc.find("ignored", None),
+            Some(Credentials::parse("anonymous:guest@"))
+        );
+        assert_eq!(netrc.find("example.com", Some("dave")), None);
+    }
+
+    #[test]
+    fn test_without_default() {
+        let netrc = Netrc::parse("machine a.test login u\nmachine b.test password only\nmachine");
+        assert_eq!(netrc.find("a.test", None), Some(Credentials::parse("u")));
+        assert_eq!(netrc.find("b.test", None), None);
+        assert_eq!(netrc.find("c.test", None), None);
+        assert!(Netrc::parse("").find("a.test", None).is_none());
+    }
+}
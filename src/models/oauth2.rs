@@ -0,0 +1,447 @@
+//! OAuth2(RFC 6749) 的客户端凭据授权和刷新令牌，获取的访问令牌可以缓存到文件中
+use super::Method;
+use super::client::Client;
+use super::error::{RequestError, Result};
+use super::proxy::ProxyConfig;
+use super::resolver::{IpVersion, Resolver};
+use super::tls::TlsConfig;
+use super::utils::base64_encode;
+use log::debug;
+use percent_encoding::{AsciiSet, NON_ALPHANUMERIC, utf8_percent_encode};
+use serde_json::{Map, Value, json};
+use std::cell::RefCell;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// application/x-www-form-urlencoded 中不需要编码的字符
+const FORM: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+// 令牌在过期前这么多秒就重新获取，避免在请求途中过期
+const EXPIRY_MARGIN: u64 = 30;
+// 令牌端点的超时时间(秒)
+const TOKEN_TIMEOUT: u64 = 20;
+
+fn error(message: impl Into<String>) -> RequestError {
+    RequestError::OAuth2(message.into())
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+// Bearer 令牌只能由 b64token 的字符组成(RFC 6750 2.1)，避免令牌中的 CR/LF 注入请求头
+fn is_b64token(token: &str) -> bool {
+    let token = token.trim_end_matches('=');
+    !token.is_empty()
+        && token
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"-._~+/".contains(&b))
+}
+
+fn form_encode(value: &str) -> String {
+    utf8_percent_encode(value, FORM).to_string()
+}
+
+#[derive(Clone, Default)]
+pub struct OAuth2Config {
+    /// 令牌端点
+    pub token_url: String,
+    pub client_id: String,
+    /// 有密钥时通过 Basic 认证发送客户端凭据，否则在表单中发送 client_id
+    pub client_secret: Option<String>,
+    pub scope: Option<String>,
+    /// 优先使用刷新令牌获取访问令牌
+    pub refresh_token: Option<String>,
+    /// 令牌缓存文件
+    pub cache: Option<PathBuf>,
+    /// 访问令牌端点使用的 TLS 配置
+    pub tls: TlsConfig,
+    /// 访问令牌端点使用的代理、解析器和 IP 协议版本，与请求相同
+    pub proxy: ProxyConfig,
+    pub resolver: Option<Rc<dyn Resolver>>,
+    pub ip_version: Option<IpVersion>,
+}
+
+/// 令牌端点返回的访问令牌(RFC 6749 5.1)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Token {
+    pub access_token: String,
+    pub token_type: String,
+    /// 过期时间(UNIX 秒)，服务器没有返回 expires_in 时为 None
+    pub expires_at: Option<u64>,
+    pub refresh_token: Option<String>,
+}
+
+impl Token {
+    /// 解析令牌端点的 JSON 响应
+    fn parse(body: &[u8], now: u64) -> Result<Self> {
+        let value: Value = serde_json::from_slice(body)
+            .map_err(|e| error(format!("令牌端点返回的不是有效的JSON: {}", e)))?;
+        let field = |name: &str| value.get(name).and_then(Value::as_str).map(str::to_string);
+        let access_token =
+            field("access_token").ok_or_else(|| error("令牌端点的响应中没有access_token"))?;
+        if !is_b64token(&access_token) {
+            return Err(error("令牌端点返回的access_token包含无效的字符"));
+        }
+        Ok(Token {
+            access_token,
+            token_type: field("token_type").unwrap_or_else(|| "Bearer".to_string()),
+            expires_at: value
+                .get("expires_in")
+                .and_then(|v| v.as_u64().or_else(|| v.as_str()?.parse().ok()))
+                .map(|expires_in| now + expires_in),
+            refresh_token: field("refresh_token"),
+        })
+    }
+
+    fn is_expired(&self, now: u64) -> bool {
+        self.expires_at
+            .is_some_and(|expires_at| expires_at <= now + EXPIRY_MARGIN)
+    }
+
+    /// Authorization 头的值，只支持 Bearer 令牌(RFC 6750)
+    pub fn authorization(&self) -> Result<String> {
+        if !self.token_type.eq_ignore_ascii_case("Bearer") {
+            return Err(error(format!("不支持的令牌类型: {}", self.token_type)));
+        }
+        Ok(format!("Bearer {}", self.access_token))
+    }
+
+    fn to_json(&self) -> Value {
+        json!({
+            "access_token": self.access_token,
+            "token_type": self.token_type,
+            "expires_at": self.expires_at,
+            "refresh_token": self.refresh_token,
+        })
+    }
+
+    fn from_json(value: &Value) -> Option<Self> {
+        let field = |name: &str| value.get(name).and_then(Value::as_str).map(str::to_string);
+        Some(Token {
+            access_token: field("access_token").filter(|token| is_b64token(token))?,
+            token_type: field("token_type")?,
+            expires_at: value.get("expires_at").and_then(Value::as_u64),
+            refresh_token: field("refresh_token"),
+        })
+    }
+}
+
+/// 获取并缓存访问令牌
+pub struct OAuth2 {
+    config: OAuth2Config,
+    token: RefCell<Option<Token>>,
+}
+
+impl OAuth2 {
+    pub fn new(config: OAuth2Config) -> Self {
+        OAuth2 {
+            config,
+            token: RefCell::new(None),
+        }
+    }
+
+    /// 未过期的访问令牌，依次使用内存中的令牌、缓存文件中的令牌，都没有时向令牌端点请求
+    pub fn token(&self) -> Result<Token> {
+        let now = now();
+        if let Some(token) = self.token.borrow().as_ref().filter(|t| !t.is_expired(now)) {
+            return Ok(token.clone());
+        }
+        if let Some(token) = self.cached(now) {
+            debug!("使用缓存的OAuth2访问令牌");
+            *self.token.borrow_mut() = Some(token.clone());
+            return Ok(token);
+        }
+        self.refresh()
+    }
+
+    /// 丢弃当前的令牌重新获取，服务器拒绝令牌(401)时使用。
+    /// 有刷新令牌时先尝试刷新，失败后改用客户端凭据
+    pub fn refresh(&self) -> Result<Token> {
+        let refresh_token = self
+            .token
+            .borrow_mut()
+            .take()
+            .and_then(|token| token.refresh_token)
+            .or_else(|| self.config.refresh_token.clone());
+        let client_credentials = [("grant_type", "client_credentials")];
+        let token = match refresh_token {
+            Some(refresh_token) => {
+                let form = [
+                    ("grant_type", "refresh_token"),
+                    ("refresh_token", &refresh_token),
+                ];
+                match self.request_token(&form) {
+                    // 刷新时服务器可以不返回新的刷新令牌，继续使用原来的
+                    Ok(token) => Token {
+                        refresh_token: token.refresh_token.or(Some(refresh_token)),
+                        ..token
+                    },
+                    Err(e) => {
+                        debug!("刷新令牌失败，改用客户端凭据: {}", e);
+                        self.request_token(&client_credentials)?
+                    }
+                }
+            }
+            None => self.request_token(&client_credentials)?,
+        };
+        self.save(&token);
+        *self.token.borrow_mut() = Some(token.clone());
+        Ok(token)
+    }
+
+    // 向令牌端点发送表单请求(RFC 6749 4.4 和 6)
+    fn request_token(&self, grant: &[(&str, &str)]) -> Result<Token> {
+        let mut form = grant.to_vec();
+        if let Some(scope) = self.config.scope.as_deref() {
+            form.push(("scope", scope));
+        }
+        if self.config.client_secret.is_none() {
+            form.push(("client_id", &self.config.client_id));
+        }
+        let body = form
+            .iter()
+            .map(|(key, value)| format!("{}={}", key, form_encode(value)))
+            .collect::<Vec<_>>()
+            .join("&");
+
+        let mut client = Client::new();
+        client.set_timeout(TOKEN_TIMEOUT);
+        client.set_tls_config(self.config.tls.clone());
+        client.set_proxy(self.config.proxy.clone());
+        if let Some(resolver) = self.config.resolver.as_ref() {
+            client.set_resolver(Box::new(Rc::clone(resolver)));
+        }
+        client.set_ip_version(self.config.ip_version);
+        {
+            let mut request = client
+                .request(&self.config.token_url, Method::POST)?
+                .borrow_mut();
+            request.set_body(body.as_bytes());
+            request.set(
+                "Content-Type".to_string(),
+                "application/x-www-form-urlencoded".to_string(),
+            );
+            request.set("Accept".to_string(), "application/json".to_string());
+            // 客户端凭据先按表单编码再进行 Basic 认证(RFC 6749 2.3.1)
+            if let Some(secret) = self.config.client_secret.as_deref() {
+                let credentials = format!(
+                    "{}:{}",
+                    form_encode(&self.config.client_id),
+                    form_encode(secret)
+                );
+                request.set(
+                    "Authorization".to_string(),
+                    format!("Basic {}", base64_encode(credentials.as_bytes())),
+                );
+            }
+        }
+        let response = client.execute()?;
+        if response.status != 200 {
+            // 错误响应(RFC 6749 5.2)
+            let value: Value = serde_json::from_slice(&response.body).unwrap_or_default();
+            let description = ["error", "error_description"]
+                .iter()
+                .filter_map(|name| value.get(name)?.as_str())
+                .collect::<Vec<_>>()
+                .join(": ");
+            return Err(error(format!(
+                "令牌端点返回状态码 {} {}",
+                response.status, description
+            )));
+        }
+        Token::parse(&response.body, now())
+    }
+
+    // 缓存文件中按令牌端点、客户端和 scope 区分令牌
+    fn cache_key(&self) -> String {
+        format!(
+            "{} {} {}",
+            self.config.token_url,
+            self.config.client_id,
+            self.config.scope.as_deref().unwrap_or_default()
+        )
+    }
+
+    fn read_cache(&self) -> Map<String, Value> {
+        let Some(path) = self.config.cache.as_ref() else {
+            return Map::new();
+        };
+        match fs::read(path).map(|data| serde_json::from_slice(&data)) {
+            Ok(Ok(Value::Object(map))) => map,
+            _ => Map::new(),
+        }
+    }
+
+    fn cached(&self, now: u64) -> Option<Token> {
+        Token::from_json(self.read_cache().get(&self.cache_key())?).filter(|t| !t.is_expired(now))
+    }
+
+    // 写入缓存文件，同时清理已经过期且不能刷新的令牌。缓存失败不影响请求
+    fn save(&self, token: &Token) {
+        let Some(path) = self.config.cache.as_ref() else {
+            return;
+        };
+        let now = now();
+        let mut cache = self.read_cache();
+        cache.retain(|_, value| {
+            Token::from_json(value)
+                .is_some_and(|token| !token.is_expired(now) || token.refresh_token.is_some())
+        });
+        cache.insert(self.cache_key(), token.to_json());
+        let mut options = OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        // 令牌相当于密码，只允许所有者读写。mode 只对新建的文件生效，
+        // 已有的文件在写入令牌之前修改权限
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        let result = options.open(path).and_then(|mut file| {
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::PermissionsExt;
+                file.set_permissions(fs::Permissions::from_mode(0o600))?;
+            }
+            file.write_all(Value::Object(cache).to_string().as_bytes())
+        });
+        if let Err(e) = result {
+            debug!("写入令牌缓存 {} 失败: {}", path.display(), e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::models::proxy::Proxy;
+    use crate::models::test_server::{response, serve, serve_proxy};
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn test_parse_token() -> Result<()> {
+        let token = Token::parse(
+            br#"{"access_token":"abc","token_type":"bearer","expires_in":3600,"refresh_token":"r"}"#,
+            1000,
+        )?;
+        assert_eq!(token.expires_at, Some(4600));
+        assert_eq!(token.authorization()?, "Bearer abc");
+        assert!(!token.is_expired(4000));
+        assert!(token.is_expired(4580));
+        assert_eq!(Token::from_json(&token.to_json()), Some(token));
+
+        let token = Token::parse(br#"{"access_token":"x","token_type":"mac"}"#, 0)?;
+        assert_eq!(token.expires_at, None);
+        assert!(token.authorization().is_err());
+        assert!(Token::parse(br#"{"token_type":"bearer"}"#, 0).is_err());
+        // 令牌中的 CR/LF 不能进入请求头
+        for access_token in ["a\r\nX-Injected: 1", "a b", "", "=abc"] {
+            let body = json!({ "access_token": access_token }).to_string();
+            assert!(
+                Token::parse(body.as_bytes(), 0).is_err(),
+                "{:?}",
+                access_token
+            );
+            let cached = json!({ "access_token": access_token, "token_type": "Bearer" });
+            assert_eq!(Token::from_json(&cached), None);
+        }
+        assert!(Token::parse(br#"{"access_token":"a-b.c_d~e+f/g=="}"#, 0).is_ok());
+        assert!(Token::parse(b"<html>", 0).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_client_credentials_and_cache() -> Result<()> {
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let seen = Arc::clone(&requests);
+        let server = serve(move |req| {
+            let body = String::from_utf8_lossy(&req.body).into_owned();
+            let auth = req.header("Authorization").unwrap_or_default().to_string();
+            seen.lock().unwrap().push(format!("{} {}", auth, body));
+            if body.contains("refresh_token=bad") {
+                return response("400 Bad Request", &[], br#"{"error":"invalid_grant"}"#);
+            }
+            response(
+                "200 OK",
+                &[("Content-Type", "application/json")],
+                br#"{"access_token":"t1","token_type":"Bearer","expires_in":3600}"#,
+            )
+        });
+        let dir = std::env::temp_dir().join(format!("rcurl-oauth2-{}", std::process::id()));
+        fs::create_dir_all(&dir)?;
+        let cache = dir.join("tokens.json");
+        // 已经存在的缓存文件也改为只允许所有者读写
+        fs::write(&cache, "{}")?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&cache, fs::Permissions::from_mode(0o644))?;
+        }
+        let config = OAuth2Config {
+            token_url: format!("{}/token", server.url),
+            client_id: "my client".to_string(),
+            client_secret: Some("s&cret".to_string()),
+            scope: Some("read write".to_string()),
+            refresh_token: Some("bad".to_string()),
+            cache: Some(cache.clone()),
+            ..OAuth2Config::default()
+        };
+        let oauth2 = OAuth2::new(config.clone());
+        assert_eq!(oauth2.token()?.access_token, "t1");
+        // 刷新令牌无效时改用客户端凭据，客户端凭据按表单编码后使用 Basic 认证
+        {
+            let requests = requests.lock().unwrap();
+            assert_eq!(requests.len(), 2);
+            assert!(
+                requests[0]
+                    .ends_with("grant_type=refresh_token&refresh_token=bad&scope=read%20write")
+            );
+            assert_eq!(
+                requests[1],
+                format!(
+                    "Basic {} grant_type=client_credentials&scope=read%20write",
+                    base64_encode(b"my%20client:s%26cret")
+                )
+            );
+        }
+        assert_eq!(oauth2.token()?.access_token, "t1");
+        // 新的实例从缓存文件读取令牌，不再请求令牌端点
+        let cached = OAuth2::new(config);
+        assert_eq!(cached.token()?.access_token, "t1");
+        assert_eq!(requests.lock().unwrap().len(), 2);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(fs::metadata(&cache)?.permissions().mode() & 0o777, 0o600);
+        }
+        fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_token_through_proxy() -> Result<()> {
+        let proxy = serve_proxy(|req| match req.target.as_str() {
+            "http://token.invalid/token" => response(
+                "200 OK",
+                &[],
+                br#"{"access_token":"t1","token_type":"Bearer"}"#,
+            ),
+            _ => response("502 Bad Gateway", &[], b""),
+        });
+        let mut config = OAuth2Config {
+            token_url: "http://token.invalid/token".to_string(),
+            client_id: "id".to_string(),
+            ..OAuth2Config::default()
+        };
+        config.proxy.set_proxy(Proxy::parse(&proxy.url)?);
+        assert_eq!(OAuth2::new(config).token()?.access_token, "t1");
+        Ok(())
+    }
+}
@@ -0,0 +1,261 @@
+use super::tls::Stream;
+use super::url::Url;
+use log::debug;
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, ErrorKind, Read, Result as IoResult, Write};
+use std::time::{Duration, Instant};
+
+// 空闲连接默认的最长保留时间
+const DEFAULT_IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+// 每个主机最多保留的空闲连接数
+const DEFAULT_MAX_IDLE_PER_HOST: usize = 8;
+
+/// 连接池的键: 协议 + 主机 + 端口
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct PoolKey {
+    pub scheme: String,
+    pub host: String,
+    pub port: u16,
+}
+
+impl From<&Url> for PoolKey {
+    fn from(url: &Url) -> Self {
+        PoolKey {
+            scheme: url.scheme.to_ascii_lowercase(),
+            host: url.host.to_ascii_lowercase(),
+            port: url.port_or_default(),
+        }
+    }
+}
+
+/// 一条到服务器的 HTTP/1.1 连接，底层可以是明文 TCP 或 TLS
+pub struct Connection {
+    reader: BufReader<Box<dyn Stream>>,
+    key: PoolKey,
+    idle_since: Instant,
+    // 服务器通过 Keep-Alive: timeout= 声明的空闲超时
+    idle_timeout: Option<Duration>,
+    // 服务器通过 Keep-Alive: max= 声明的剩余请求数
+    remaining_requests: Option<u32>,
+}
+
+impl Connection {
+    pub fn new(stream: Box<dyn Stream>, key: PoolKey) -> Self {
+        Connection {
+            reader: BufReader::new(stream),
+            key,
+            idle_since: Instant::now(),
+            idle_timeout: None,
+            remaining_requests: None,
+        }
+    }
+
+    pub fn key(&self) -> &PoolKey {
+        &self.key
+    }
+
+    /// 根据响应头中的 `Keep-Alive: timeout=5, max=100` 更新连接的限制
+    pub fn apply_keep_alive(&mut self, value: &str) {
+        for param in value.split(',') {
+            let Some((name, value)) = param.split_once('=') else {
+                continue;
+            };
+            let value = value.trim().trim_matches('"');
+            match name.trim().to_ascii_lowercase().as_str() {
+                "timeout" => {
+                    if let Ok(secs) = value.parse::<u64>() {
+                        self.idle_timeout = Some(Duration::from_secs(secs));
+                    }
+                }
+                "max" => {
+                    if let Ok(max) = value.parse::<u32>() {
+                        self.remaining_requests = Some(max);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    // 连接是否超过了空闲时间或请求次数限制
+    fn is_expired(&self, idle_timeout: Duration) -> bool {
+        let timeout = self
+            .idle_timeout
+            .map_or(idle_timeout, |t| t.min(idle_timeout));
+        self.idle_since.elapsed() >= timeout || self.remaining_requests == Some(0)
+    }
+
+    // 检查对端是否已经关闭了连接: 空闲连接上不应该有任何可读数据
+    fn is_alive(&self) -> bool {
+        if !self.reader.buffer().is_empty() {
+            return false;
+        }
+        let stream = self.reader.get_ref().tcp();
+        if stream.set_nonblocking(true).is_err() {
+            return false;
+        }
+        let mut buf = [0u8; 1];
+        let alive = match stream.peek(&mut buf) {
+            // 0 表示对端已关闭，>0 表示收到了不属于任何请求的数据
+            Ok(_) => false,
+            Err(e) => e.kind() == ErrorKind::WouldBlock,
+        };
+        stream.set_nonblocking(false).is_ok() && alive
+    }
+}
+
+impl Read for Connection {
+    fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
+        self.reader.read(buf)
+    }
+}
+
+impl BufRead for Connection {
+    fn fill_buf(&mut self) -> IoResult<&[u8]> {
+        self.reader.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.reader.consume(amt)
+    }
+}
+
+impl Write for Connection {
+    fn write(&mut self, buf: &[u8]) -> IoResult<usize> {
+        self.reader.get_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> IoResult<()> {
+        self.reader.get_mut().flush()
+    }
+}
+
+/// 空闲连接池
+pub struct Pool {
+    idle: HashMap<PoolKey, Vec<Connection>>,
+    idle_timeout: Duration,
+    max_idle_per_host: usize,
+}
+
+impl Pool {
+    pub fn new() -> Self {
+        Pool {
+            idle: HashMap::new(),
+            idle_timeout: DEFAULT_IDLE_TIMEOUT,
+            max_idle_per_host: DEFAULT_MAX_IDLE_PER_HOST,
+        }
+    }
+
+    pub fn set_idle_timeout(&mut self, timeout: Duration) {
+        self.idle_timeout = timeout;
+    }
+
+    /// 取出一条可用的空闲连接，过期或已被对端关闭的连接会被丢弃
+    pub fn checkout(&mut self, key: &PoolKey) -> Option<Connection> {
+        self.evict_expired();
+        let conns = self.idle.get_mut(key)?;
+        // 优先使用最近放回的连接
+        while let Some(mut conn) = conns.pop() {
+            if conn.is_alive() {
+                debug!("复用连接: {}:{}", key.host, key.port);
+                if let Some(remaining) = conn.remaining_requests.as_mut() {
+                    *remaining = remaining.saturating_sub(1);
+                }
+                return Some(conn);
+            }
+            debug!("丢弃已关闭的连接: {}:{}", key.host, key.port);
+        }
+        None
+    }
+
+    /// 放回一条已经读完响应的连接
+    pub fn checkin(&mut self, mut conn: Connection) {
+        if conn.remaining_requests == Some(0) {
+            return;
+        }
+        conn.idle_since = Instant::now();
+        let conns = self.idle.entry(conn.key.clone()).or_default();
+        if conns.len() >= self.max_idle_per_host {
+            conns.remove(0);
+        }
+        conns.push(conn);
+    }
+
+    /// 清理所有过期的空闲连接
+    pub fn evict_expired(&mut self) {
+        let idle_timeout = self.idle_timeout;
+        self.idle.retain(|_, conns| {
+            conns.retain(|conn| !conn.is_expired(idle_timeout));
+            !conns.is_empty()
+        });
+    }
+
+    /// 当前空闲连接总数
+    #[cfg(test)]
+    pub fn idle_count(&self) -> usize {
+        self.idle.values().map(Vec::len).sum()
+    }
+}
+
+impl Default for Pool {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::net::{TcpListener, TcpStream};
+
+    fn connect() -> (Connection, TcpStream) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let client = TcpStream::connect(addr).unwrap();
+        let (server, _) = listener.accept().unwrap();
+        let key = PoolKey {
+            scheme: "http".to_string(),
+            host: "127.0.0.1".to_string(),
+            port: addr.port(),
+        };
+        (Connection::new(Box::new(client), key), server)
+    }
+
+    #[test]
+    fn test_checkout_checkin() {
+        let mut pool = Pool::new();
+        let (conn, _server) = connect();
+        let key = conn.key().clone();
+        pool.checkin(conn);
+        assert_eq!(pool.idle_count(), 1);
+        assert!(pool.checkout(&key).is_some());
+        assert_eq!(pool.idle_count(), 0);
+    }
+
+    #[test]
+    fn test_closed_connection_is_discarded() {
+        let mut pool = Pool::new();
+        let (conn, server) = connect();
+        let key = conn.key().clone();
+        pool.checkin(conn);
+        drop(server);
+        std::thread::sleep(Duration::from_millis(50));
+        assert!(pool.checkout(&key).is_none());
+    }
+
+    #[test]
+    fn test_keep_alive_limits() {
+        let mut pool = Pool::new();
+        let (mut conn, _server) = connect();
+        let key = conn.key().clone();
+        conn.apply_keep_alive("timeout=0, max=5");
+        pool.checkin(conn);
+        pool.evict_expired();
+        assert_eq!(pool.idle_count(), 0);
+
+        let (mut conn, _server) = connect();
+        conn.apply_keep_alive("max=0");
+        pool.checkin(conn);
+        assert!(pool.checkout(&key).is_none());
+    }
+}
@@ -0,0 +1,273 @@
+//! SOCKS4a(https://www.openssh.com/txt/socks4a.protocol) 和
+//! SOCKS5(RFC 1928，用户名密码认证见 RFC 1929) 的客户端握手，只支持 CONNECT 命令
+use crate::models::error::{RequestError, Result};
+use std::io::{self, Read, Write};
+use std::net::IpAddr;
+
+const SOCKS4_VERSION: u8 = 4;
+const SOCKS5_VERSION: u8 = 5;
+const CMD_CONNECT: u8 = 1;
+// SOCKS5 的认证方式
+const AUTH_NONE: u8 = 0;
+const AUTH_PASSWORD: u8 = 2;
+const AUTH_UNACCEPTABLE: u8 = 0xff;
+// SOCKS5 的地址类型
+const ATYP_IPV4: u8 = 1;
+const ATYP_DOMAIN: u8 = 3;
+const ATYP_IPV6: u8 = 4;
+
+fn error(message: impl Into<String>) -> RequestError {
+    RequestError::Proxy(message.into())
+}
+
+/// SOCKS4a 握手: IPv4 地址直接发送，域名由代理解析。userid 为空时发送空字符串
+pub fn socks4a<S: Read + Write + ?Sized>(
+    stream: &mut S,
+    host: &str,
+    port: u16,
+    userid: Option<&str>,
+) -> Result<()> {
+    let mut request = vec![SOCKS4_VERSION, CMD_CONNECT];
+    request.extend_from_slice(&port.to_be_bytes());
+    let domain = match host.parse::<IpAddr>() {
+        Ok(IpAddr::V4(ip)) => {
+            request.extend_from_slice(&ip.octets());
+            None
+        }
+        Ok(IpAddr::V6(_)) => return Err(error("SOCKS4a 不支持IPv6地址")),
+        // 0.0.0.x(x 不为 0) 表示域名跟在 userid 之后
+        Err(_) => {
+            request.extend_from_slice(&[0, 0, 0, 1]);
+            Some(host)
+        }
+    };
+    request.extend_from_slice(userid.unwrap_or_default().as_bytes());
+    request.push(0);
+    if let Some(domain) = domain {
+        request.extend_from_slice(domain.as_bytes());
+        request.push(0);
+    }
+    stream.write_all(&request)?;
+
+    let mut reply = [0u8; 8];
+    stream.read_exact(&mut reply)?;
+    // 响应的第一个字节(VN)必须为 0
+    if reply[0] != 0 {
+        return Err(error("代理不是 SOCKS4 服务器"));
+    }
+    match reply[1] {
+        90 => Ok(()),
+        91 => Err(error("SOCKS4a 代理拒绝了请求")),
+        92 | 93 => Err(error("SOCKS4a 代理无法通过 identd 验证用户")),
+        code => Err(error(format!("SOCKS4a 代理返回未知的状态: {}", code))),
+    }
+}
+
+/// SOCKS5 握手: host 为 IP 地址时按地址连接，否则把域名交给代理解析。
+/// 指定了用户名和密码时同时提供用户名密码认证
+pub fn socks5<S: Read + Write + ?Sized>(
+    stream: &mut S,
+    host: &str,
+    port: u16,
+    credentials: Option<(&str, &str)>,
+) -> Result<()> {
+    let greeting: &[u8] = match credentials {
+        Some(_) => &[SOCKS5_VERSION, 2, AUTH_NONE, AUTH_PASSWORD],
+        None => &[SOCKS5_VERSION, 1, AUTH_NONE],
+    };
+    stream.write_all(greeting)?;
+    let mut choice = [0u8; 2];
+    stream.read_exact(&mut choice)?;
+    if choice[0] != SOCKS5_VERSION {
+        return Err(error("代理不是 SOCKS5 服务器"));
+    }
+    match (choice[1], credentials) {
+        (AUTH_NONE, _) => {}
+        (AUTH_PASSWORD, Some((username, password))) => authenticate(stream, username, password)?,
+        (AUTH_UNACCEPTABLE, None) => {
+            return Err(error(
+                "SOCKS5 代理需要认证，请使用 --proxy-user 指定用户名和密码",
+            ));
+        }
+        (method, _) => {
+            return Err(error(format!(
+                "SOCKS5 代理选择了不支持的认证方式: {}",
+                method
+            )));
+        }
+    }
+
+    let mut request = vec![SOCKS5_VERSION, CMD_CONNECT, 0];
+    match host.parse::<IpAddr>() {
+        Ok(IpAddr::V4(ip)) => {
+            request.push(ATYP_IPV4);
+            request.extend_from_slice(&ip.octets());
+        }
+        Ok(IpAddr::V6(ip)) => {
+            request.push(ATYP_IPV6);
+            request.extend_from_slice(&ip.octets());
+        }
+        Err(_) => {
+            let len = u8::try_from(host.len()).map_err(|_| error("SOCKS5 的主机名过长"))?;
+            request.extend_from_slice(&[ATYP_DOMAIN, len]);
+            request.extend_from_slice(host.as_bytes());
+        }
+    }
+    request.extend_from_slice(&port.to_be_bytes());
+    stream.write_all(&request)?;
+
+    let mut reply = [0u8; 4];
+    stream.read_exact(&mut reply)?;
+    if reply[1] != 0 {
+        let message = format!("SOCKS5 代理连接失败: {}", reply_message(reply[1]));
+        // 代理连接目标地址失败，调用方可以换一个地址重试
+        let kind = match reply[1] {
+            3 => io::ErrorKind::NetworkUnreachable,
+            4 => io::ErrorKind::HostUnreachable,
+            5 => io::ErrorKind::ConnectionRefused,
+            _ => return Err(error(message)),
+        };
+        return Err(RequestError::Connect(io::Error::new(kind, message)));
+    }
+    // 读完代理绑定的地址和端口，之后的数据都属于目标服务器
+    let bound = match reply[3] {
+        ATYP_IPV4 => 4,
+        ATYP_IPV6 => 16,
+        ATYP_DOMAIN => {
+            let mut len = [0u8; 1];
+            stream.read_exact(&mut len)?;
+            len[0] as usize
+        }
+        atyp => return Err(error(format!("SOCKS5 代理返回未知的地址类型: {}", atyp))),
+    };
+    let mut bound = vec![0u8; bound + 2];
+    stream.read_exact(&mut bound)?;
+    Ok(())
+}
+
+// 用户名密码认证(RFC 1929)
+fn authenticate<S: Read + Write + ?Sized>(
+    stream: &mut S,
+    username: &str,
+    password: &str,
+) -> Result<()> {
+    let too_long = || error("SOCKS5 的用户名或密码超过255字节");
+    let mut request = vec![1, u8::try_from(username.len()).map_err(|_| too_long())?];
+    request.extend_from_slice(username.as_bytes());
+    request.push(u8::try_from(password.len()).map_err(|_| too_long())?);
+    request.extend_from_slice(password.as_bytes());
+    stream.write_all(&request)?;
+    let mut reply = [0u8; 2];
+    stream.read_exact(&mut reply)?;
+    if reply[1] != 0 {
+        return Err(error("SOCKS5 代理认证失败，用户名或密码错误"));
+    }
+    Ok(())
+}
+
+fn reply_message(code: u8) -> String {
+    match code {
+        1 => "服务器错误".to_string(),
+        2 => "规则不允许该连接".to_string(),
+        3 => "网络不可达".to_string(),
+        4 => "主机不可达".to_string(),
+        5 => "连接被拒绝".to_string(),
+        6 => "TTL过期".to_string(),
+        7 => "不支持的命令".to_string(),
+        8 => "不支持的地址类型".to_string(),
+        code => format!("未知错误 {}", code),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+
+    // 读取预先准备的响应，记录写入的数据
+    struct Script {
+        reply: Cursor<Vec<u8>>,
+        sent: Vec<u8>,
+    }
+
+    impl Script {
+        fn new(reply: &[u8]) -> Self {
+            Script {
+                reply: Cursor::new(reply.to_vec()),
+                sent: Vec::new(),
+            }
+        }
+    }
+
+    impl Read for Script {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.reply.read(buf)
+        }
+    }
+
+    impl Write for Script {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.sent.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_socks5_bytes() -> Result<()> {
+        let mut stream = Script::new(&[5, 2, 1, 0, 5, 0, 0, 3, 2, b'p', b'x', 0x1f, 0x90, b'H']);
+        socks5(&mut stream, "example.com", 443, Some(("u", "pw")))?;
+        let mut expected = vec![5, 2, 0, 2, 1, 1, b'u', 2, b'p', b'w', 5, 1, 0, 3, 11];
+        expected.extend_from_slice(b"example.com");
+        expected.extend_from_slice(&[1, 187]);
+        assert_eq!(stream.sent, expected);
+        // 绑定地址之后的数据留给目标服务器
+        assert_eq!(stream.reply.position(), 13);
+
+        let mut stream = Script::new(&[
+            5, 0, 5, 0, 0, 4, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        ]);
+        socks5(&mut stream, "::1", 80, None)?;
+        assert_eq!(&stream.sent[..6], &[5, 1, 0, 5, 1, 0]);
+        assert_eq!(stream.sent[6], ATYP_IPV6);
+
+        let mut stream = Script::new(&[5, 0, 5, 5, 0, 1]);
+        let err = socks5(&mut stream, "10.0.0.1", 80, None).unwrap_err();
+        assert!(err.to_string().contains("连接被拒绝"));
+        assert!(matches!(err, RequestError::Connect(_)));
+        let mut stream = Script::new(&[5, 0, 5, 2, 0, 1]);
+        let err = socks5(&mut stream, "10.0.0.1", 80, None).unwrap_err();
+        assert!(matches!(err, RequestError::Proxy(_)));
+        let mut stream = Script::new(&[5, 0xff]);
+        assert!(socks5(&mut stream, "example.com", 80, None).is_err());
+        let mut stream = Script::new(&[5, 2, 1, 1]);
+        let err = socks5(&mut stream, "example.com", 80, Some(("u", "bad"))).unwrap_err();
+        assert!(err.to_string().contains("认证失败"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_socks4a_bytes() -> Result<()> {
+        let mut stream = Script::new(&[0, 90, 0, 0, 0, 0, 0, 0]);
+        socks4a(&mut stream, "example.com", 80, Some("alice"))?;
+        let mut expected = vec![4, 1, 0, 80, 0, 0, 0, 1];
+        expected.extend_from_slice(b"alice\0example.com\0");
+        assert_eq!(stream.sent, expected);
+
+        let mut stream = Script::new(&[0, 90, 0, 0, 0, 0, 0, 0]);
+        socks4a(&mut stream, "192.0.2.1", 8080, None)?;
+        assert_eq!(stream.sent, vec![4, 1, 0x1f, 0x90, 192, 0, 2, 1, 0]);
+
+        let mut stream = Script::new(&[0, 91, 0, 0, 0, 0, 0, 0]);
+        assert!(socks4a(&mut stream, "example.com", 80, None).is_err());
+        // HTTP 代理等非 SOCKS4 服务器的响应
+        let mut stream = Script::new(b"HTTP/1.1");
+        let err = socks4a(&mut stream, "example.com", 80, None).unwrap_err();
+        assert!(err.to_string().contains("不是 SOCKS4"));
+        assert!(socks4a(&mut Script::new(&[]), "::1", 80, None).is_err());
+        Ok(())
+    }
+}
@@ -1,5 +1,8 @@
+use super::error::Result;
+use super::headers::HeaderKey;
 use super::{Method, headers::Headers, url::Url};
 
+#[derive(Clone)]
 pub struct Request {
     url: Url,
     pub method: String,
@@ -9,20 +12,36 @@ pub struct Request {
 }
 
 impl Request {
-    pub fn build(url: &str, method: Method) -> Request {
-        Request {
-            url: url.into(),
+    pub fn build(url: &str, method: Method) -> Result<Request> {
+        let url = Url::parse(url)?;
+        // Host 放在第一行，之后是默认请求头
+        let mut headers = Headers::new();
+        headers.set("Host".to_string(), url.host_header());
+        for (key, value) in &Headers::default() {
+            headers.append(key.clone(), value.clone());
+        }
+        Ok(Request {
+            url,
             method: method.to_string(),
-            headers: Headers::default(),
+            headers,
             body: Vec::new(),
             http_version: "1.1".to_string(),
-        }
+        })
     }
     pub fn to_bytes(&self) -> Vec<u8> {
+        self.to_bytes_with_target(&self.url.get_path())
+    }
+
+    /// 通过 HTTP 代理发送时使用绝对地址作为请求目标(RFC 9112 3.2.2)
+    pub fn to_proxy_bytes(&self) -> Vec<u8> {
+        self.to_bytes_with_target(&self.url.absolute_form())
+    }
+
+    fn to_bytes_with_target(&self, target: &str) -> Vec<u8> {
         let mut data = Vec::new();
         data.extend_from_slice(self.method.as_bytes());
         data.extend_from_slice(b" ");
-        data.extend_from_slice(&self.url.get_path().as_bytes());
+        data.extend_from_slice(target.as_bytes());
         data.extend_from_slice(b" HTTP/");
         data.extend_from_slice(self.http_version.as_bytes());
         data.extend_from_slice(b"\r\n");
@@ -41,22 +60,61 @@ impl Request {
         self.headers.set(key, value);
     }
 
+    /// 设置请求体，同时更新 Content-Length
     pub fn set_body(&mut self, body: &[u8]) {
         self.body = body.to_vec();
+        self.headers
+            .set("Content-Length".to_string(), body.len().to_string());
     }
 
-    pub fn addr(&self) -> String {
-        format!("{}", self.url.addr())
-    }
-
+    #[allow(dead_code)]
     pub fn set_headers(&mut self, headers: Headers) {
         self.headers = headers;
     }
+
+    pub fn url(&self) -> &Url {
+        &self.url
+    }
+
+    /// 根据重定向响应构造下一跳的请求
+    ///
+    /// 303 改为 GET(HEAD 除外)，301/302 的 POST 按惯例改为 GET，
+    /// 307/308 保留原方法和请求体；跨主机时不再携带认证信息
+    pub fn redirect(&self, status: u16, location: &str) -> Result<Request> {
+        let mut next = self.clone();
+        next.url = self.url.join(location)?;
+        next.headers.set("Host".to_string(), next.url.host_header());
+        let to_get = match status {
+            303 => self.method != Method::HEAD.to_string(),
+            301 | 302 => self.method == Method::POST.to_string(),
+            _ => false,
+        };
+        if to_get {
+            next.method = Method::GET.to_string();
+            next.body.clear();
+            next.headers.remove("Content-Type");
+            next.headers.remove("Content-Length");
+        }
+        if !next.url.same_origin(&self.url) {
+            next.headers.remove(HeaderKey::Authorization.as_str());
+            next.headers.remove("Cookie");
+        }
+        Ok(next)
+    }
+
+    /// 请求是否允许复用连接(没有设置 Connection: close)
+    pub fn keep_alive(&self) -> bool {
+        !self
+            .headers
+            .get("Connection")
+            .is_some_and(|v| v.to_ascii_lowercase().contains("close"))
+    }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::models::error::RequestError;
 
     #[test]
     fn test_request() {
@@ -64,7 +122,7 @@ mod test {
         headers.add("Content-Type".to_string(), "application/json".to_string());
         headers.add("user_id".to_string(), "1".to_string());
         let request = Request {
-            url: "http://localhost:8008".into(),
+            url: Url::parse("http://localhost:8008").unwrap(),
             method: "GET".to_string(),
             headers,
             body: Vec::new(),
@@ -72,4 +130,28 @@ mod test {
         };
         let _ = request.to_bytes();
     }
+
+    #[test]
+    fn test_set_body() {
+        let mut request = Request::build("http://localhost:8008/submit", Method::POST).unwrap();
+        request.set_body(b"name=rcurl");
+        let bytes = String::from_utf8(request.to_bytes()).unwrap();
+        assert!(bytes.starts_with("POST /submit HTTP/1.1\r\n"));
+        assert!(bytes.contains("Content-Length: 10\r\n"));
+        assert!(bytes.ends_with("\r\n\r\nname=rcurl"));
+    }
+
+    #[test]
+    fn test_build_and_redirect_urls() {
+        assert!(matches!(
+            Request::build("http://localhost:abc/", Method::GET),
+            Err(RequestError::InvalidUrl(_))
+        ));
+        let request = Request::build("http://[::1]:8080/a/b#frag", Method::GET).unwrap();
+        let bytes = String::from_utf8(request.to_bytes()).unwrap();
+        assert!(bytes.starts_with("GET /a/b HTTP/1.1\r\nHost: [::1]:8080\r\n"));
+        let next = request.redirect(302, "../c?d=1").unwrap();
+        assert_eq!(next.url().to_string(), "http://[::1]:8080/c?d=1");
+        assert!(request.redirect(302, "http://[bad/").is_err());
+    }
 }
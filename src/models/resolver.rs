@@ -0,0 +1,444 @@
+//! 域名解析，可以选择系统解析器或者内置的 DNS 解析器，
+//! 还可以用 --resolve 和 --connect-to 改写解析结果
+use super::dns::{self, DnsCache, ResolvConf, Upstream};
+use super::error::{RequestError, Result};
+use clap::ValueEnum;
+use log::debug;
+use std::net::{IpAddr, SocketAddr, ToSocketAddrs};
+use std::rc::Rc;
+use std::sync::Arc;
+
+/// 将主机名解析为可以连接的地址列表，调用方按顺序尝试每个地址
+pub trait Resolver {
+    fn resolve(&self, host: &str, port: u16) -> Result<Vec<SocketAddr>>;
+}
+
+// 多个客户端(例如 OAuth2 的令牌请求)共享同一个解析器
+impl<R: Resolver + ?Sized> Resolver for Rc<R> {
+    fn resolve(&self, host: &str, port: u16) -> Result<Vec<SocketAddr>> {
+        (**self).resolve(host, port)
+    }
+}
+
+/// 命令行中可选的解析器
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum ResolverKind {
+    /// 内置的 DNS 解析器
+    Builtin,
+    /// 操作系统的解析器(getaddrinfo)
+    #[default]
+    System,
+}
+
+/// -4/-6: 只使用指定协议版本的地址
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IpVersion {
+    V4,
+    V6,
+}
+
+impl IpVersion {
+    pub fn matches(self, addr: &SocketAddr) -> bool {
+        match self {
+            IpVersion::V4 => addr.is_ipv4(),
+            IpVersion::V6 => addr.is_ipv6(),
+        }
+    }
+}
+
+/// 使用操作系统的解析器
+#[derive(Debug, Default)]
+pub struct SystemResolver;
+
+impl Resolver for SystemResolver {
+    fn resolve(&self, host: &str, port: u16) -> Result<Vec<SocketAddr>> {
+        let addrs = (host, port)
+            .to_socket_addrs()
+            .map_err(|e| RequestError::Resolve(format!("{}: {}", host, e)))?;
+        Ok(addrs.collect())
+    }
+}
+
+/// 使用内置的 DNS 解析器，可以指定 DNS 服务器
+#[derive(Debug)]
+pub struct BuiltinResolver {
+    // /etc/resolv.conf 中的配置，指定了 DNS 服务器时替换其中的服务器
+    conf: ResolvConf,
+    // 查询结果按 TTL 缓存，可以与其他解析器共享
+    cache: Arc<DnsCache>,
+    // DoH 或 DoT 服务器，指定时代替 resolv.conf 中的服务器
+    upstream: Option<Upstream>,
+}
+
+impl BuiltinResolver {
+    pub fn new(servers: Vec<SocketAddr>) -> Self {
+        let mut conf = ResolvConf::system().clone();
+        if !servers.is_empty() {
+            conf.nameservers = servers;
+        }
+        BuiltinResolver {
+            conf,
+            cache: Arc::new(DnsCache::default()),
+            upstream: None,
+        }
+    }
+
+    /// 使用指定的缓存，例如从文件中读取的缓存
+    pub fn set_cache(&mut self, cache: Arc<DnsCache>) {
+        self.cache = cache;
+    }
+
+    /// 只向指定的服务器查询，例如 DoH 或 DoT 服务器
+    pub fn set_upstream(&mut self, upstream: Upstream) {
+        self.upstream = Some(upstream);
+    }
+}
+
+impl Default for BuiltinResolver {
+    fn default() -> Self {
+        Self::new(Vec::new())
+    }
+}
+
+impl Resolver for BuiltinResolver {
+    fn resolve(&self, host: &str, port: u16) -> Result<Vec<SocketAddr>> {
+        if let Ok(ip) = host.parse::<IpAddr>() {
+            return Ok(vec![SocketAddr::new(ip, port)]);
+        }
+        let ips = dns::resolve_with(host, &self.conf, self.upstream.as_ref(), &self.cache)
+            .map_err(|e| RequestError::Resolve(format!("{}: {}", host, e)))?;
+        Ok(ips
+            .into_iter()
+            .map(|ip| SocketAddr::new(ip, port))
+            .collect())
+    }
+}
+
+/// 解析 --dns-servers 中的一项: IP 或 IP:端口，IPv6 带端口时写在方括号中，默认端口为 53
+pub fn parse_dns_server(value: &str) -> std::result::Result<SocketAddr, String> {
+    let value = value.trim();
+    if let Ok(ip) = value.parse::<IpAddr>() {
+        return Ok(SocketAddr::new(ip, 53));
+    }
+    value
+        .parse::<SocketAddr>()
+        .map_err(|_| format!("无效的DNS服务器地址: {}", value))
+}
+
+/// --resolve 中的一项: host:port 解析为指定的地址，不再查询 DNS
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResolveEntry {
+    host: String,
+    port: u16,
+    addrs: Vec<IpAddr>,
+}
+
+/// --connect-to 中的一项: 连接 host:port 时改为连接另一个主机和端口，
+/// 请求的 Host 头和 TLS 的 SNI 不变。为 None 的部分匹配任意主机(端口)或者保持不变
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConnectTo {
+    host: Option<String>,
+    port: Option<u16>,
+    to_host: Option<String>,
+    to_port: Option<u16>,
+}
+
+/// 在其他解析器之前应用 --connect-to 和 --resolve，
+/// 连接重定向后的地址时同样生效
+pub struct OverrideResolver {
+    inner: Box<dyn Resolver>,
+    resolve: Vec<ResolveEntry>,
+    connect_to: Vec<ConnectTo>,
+}
+
+impl OverrideResolver {
+    pub fn new(
+        inner: Box<dyn Resolver>,
+        resolve: Vec<ResolveEntry>,
+        connect_to: Vec<ConnectTo>,
+    ) -> Self {
+        OverrideResolver {
+            inner,
+            resolve,
+            connect_to,
+        }
+    }
+}
+
+impl Resolver for OverrideResolver {
+    // 与 curl 一样先改写连接的目标，再对新的目标应用 --resolve，只使用第一个匹配的项
+    fn resolve(&self, host: &str, port: u16) -> Result<Vec<SocketAddr>> {
+        let (host, port) = match self.connect_to.iter().find(|c| c.matches(host, port)) {
+            Some(connect_to) => {
+                let target = (
+                    connect_to.to_host.as_deref().unwrap_or(host),
+                    connect_to.to_port.unwrap_or(port),
+                );
+                debug!(
+                    "--connect-to: {}:{} 改为连接 {}:{}",
+                    host, port, target.0, target.1
+                );
+                target
+            }
+            None => (host, port),
+        };
+        let entry = self
+            .resolve
+            .iter()
+            .find(|entry| entry.port == port && entry.host.eq_ignore_ascii_case(host));
+        if let Some(entry) = entry {
+            debug!("--resolve: {}:{} 解析为 {:?}", host, port, entry.addrs);
+            return Ok(entry
+                .addrs
+                .iter()
+                .map(|ip| SocketAddr::new(*ip, port))
+                .collect());
+        }
+        self.inner.resolve(host, port)
+    }
+}
+
+impl ConnectTo {
+    fn matches(&self, host: &str, port: u16) -> bool {
+        self.host
+            .as_deref()
+            .is_none_or(|h| h.eq_ignore_ascii_case(host))
+            && self.port.is_none_or(|p| p == port)
+    }
+}
+
+/// 解析 --resolve 的参数: host:port:addr[,addr]...，IPv6 地址可以写在方括号中
+pub fn parse_resolve(value: &str) -> std::result::Result<ResolveEntry, String> {
+    let invalid = || {
+        format!(
+            "无效的--resolve参数，格式为host:port:addr[,addr]: {}",
+            value
+        )
+    };
+    let (host, rest) = split_host(value.trim()).ok_or_else(invalid)?;
+    let (port, addrs) = rest.split_once(':').ok_or_else(invalid)?;
+    let port = port.parse().map_err(|_| invalid())?;
+    let addrs = addrs
+        .split(',')
+        .map(|addr| {
+            let addr = addr.trim();
+            let addr = addr
+                .strip_prefix('[')
+                .and_then(|addr| addr.strip_suffix(']'))
+                .unwrap_or(addr);
+            addr.parse::<IpAddr>()
+                .map_err(|_| format!("无效的IP地址: {}", addr))
+        })
+        .collect::<std::result::Result<Vec<_>, _>>()?;
+    if host.is_empty() {
+        return Err(invalid());
+    }
+    Ok(ResolveEntry {
+        host: host.to_ascii_lowercase(),
+        port,
+        addrs,
+    })
+}
+
+/// 解析 --connect-to 的参数: HOST1:PORT1:HOST2:PORT2，任何部分都可以为空，
+/// IPv6 地址写在方括号中
+pub fn parse_connect_to(value: &str) -> std::result::Result<ConnectTo, String> {
+    let invalid = || {
+        format!(
+            "无效的--connect-to参数，格式为HOST1:PORT1:HOST2:PORT2: {}",
+            value
+        )
+    };
+    let (host, rest) = split_host(value.trim()).ok_or_else(invalid)?;
+    let (port, rest) = rest.split_once(':').ok_or_else(invalid)?;
+    let (to_host, to_port) = split_host(rest).ok_or_else(invalid)?;
+    let host = (!host.is_empty()).then(|| host.to_ascii_lowercase());
+    let to_host = (!to_host.is_empty()).then(|| to_host.to_ascii_lowercase());
+    let port = match port {
+        "" => None,
+        port => Some(port.parse().map_err(|_| invalid())?),
+    };
+    let to_port = match to_port {
+        "" => None,
+        port => Some(port.parse().map_err(|_| invalid())?),
+    };
+    Ok(ConnectTo {
+        host,
+        port,
+        to_host,
+        to_port,
+    })
+}
+
+// 分开开头的主机名和之后以冒号分隔的部分，主机名是方括号中的 IPv6 地址时去掉方括号
+fn split_host(value: &str) -> Option<(&str, &str)> {
+    if let Some(rest) = value.strip_prefix('[') {
+        let (host, rest) = rest.split_once(']')?;
+        return Some((host, rest.strip_prefix(':')?));
+    }
+    value.split_once(':')
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::models::dns::{Message, RData, Record, RecordType};
+    use std::net::{Ipv4Addr, UdpSocket};
+    use std::thread;
+
+    // 回答 A 和 AAAA 查询，AAAA 查询没有记录
+    fn fake_dns_server(ip: Ipv4Addr) -> SocketAddr {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let addr = socket.local_addr().unwrap();
+        thread::spawn(move || {
+            for _ in 0..2 {
+                let mut buf = [0u8; 512];
+                let (n, peer) = socket.recv_from(&mut buf).unwrap();
+                let query = Message::parse(&buf[..n]).unwrap();
+                let mut reply = query.clone();
+                reply.header.qr = true;
+                reply.additionals.clear();
+                let question = &query.questions[0];
+                if question.qtype == RecordType::A {
+                    reply.answers = vec![Record::new(&question.name, 60, RData::A(ip))];
+                }
+                socket.send_to(&reply.to_bytes().unwrap(), peer).unwrap();
+            }
+        });
+        addr
+    }
+
+    #[test]
+    fn test_parse_dns_server() {
+        assert_eq!(
+            parse_dns_server("1.1.1.1"),
+            Ok("1.1.1.1:53".parse().unwrap())
+        );
+        assert_eq!(
+            parse_dns_server("127.0.0.1:5353"),
+            Ok("127.0.0.1:5353".parse().unwrap())
+        );
+        assert_eq!(
+            parse_dns_server("2001:db8::1"),
+            Ok("[2001:db8::1]:53".parse().unwrap())
+        );
+        assert_eq!(
+            parse_dns_server("[2001:db8::1]:5353"),
+            Ok("[2001:db8::1]:5353".parse().unwrap())
+        );
+        assert!(parse_dns_server("dns.example").is_err());
+    }
+
+    #[test]
+    fn test_builtin_resolver() -> Result<()> {
+        let server = fake_dns_server(Ipv4Addr::new(192, 0, 2, 7));
+        let resolver = BuiltinResolver::new(vec![server]);
+        assert_eq!(
+            resolver.resolve("rcurl.test", 8080)?,
+            vec!["192.0.2.7:8080".parse().unwrap()]
+        );
+        // 第二次查询使用缓存，假的 DNS 服务器只回答一次
+        assert_eq!(
+            resolver.resolve("RCURL.test", 443)?,
+            vec!["192.0.2.7:443".parse().unwrap()]
+        );
+        // IP 地址不需要查询
+        assert_eq!(
+            resolver.resolve("::1", 80)?,
+            vec!["[::1]:80".parse().unwrap()]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_system_resolver() -> Result<()> {
+        let addrs = SystemResolver.resolve("127.0.0.1", 80)?;
+        assert_eq!(addrs, vec!["127.0.0.1:80".parse().unwrap()]);
+        Ok(())
+    }
+
+    // 把主机名和端口编码在返回的地址中，用来检查传给内层解析器的参数
+    struct EchoResolver;
+
+    impl Resolver for EchoResolver {
+        fn resolve(&self, host: &str, port: u16) -> Result<Vec<SocketAddr>> {
+            let last = host.len() as u8;
+            Ok(vec![SocketAddr::new(
+                Ipv4Addr::new(10, 0, 0, last).into(),
+                port,
+            )])
+        }
+    }
+
+    #[test]
+    fn test_parse_resolve() {
+        assert_eq!(
+            parse_resolve("Example.com:443:192.0.2.1,[2001:db8::1]"),
+            Ok(ResolveEntry {
+                host: "example.com".to_string(),
+                port: 443,
+                addrs: vec!["192.0.2.1".parse().unwrap(), "2001:db8::1".parse().unwrap()],
+            })
+        );
+        assert!(parse_resolve("example.com:443").is_err());
+        assert!(parse_resolve("example.com:https:192.0.2.1").is_err());
+        assert!(parse_resolve("example.com:443:backend").is_err());
+        assert!(parse_resolve(":443:192.0.2.1").is_err());
+    }
+
+    #[test]
+    fn test_parse_connect_to() {
+        assert_eq!(
+            parse_connect_to("example.com:443:backend.internal:8443"),
+            Ok(ConnectTo {
+                host: Some("example.com".to_string()),
+                port: Some(443),
+                to_host: Some("backend.internal".to_string()),
+                to_port: Some(8443),
+            })
+        );
+        assert_eq!(
+            parse_connect_to("::[2001:db8::1]:"),
+            Ok(ConnectTo {
+                host: None,
+                port: None,
+                to_host: Some("2001:db8::1".to_string()),
+                to_port: None,
+            })
+        );
+        assert!(parse_connect_to("example.com:443:backend").is_err());
+        assert!(parse_connect_to("example.com:x:backend:80").is_err());
+    }
+
+    #[test]
+    fn test_override_resolver() -> Result<()> {
+        let resolver = OverrideResolver::new(
+            Box::new(EchoResolver),
+            vec![parse_resolve("backend:8080:192.0.2.9").unwrap()],
+            vec![
+                parse_connect_to("example.com:80:backend:8080").unwrap(),
+                parse_connect_to(":443::8443").unwrap(),
+            ],
+        );
+        // 改写连接目标后再应用 --resolve
+        assert_eq!(
+            resolver.resolve("EXAMPLE.com", 80)?,
+            vec!["192.0.2.9:8080".parse().unwrap()]
+        );
+        // 只改端口
+        assert_eq!(
+            resolver.resolve("example.org", 443)?,
+            vec!["10.0.0.11:8443".parse().unwrap()]
+        );
+        // 没有匹配的项时使用内层解析器
+        assert_eq!(
+            resolver.resolve("example.com", 8080)?,
+            vec!["10.0.0.11:8080".parse().unwrap()]
+        );
+        assert_eq!(
+            resolver.resolve("backend", 8080)?,
+            vec!["192.0.2.9:8080".parse().unwrap()]
+        );
+        Ok(())
+    }
+}
@@ -1,129 +1,356 @@
 use super::Headers;
-use super::error::Result;
+use super::chunked::ChunkedDecoder;
+use super::decoder::{self, Decoder};
+use super::error::{RequestError, Result};
 use super::http_version::HttpVersion;
+use super::pool::{Connection, Pool};
+use super::retry::parse_retry_after;
 use anyhow::Context;
-use anyhow::anyhow;
 use log::debug;
-use std::{
-    io::{BufRead, BufReader, Read, Result as IoResult, Write},
-    net::TcpStream,
-};
+use percent_encoding::percent_decode_str;
+use std::cell::RefCell;
+use std::io::{self, BufRead, Read, Result as IoResult, Write};
+use std::rc::Rc;
+use std::time::Duration;
 
-pub struct Response<'a> {
+// 读取消息体时每次读取的块大小
+const BUFFER_SIZE: usize = 8192;
+
+/// 重定向链中的一跳
+#[derive(Debug, Clone)]
+pub struct Redirect {
+    pub status: u16,
+    pub url: String,
+    pub location: String,
+}
+
+pub struct Response {
     pub headers: Headers,
     pub status: u16,
     pub version: HttpVersion,
-    reader: BufReader<&'a mut TcpStream>,
+    // 消息体读取器，开启解压时在原始数据外层嵌套解码器
+    reader: Decoder<RawBody>,
+    /// 通过 get_body 读取的完整消息体，流式读取时保持为空
     pub body: Vec<u8>,
-    content_length: Option<u64>,
     content_disposition: Option<String>,
+    /// 跟随重定向时经过的地址，按顺序排列
+    pub redirects: Vec<Redirect>,
 }
 
-impl<'a> Read for Response<'a> {
-    fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
-        // 如果有content_length，确保不会读取超过指定长度
-        if let Some(len) = self.content_length {
-            let already_read = self.body.len() as u64;
-            if already_read >= len {
-                return Ok(0); // 已经读取完毕
-            }
+// 从连接中读取未解码的消息体，处理分块传输和 Content-Length，读完后释放连接
+#[derive(Default)]
+struct RawBody {
+    // 读完消息体后连接会被放回连接池，之后为 None
+    conn: Option<Connection>,
+    // 连接读完后放回的连接池
+    pool: Option<Rc<RefCell<Pool>>>,
+    content_length: Option<u64>,
+    // Transfer-Encoding 为 chunked 时的解码器
+    chunked: Option<ChunkedDecoder>,
+    // 已经读取的消息体字节数
+    body_read: u64,
+    // HEAD 请求以及 1xx/204/304 响应没有消息体
+    no_body: bool,
+    // 连接在读完响应后是否可以复用
+    keep_alive: bool,
+}
 
-            let remaining = len - already_read;
+impl Read for RawBody {
+    fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
+        let Some(conn) = self.conn.as_mut() else {
+            return Ok(0);
+        };
+        let n = if self.no_body {
+            0
+        } else if let Some(decoder) = self.chunked.as_mut() {
+            // 分块传输优先于Content-Length
+            decoder.read(conn, buf)?
+        } else if let Some(len) = self.content_length {
+            // 如果有content_length，确保不会读取超过指定长度
+            let remaining = len.saturating_sub(self.body_read);
             let to_read = std::cmp::min(buf.len() as u64, remaining) as usize;
-            let n = self.reader.read(&mut buf[..to_read])?;
-            self.body.extend_from_slice(&buf[..n]);
-            return Ok(n);
+            conn.read(&mut buf[..to_read])?
+        } else {
+            // 普通读取
+            conn.read(buf)?
+        };
+        self.body_read += n as u64;
+        if self.is_complete() {
+            self.release();
         }
+        Ok(n)
+    }
+}
 
-        // 普通读取
+impl Drop for RawBody {
+    fn drop(&mut self) {
+        self.release();
+    }
+}
+
+impl RawBody {
+    fn is_complete(&self) -> bool {
+        if self.no_body {
+            return true;
+        }
+        if let Some(decoder) = self.chunked.as_ref() {
+            return decoder.is_done();
+        }
+        match self.content_length {
+            Some(len) => self.body_read >= len,
+            // 读到EOF为止的响应无法判断是否完整
+            None => false,
+        }
+    }
+
+    // 释放连接: 响应完整且允许复用时放回连接池，否则直接关闭
+    fn release(&mut self) {
+        let Some(conn) = self.conn.take() else {
+            return;
+        };
+        if self.keep_alive
+            && self.is_complete()
+            && let Some(pool) = self.pool.as_ref()
+        {
+            debug!("连接放回连接池: {}:{}", conn.key().host, conn.key().port);
+            pool.borrow_mut().checkin(conn);
+        }
+    }
+
+    // 检查按 Content-Length 读取的消息体是否被截断
+    fn check_length(&self) -> Result<()> {
+        if let Some(len) = self.content_length
+            && self.chunked.is_none()
+            && !self.no_body
+            && self.body_read < len
+        {
+            return Err(anyhow::anyhow!(
+                "提前到达流结尾，预期读取{}字节，实际读取{}字节",
+                len,
+                self.body_read
+            )
+            .into());
+        }
+        Ok(())
+    }
+}
+
+impl Read for Response {
+    fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
         let n = self.reader.read(buf)?;
-        self.body.extend_from_slice(&buf[..n]);
+        if n == 0 && !buf.is_empty() {
+            // 压缩数据结束后可能还有未读取的原始数据(如分块传输的结尾)，
+            // 读完它们以便连接可以复用
+            io::copy(self.reader.get_mut(), &mut io::sink())?;
+        }
         Ok(n)
     }
 }
 
-impl<'a> Response<'a> {
-    // 从原始字节流解析响应
-    pub fn from_bytes(stream: &'a mut TcpStream) -> Result<Response<'a>> {
-        let mut reader = BufReader::new(&mut *stream);
-        // 2. 解析请求头
-        let mut headers = Headers::new();
-        let mut header_line = String::new();
-        reader.read_line(&mut header_line)?;
-        let (version, status) = Response::parse_status_lien(&header_line)?;
-        loop {
-            header_line.clear();
-            reader.read_line(&mut header_line)?;
-            if header_line.is_empty() {
-                debug!("Header line is empty, skipping");
+impl Response {
+    // 从连接中解析响应, method 为对应请求的方法
+    pub fn from_bytes(mut conn: Connection, method: &str) -> Result<Response> {
+        // 跳过 100 Continue 等临时响应
+        let (version, status, headers) = loop {
+            let (version, status, headers) = Response::read_head(&mut conn)?;
+            if (100..200).contains(&status) && status != 101 {
+                debug!("跳过临时响应: {}", status);
                 continue;
             }
-
-            if header_line == "\r\n" || header_line.is_empty() {
-                break;
-            }
-
-            if let Some((key, value)) = header_line.split_once(':') {
-                headers.set(key.trim().to_string(), value.trim().to_string());
-            }
-        }
+            break (version, status, headers);
+        };
         debug!("Response Headers:\n{:?}", headers);
-        // 解析Content-Length和Content-Disposition头
+        // Transfer-Encoding 可以分成多行，按出现顺序合并后，最后一个编码为 chunked 时使用分块解码
+        let transfer_encoding = headers
+            .get_all("Transfer-Encoding")
+            .map(String::as_str)
+            .collect::<Vec<_>>()
+            .join(",");
+        let chunked = transfer_encoding
+            .rsplit(',')
+            .next()
+            .filter(|s| s.trim().eq_ignore_ascii_case("chunked"))
+            .map(|_| ChunkedDecoder::new());
+        // 解析Content-Length和Content-Disposition头。有 Transfer-Encoding 时忽略 Content-Length，
+        // 最后一个编码不是 chunked 的消息体读到连接关闭为止(RFC 9112 6.3)
         let content_length = headers
             .get("Content-Length")
+            .filter(|_| transfer_encoding.is_empty())
             .and_then(|s| s.parse::<u64>().ok());
         let content_disposition = headers.get("Content-Disposition").map(|s| s.to_string());
+        let no_body = method.eq_ignore_ascii_case("HEAD")
+            || (100..200).contains(&status)
+            || status == 204
+            || status == 304;
 
+        // HTTP/1.1 默认保持连接，HTTP/1.0 需要显式声明 keep-alive；
+        // 没有明确长度的消息体只能读到连接关闭为止
+        let connection = headers
+            .get("Connection")
+            .map(|s| s.to_ascii_lowercase())
+            .unwrap_or_default();
+        let persistent = match version {
+            HttpVersion::Http1_1 => !connection.contains("close"),
+            HttpVersion::Http1_0 => connection.contains("keep-alive"),
+            HttpVersion::Http2_0 => false,
+        };
+        let keep_alive = persistent && (no_body || chunked.is_some() || content_length.is_some());
+        if let Some(value) = headers.get("Keep-Alive") {
+            conn.apply_keep_alive(value);
+        }
+
+        let raw = RawBody {
+            conn: Some(conn),
+            pool: None,
+            content_length,
+            chunked,
+            body_read: 0,
+            no_body,
+            keep_alive,
+        };
         Ok(Response {
             headers,
             version,
             status,
-            reader: reader,
+            reader: Decoder::Identity(raw),
             body: Vec::new(),
-            content_length,
             content_disposition,
+            redirects: Vec::new(),
         })
     }
 
+    // 读取状态行和响应头
+    fn read_head(conn: &mut Connection) -> Result<(HttpVersion, u16, Headers)> {
+        let mut headers = Headers::new();
+        let mut header_line = String::new();
+        if conn.read_line(&mut header_line)? == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "服务器在返回响应前关闭了连接",
+            )
+            .into());
+        }
+        let (version, status) = Response::parse_status_lien(&header_line)?;
+        loop {
+            header_line.clear();
+            if conn.read_line(&mut header_line)? == 0 {
+                return Err(
+                    io::Error::new(io::ErrorKind::UnexpectedEof, "读取响应头时连接被关闭").into(),
+                );
+            }
+
+            if header_line == "\r\n" || header_line == "\n" {
+                break;
+            }
+
+            if let Some((key, value)) = header_line.split_once(':') {
+                headers.append(key.trim().to_string(), value.trim().to_string());
+            }
+        }
+        Ok((version, status, headers))
+    }
+
+    /// 重定向响应的目标地址
+    pub fn location(&self) -> Option<&str> {
+        match self.status {
+            301 | 302 | 303 | 307 | 308 => self.headers.get("Location").map(|s| s.as_str()),
+            _ => None,
+        }
+    }
+
+    /// 响应中的所有 Set-Cookie 值，按出现顺序排列
+    pub fn set_cookies(&self) -> impl Iterator<Item = &String> {
+        self.headers.get_all("Set-Cookie")
+    }
+
+    /// 服务器通过 Retry-After 头要求的重试等待时间
+    pub fn retry_after(&self) -> Option<Duration> {
+        self.headers
+            .get("Retry-After")
+            .and_then(|v| parse_retry_after(v))
+    }
+
+    /// 按 Content-Encoding 解码消息体，需要在读取消息体之前调用
+    pub fn decode_content(&mut self) -> Result<()> {
+        let Some(value) = self.headers.get("Content-Encoding") else {
+            return Ok(());
+        };
+        if self.raw().no_body {
+            return Ok(());
+        }
+        let encodings =
+            decoder::parse_content_encoding(value).map_err(RequestError::UnsupportedEncoding)?;
+        if let Decoder::Identity(raw) = &mut self.reader
+            && !encodings.is_empty()
+        {
+            debug!("解码消息体: {}", value);
+            let raw = std::mem::take(raw);
+            self.reader = Decoder::new(raw, &encodings);
+        }
+        Ok(())
+    }
+
+    fn raw(&self) -> &RawBody {
+        self.reader.get_ref()
+    }
+
+    /// 设置读完响应后放回连接的连接池
+    pub fn set_pool(&mut self, pool: Rc<RefCell<Pool>>) {
+        let raw = self.reader.get_mut();
+        raw.pool = Some(pool);
+        if raw.is_complete() {
+            raw.release();
+        }
+    }
+
     // 获取响应体数据(惰性加载)
     pub fn get_body(&mut self) -> Result<&[u8]> {
-        if self.body.is_empty() {
-            match self.content_length {
-                Some(len) if len > 0 => {
-                    // 精确分配内存
-                    self.body.reserve_exact(len as usize);
-                    // 精确读取指定长度
-                    let mut remaining = len;
-                    let mut chunk = vec![0u8; 8192]; // 8KB缓冲区
-
-                    while remaining > 0 {
-                        let to_read = std::cmp::min(chunk.len() as u64, remaining) as usize;
-                        let bytes_read = self.reader.read(&mut chunk[..to_read])?;
-                        if bytes_read == 0 {
-                            break;
-                        }
-                        self.body.extend_from_slice(&chunk[..bytes_read]);
-                        remaining -= bytes_read as u64;
-                    }
-
-                    if remaining > 0 {
-                        return Err(anyhow::anyhow!(
-                            "提前到达流结尾，预期读取{}字节，实际读取{}字节",
-                            len,
-                            len - remaining
-                        )
-                        .into());
-                    }
-                }
-                _ => {
-                    // 如果没有Content-Length，读取直到EOF
-                    self.reader.read_to_end(&mut self.body)?;
-                }
+        let mut chunk = vec![0u8; BUFFER_SIZE];
+        loop {
+            let n = self.read(&mut chunk)?;
+            if n == 0 {
+                break;
             }
+            self.body.extend_from_slice(&chunk[..n]);
         }
+        self.raw().check_length()?;
         Ok(&self.body)
     }
 
+    /// 将消息体按固定大小的块写入 writer，不在内存中保留整个消息体，返回写入的字节数
+    pub fn copy_to<W: Write + ?Sized>(&mut self, writer: &mut W) -> Result<u64> {
+        let mut chunk = vec![0u8; BUFFER_SIZE];
+        let mut written = 0u64;
+        loop {
+            let n = self.read(&mut chunk)?;
+            if n == 0 {
+                break;
+            }
+            writer.write_all(&chunk[..n])?;
+            written += n as u64;
+        }
+        writer.flush()?;
+        self.raw().check_length()?;
+        Ok(written)
+    }
+
+    /// 分块传输结束后的尾部字段，非分块响应返回 None
+    pub fn trailers(&self) -> Option<&Headers> {
+        self.raw()
+            .chunked
+            .as_ref()
+            .map(|decoder| decoder.trailers())
+    }
+
+    /// 分块传输中各个块携带的扩展参数
+    pub fn chunk_extensions(&self) -> &[(String, Option<String>)] {
+        self.raw()
+            .chunked
+            .as_ref()
+            .map(|decoder| decoder.extensions())
+            .unwrap_or_default()
+    }
+
     fn parse_status_lien(line: &str) -> Result<(HttpVersion, u16)> {
         let mut parts = line.split_whitespace();
         let version = parts.next().context("Invalid status line")?;
@@ -132,28 +359,231 @@ impl<'a> Response<'a> {
         Ok((version.try_into()?, status))
     }
 
-    // // 下载文件到指定路径
-    // pub fn download_to_file(&mut self, path: &str) -> Result<()> {
-    //     use std::fs::File;
-    //     use std::io::Write;
-
-    //     // 确保body已加载
-    //     self.get_body()?;
-    //     let mut file = File::create(path)?;
-    //     file.write_all(&self.body)?;
-    //     Ok(())
-    // }
-
-    // 获取建议的文件名(从Content-Disposition头)
-    // pub fn suggested_filename(&self) -> Option<&str> {
-    //     self.content_disposition
-    //         .as_ref()
-    //         .and_then(|s| s.split("filename=").nth(1))
-    //         .map(|s| s.trim_matches('"'))
-    // }
-
-    // // 获取文件大小(从Content-Length头)
-    // pub fn content_length(&self) -> Option<u64> {
-    //     self.content_length
-    // }
+    /// 获取建议的文件名(从Content-Disposition头)，优先使用 RFC 6266 的 filename*
+    ///
+    /// 返回的文件名未经处理，写入磁盘前需要清理
+    pub fn suggested_filename(&self) -> Option<String> {
+        let disposition = self.content_disposition.as_ref()?;
+        let mut filename = None;
+        for param in disposition.split(';').skip(1) {
+            let Some((name, value)) = param.split_once('=') else {
+                continue;
+            };
+            let value = value.trim();
+            match name.trim().to_ascii_lowercase().as_str() {
+                // filename*=UTF-8''%e4%b8%ad.txt
+                "filename*" => {
+                    let mut parts = value.splitn(3, '\'');
+                    let charset = parts.next().unwrap_or_default();
+                    if let (Some(_), Some(encoded)) = (parts.next(), parts.next())
+                        && charset.eq_ignore_ascii_case("utf-8")
+                        && let Ok(decoded) = percent_decode_str(encoded).decode_utf8()
+                    {
+                        return Some(decoded.into_owned());
+                    }
+                }
+                "filename" => filename = Some(value.trim_matches('"').to_string()),
+                _ => {}
+            }
+        }
+        filename
+    }
+
+    // 获取文件大小(从Content-Length头)
+    pub fn content_length(&self) -> Option<u64> {
+        self.raw().content_length
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::models::pool::PoolKey;
+    use std::io::Write;
+    use std::net::{TcpListener, TcpStream};
+    use std::thread;
+
+    // 启动一个只返回固定内容的本地服务器，返回连接到它的连接
+    fn serve(raw: &'static [u8]) -> Connection {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            stream.write_all(raw).unwrap();
+            // 只关闭写方向，等待客户端读完，避免连接被重置
+            stream.shutdown(std::net::Shutdown::Write).unwrap();
+            let _ = stream.read(&mut [0u8; 1]);
+        });
+        let key = PoolKey {
+            scheme: "http".to_string(),
+            host: "127.0.0.1".to_string(),
+            port: addr.port(),
+        };
+        Connection::new(Box::new(TcpStream::connect(addr).unwrap()), key)
+    }
+
+    #[test]
+    fn test_chunked_body() -> Result<()> {
+        let conn = serve(
+            b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n\
+              5;ext=1\r\nhello\r\n6\r\n world\r\n0\r\nX-Trailer: done\r\n\r\n",
+        );
+        let mut response = Response::from_bytes(conn, "GET")?;
+        assert_eq!(response.get_body()?, b"hello world");
+        assert_eq!(
+            response.trailers().unwrap().get("X-Trailer").unwrap(),
+            "done"
+        );
+        assert_eq!(
+            response.chunk_extensions(),
+            &[("ext".to_string(), Some("1".to_string()))]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_chunked_read() -> Result<()> {
+        let conn = serve(
+            b"HTTP/1.1 200 OK\r\nTransfer-Encoding: gzip, chunked\r\nContent-Length: 100\r\n\r\n\
+              3\r\nabc\r\n0\r\n\r\n",
+        );
+        let mut response = Response::from_bytes(conn, "GET")?;
+        let mut body = Vec::new();
+        response.read_to_end(&mut body)?;
+        assert_eq!(body, b"abc");
+        assert!(response.trailers().unwrap().keys().next().is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_split_transfer_encoding() -> Result<()> {
+        // 分成两行的 Transfer-Encoding 仍然以 chunked 结尾
+        let pool = Rc::new(RefCell::new(Pool::new()));
+        let conn = serve(
+            b"HTTP/1.1 200 OK\r\nTransfer-Encoding: gzip\r\nTransfer-Encoding: chunked\r\n\
+              Content-Length: 100\r\n\r\n3\r\nabc\r\n0\r\n\r\n",
+        );
+        let mut response = Response::from_bytes(conn, "GET")?;
+        response.set_pool(Rc::clone(&pool));
+        assert_eq!(response.get_body()?, b"abc");
+        assert_eq!(pool.borrow().idle_count(), 1);
+
+        // 最后一个编码不是 chunked 时忽略 Content-Length，读到连接关闭，连接不能复用
+        let pool = Rc::new(RefCell::new(Pool::new()));
+        let conn = serve(
+            b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\nTransfer-Encoding: gzip\r\n\
+              Content-Length: 2\r\n\r\nokEXTRA",
+        );
+        let mut response = Response::from_bytes(conn, "GET")?;
+        response.set_pool(Rc::clone(&pool));
+        assert_eq!(response.get_body()?, b"okEXTRA");
+        assert!(response.trailers().is_none());
+        drop(response);
+        assert_eq!(pool.borrow().idle_count(), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_content_length_body() -> Result<()> {
+        let conn = serve(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok");
+        let mut response = Response::from_bytes(conn, "GET")?;
+        assert_eq!(response.get_body()?, b"ok");
+        assert!(response.trailers().is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_case_insensitive_headers() -> Result<()> {
+        let conn = serve(
+            b"HTTP/1.1 200 OK\r\nset-cookie: a=1\r\ncontent-length: 2\r\n\
+              Set-Cookie: b=2\r\n\r\nok",
+        );
+        let mut response = Response::from_bytes(conn, "GET")?;
+        assert_eq!(response.set_cookies().collect::<Vec<_>>(), ["a=1", "b=2"]);
+        assert_eq!(response.content_length(), Some(2));
+        assert_eq!(response.get_body()?, b"ok");
+        Ok(())
+    }
+
+    #[test]
+    fn test_keep_alive_returns_connection() -> Result<()> {
+        let pool = Rc::new(RefCell::new(Pool::new()));
+        let conn = serve(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok");
+        let mut response = Response::from_bytes(conn, "GET")?;
+        response.set_pool(Rc::clone(&pool));
+        assert_eq!(pool.borrow().idle_count(), 0);
+        response.get_body()?;
+        assert_eq!(pool.borrow().idle_count(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_connection_close_is_not_pooled() -> Result<()> {
+        let pool = Rc::new(RefCell::new(Pool::new()));
+        let conn = serve(b"HTTP/1.1 200 OK\r\nConnection: close\r\nContent-Length: 2\r\n\r\nok");
+        let mut response = Response::from_bytes(conn, "GET")?;
+        response.set_pool(Rc::clone(&pool));
+        response.get_body()?;
+        drop(response);
+        assert_eq!(pool.borrow().idle_count(), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_head_and_continue() -> Result<()> {
+        let conn =
+            serve(b"HTTP/1.1 100 Continue\r\n\r\nHTTP/1.1 200 OK\r\nContent-Length: 10\r\n\r\n");
+        let mut response = Response::from_bytes(conn, "HEAD")?;
+        assert_eq!(response.status, 200);
+        assert!(response.raw().is_complete());
+        assert!(response.get_body()?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_copy_to_streams_body() -> Result<()> {
+        let conn = serve(
+            b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n\
+              4\r\n\x00\xff\x10\x80\r\n2\r\nzz\r\n0\r\n\r\n",
+        );
+        let mut response = Response::from_bytes(conn, "GET")?;
+        let mut out = Vec::new();
+        assert_eq!(response.copy_to(&mut out)?, 6);
+        assert_eq!(out, b"\x00\xff\x10\x80zz");
+        assert!(response.body.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_copy_to_truncated() -> Result<()> {
+        let conn =
+            serve(b"HTTP/1.1 200 OK\r\nContent-Length: 10\r\nConnection: close\r\n\r\nshort");
+        let mut response = Response::from_bytes(conn, "GET")?;
+        assert!(response.copy_to(&mut Vec::new()).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_suggested_filename() -> Result<()> {
+        let cases: [(&'static [u8], Option<&str>); 4] = [
+            (
+                b"HTTP/1.1 204 No Content\r\nContent-Disposition: attachment; filename=\"a b.txt\"\r\n\r\n",
+                Some("a b.txt"),
+            ),
+            (
+                b"HTTP/1.1 204 No Content\r\nContent-Disposition: attachment; filename=plain.txt; filename*=UTF-8''%E4%B8%AD%E6%96%87.txt\r\n\r\n",
+                Some("\u{4e2d}\u{6587}.txt"),
+            ),
+            (
+                b"HTTP/1.1 204 No Content\r\nContent-Disposition: inline\r\n\r\n",
+                None,
+            ),
+            (b"HTTP/1.1 204 No Content\r\n\r\n", None),
+        ];
+        for (raw, expected) in cases {
+            let response = Response::from_bytes(serve(raw), "GET")?;
+            assert_eq!(response.suggested_filename().as_deref(), expected);
+        }
+        Ok(())
+    }
 }
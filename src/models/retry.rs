@@ -0,0 +1,193 @@
+use super::Method;
+use super::error::RequestError;
+use clap::ValueEnum;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::io::ErrorKind;
+use std::time::{Duration, SystemTime};
+
+// 指数退避的最大等待时间
+const MAX_BACKOFF: Duration = Duration::from_secs(120);
+// Retry-After 允许的最大等待时间，防止服务器让客户端无限等待
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(300);
+
+/// 重试间隔的计算方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Backoff {
+    /// 每次等待固定的间隔
+    Fixed,
+    /// 间隔按 2 的幂增长，并加入随机抖动
+    Exponential,
+}
+
+/// 请求失败时的重试策略
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// 最大重试次数，0 表示不重试
+    pub max_retries: u32,
+    /// 基础重试间隔
+    pub interval: Duration,
+    pub backoff: Backoff,
+    /// 需要重试的响应状态码
+    pub statuses: Vec<u16>,
+    /// 是否也重试 POST、PATCH 等非幂等请求
+    pub retry_all: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_retries: 0,
+            interval: Duration::from_secs(1),
+            backoff: Backoff::Fixed,
+            statuses: vec![408, 429, 500, 502, 503, 504],
+            retry_all: false,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// 请求方式是否允许重试
+    pub fn allows_method(&self, method: &str) -> bool {
+        if self.retry_all {
+            return true;
+        }
+        // RFC 9110 9.2.2 中定义的幂等方法
+        [
+            Method::GET,
+            Method::HEAD,
+            Method::PUT,
+            Method::DELETE,
+            Method::OPTIONS,
+            Method::TRACE,
+        ]
+        .iter()
+        .any(|m| method.eq_ignore_ascii_case(&m.to_string()))
+    }
+
+    /// 响应状态码是否需要重试
+    pub fn retries_status(&self, status: u16) -> bool {
+        self.statuses.contains(&status)
+    }
+
+    /// 错误是否是可以重试的临时错误(连接失败、超时、连接被中断)
+    pub fn retries_error(&self, error: &RequestError) -> bool {
+        match error {
+            RequestError::Connect(_) | RequestError::SendRquestError(_) => true,
+            RequestError::Io(e) => matches!(
+                e.kind(),
+                ErrorKind::TimedOut
+                    | ErrorKind::WouldBlock
+                    | ErrorKind::ConnectionReset
+                    | ErrorKind::ConnectionAborted
+                    | ErrorKind::BrokenPipe
+                    | ErrorKind::UnexpectedEof
+            ),
+            _ => false,
+        }
+    }
+
+    /// 第 attempt 次重试(从 1 开始)前的等待时间，服务器给出 Retry-After 时以其为准
+    pub fn delay(&self, attempt: u32, retry_after: Option<Duration>) -> Duration {
+        if let Some(retry_after) = retry_after {
+            return retry_after.min(MAX_RETRY_AFTER);
+        }
+        match self.backoff {
+            Backoff::Fixed => self.interval,
+            Backoff::Exponential => {
+                let factor = 1u32 << attempt.saturating_sub(1).min(16);
+                let delay = self.interval.saturating_mul(factor).min(MAX_BACKOFF);
+                // 在 [delay/2, delay] 之间随机取值，避免多个客户端同时重试
+                let half = delay / 2;
+                half + half.mul_f64(jitter())
+            }
+        }
+    }
+}
+
+// [0, 1) 之间的随机数，每个 RandomState 使用不同的随机种子
+fn jitter() -> f64 {
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u64(0);
+    (hasher.finish() >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// 解析 Retry-After 头，支持秒数和 HTTP-date 两种格式
+pub fn parse_retry_after(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+    let date = httpdate::parse_http_date(value).ok()?;
+    // 日期已经过去时立即重试
+    Some(
+        date.duration_since(SystemTime::now())
+            .unwrap_or(Duration::ZERO),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io;
+
+    #[test]
+    fn test_allows_method() {
+        let mut policy = RetryPolicy::default();
+        assert!(policy.allows_method("GET"));
+        assert!(policy.allows_method("put"));
+        assert!(!policy.allows_method("POST"));
+        assert!(!policy.allows_method("PATCH"));
+        policy.retry_all = true;
+        assert!(policy.allows_method("POST"));
+    }
+
+    #[test]
+    fn test_retries_error() {
+        let policy = RetryPolicy::default();
+        let refused = io::Error::from(ErrorKind::ConnectionRefused);
+        assert!(policy.retries_error(&RequestError::Connect(refused)));
+        let timeout = io::Error::from(ErrorKind::TimedOut);
+        assert!(policy.retries_error(&RequestError::Io(timeout)));
+        let invalid = io::Error::from(ErrorKind::InvalidData);
+        assert!(!policy.retries_error(&RequestError::Io(invalid)));
+        assert!(!policy.retries_error(&RequestError::Tls("bad cert".to_string())));
+    }
+
+    #[test]
+    fn test_delay() {
+        let mut policy = RetryPolicy {
+            interval: Duration::from_secs(2),
+            ..RetryPolicy::default()
+        };
+        assert_eq!(policy.delay(3, None), Duration::from_secs(2));
+        assert_eq!(
+            policy.delay(1, Some(Duration::from_secs(7))),
+            Duration::from_secs(7)
+        );
+        assert_eq!(
+            policy.delay(1, Some(Duration::from_secs(100_000))),
+            MAX_RETRY_AFTER
+        );
+
+        policy.backoff = Backoff::Exponential;
+        for (attempt, max) in [(1, 2), (2, 4), (3, 8), (30, 120)] {
+            let delay = policy.delay(attempt, None);
+            let max = Duration::from_secs(max);
+            assert!(delay >= max / 2 && delay <= max, "{attempt}: {delay:?}");
+        }
+    }
+
+    #[test]
+    fn test_parse_retry_after() {
+        assert_eq!(parse_retry_after(" 120 "), Some(Duration::from_secs(120)));
+        assert_eq!(
+            parse_retry_after("Wed, 21 Oct 2015 07:28:00 GMT"),
+            Some(Duration::ZERO)
+        );
+        let future = SystemTime::now() + Duration::from_secs(3600);
+        let delay = parse_retry_after(&httpdate::fmt_http_date(future)).unwrap();
+        assert!(delay > Duration::from_secs(3590) && delay <= Duration::from_secs(3600));
+        assert_eq!(parse_retry_after("soon"), None);
+    }
+}
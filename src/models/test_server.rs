@@ -0,0 +1,314 @@
+//! 测试用的本地 HTTP 服务器
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{Ipv6Addr, Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+/// 服务器收到的请求
+#[derive(Debug, Clone)]
+pub struct TestRequest {
+    pub method: String,
+    pub target: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl TestRequest {
+    /// 按名称查找请求头(不区分大小写)
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+pub struct TestServer {
+    pub url: String,
+    accepted: Arc<AtomicUsize>,
+}
+
+impl TestServer {
+    /// 已经接受的连接数
+    pub fn accepted(&self) -> usize {
+        self.accepted.load(Ordering::SeqCst)
+    }
+}
+
+/// 启动一个支持 keep-alive 的服务器，每个请求的响应由 `handler` 生成；
+/// 响应中带有 `Connection: close` 时服务器会在写完后关闭连接
+pub fn serve<F>(handler: F) -> TestServer
+where
+    F: Fn(&TestRequest) -> Vec<u8> + Send + Sync + 'static,
+{
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let accepted = Arc::new(AtomicUsize::new(0));
+    let counter = Arc::clone(&accepted);
+    let handler = Arc::new(handler);
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(stream) = stream else {
+                continue;
+            };
+            counter.fetch_add(1, Ordering::SeqCst);
+            let handler = Arc::clone(&handler);
+            thread::spawn(move || {
+                let mut writer = stream.try_clone().unwrap();
+                let mut reader = BufReader::new(stream);
+                while let Some(request) = read_request(&mut reader) {
+                    let response = handler(&request);
+                    if writer.write_all(&response).is_err() {
+                        break;
+                    }
+                    let head = String::from_utf8_lossy(&response).to_ascii_lowercase();
+                    if head.contains("connection: close") {
+                        break;
+                    }
+                }
+            });
+        }
+    });
+    TestServer {
+        url: format!("http://{}", addr),
+        accepted,
+    }
+}
+
+/// 启动一个 HTTP 代理，每个请求的响应由 `handler` 生成。CONNECT 请求得到 2xx 响应后，
+/// 代理连接请求目标中的地址并双向转发数据；其他请求由 `handler` 直接回答
+pub fn serve_proxy<F>(handler: F) -> TestServer
+where
+    F: Fn(&TestRequest) -> Vec<u8> + Send + Sync + 'static,
+{
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let accepted = Arc::new(AtomicUsize::new(0));
+    let counter = Arc::clone(&accepted);
+    let handler = Arc::new(handler);
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(stream) = stream else {
+                continue;
+            };
+            counter.fetch_add(1, Ordering::SeqCst);
+            let handler = Arc::clone(&handler);
+            thread::spawn(move || {
+                let mut writer = stream.try_clone().unwrap();
+                let mut reader = BufReader::new(stream);
+                while let Some(request) = read_request(&mut reader) {
+                    let response = handler(&request);
+                    if writer.write_all(&response).is_err() {
+                        break;
+                    }
+                    if request.method == "CONNECT" && response.starts_with(b"HTTP/1.1 2") {
+                        tunnel(reader, writer, &request.target);
+                        return;
+                    }
+                }
+            });
+        }
+    });
+    TestServer {
+        url: format!("http://{}", addr),
+        accepted,
+    }
+}
+
+/// 启动一个同时支持 SOCKS4a 和 SOCKS5 的代理，所有连接都转发到 `backend`，
+/// 客户端请求的目标(host:port)按顺序记录在返回的列表中。
+/// 指定了 `credentials` 时 SOCKS5 要求用户名密码认证，SOCKS4a 要求 userid 等于用户名。
+/// SOCKS5 请求的主机在 `refused` 中时按连接被拒绝应答
+pub fn serve_socks(
+    backend: SocketAddr,
+    credentials: Option<(&'static str, &'static str)>,
+    refused: &'static [&'static str],
+) -> (TestServer, Arc<Mutex<Vec<String>>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let accepted = Arc::new(AtomicUsize::new(0));
+    let counter = Arc::clone(&accepted);
+    let targets = Arc::new(Mutex::new(Vec::new()));
+    let recorded = Arc::clone(&targets);
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(stream) = stream else {
+                continue;
+            };
+            counter.fetch_add(1, Ordering::SeqCst);
+            let recorded = Arc::clone(&recorded);
+            thread::spawn(move || {
+                let mut writer = stream.try_clone().unwrap();
+                let mut reader = BufReader::new(stream);
+                if let Some(target) =
+                    socks_handshake(&mut reader, &mut writer, credentials, refused)
+                {
+                    recorded.lock().unwrap().push(target);
+                    tunnel(reader, writer, &backend.to_string());
+                }
+            });
+        }
+    });
+    (
+        TestServer {
+            url: format!("http://{}", addr),
+            accepted,
+        },
+        targets,
+    )
+}
+
+// 服务器端的 SOCKS 握手，成功时返回客户端请求的目标
+fn socks_handshake<R: BufRead>(
+    reader: &mut R,
+    writer: &mut TcpStream,
+    credentials: Option<(&str, &str)>,
+    refused: &[&str],
+) -> Option<String> {
+    let mut read = |len: usize| {
+        let mut buf = vec![0u8; len];
+        reader.read_exact(&mut buf).ok().map(|_| buf)
+    };
+    match read(1)?[0] {
+        4 => {
+            let head = read(7)?;
+            let port = u16::from_be_bytes([head[1], head[2]]);
+            let mut read_string = || {
+                let mut value = Vec::new();
+                reader.read_until(0, &mut value).ok()?;
+                value.pop();
+                String::from_utf8(value).ok()
+            };
+            let userid = read_string()?;
+            let host = if head[3..6] == [0, 0, 0] && head[6] != 0 {
+                read_string()?
+            } else {
+                format!("{}.{}.{}.{}", head[3], head[4], head[5], head[6])
+            };
+            if credentials.is_some_and(|(username, _)| username != userid) {
+                writer.write_all(&[0, 91, 0, 0, 0, 0, 0, 0]).ok()?;
+                return None;
+            }
+            writer.write_all(&[0, 90, 0, 0, 0, 0, 0, 0]).ok()?;
+            Some(format!("{}:{}", host, port))
+        }
+        5 => {
+            let count = read(1)?[0] as usize;
+            let methods = read(count)?;
+            let method = if credentials.is_some() { 2 } else { 0 };
+            if !methods.contains(&method) {
+                writer.write_all(&[5, 0xff]).ok()?;
+                return None;
+            }
+            writer.write_all(&[5, method]).ok()?;
+            if let Some((username, password)) = credentials {
+                let len = read(2)?[1] as usize;
+                let user = read(len)?;
+                let len = read(1)?[0] as usize;
+                let pass = read(len)?;
+                let ok = user == username.as_bytes() && pass == password.as_bytes();
+                writer.write_all(&[1, if ok { 0 } else { 1 }]).ok()?;
+                if !ok {
+                    return None;
+                }
+            }
+            let head = read(4)?;
+            let host = match head[3] {
+                1 => read(4)?
+                    .iter()
+                    .map(|b| b.to_string())
+                    .collect::<Vec<_>>()
+                    .join("."),
+                3 => {
+                    let len = read(1)?[0] as usize;
+                    String::from_utf8(read(len)?).ok()?
+                }
+                4 => {
+                    let octets: [u8; 16] = read(16)?.try_into().ok()?;
+                    format!("[{}]", Ipv6Addr::from(octets))
+                }
+                _ => return None,
+            };
+            let port = read(2)?;
+            if refused.contains(&host.as_str()) {
+                writer.write_all(&[5, 5, 0, 1, 0, 0, 0, 0, 0, 0]).ok()?;
+                return None;
+            }
+            writer.write_all(&[5, 0, 0, 1, 0, 0, 0, 0, 0, 0]).ok()?;
+            Some(format!(
+                "{}:{}",
+                host,
+                u16::from_be_bytes([port[0], port[1]])
+            ))
+        }
+        _ => None,
+    }
+}
+
+// 在客户端和目标服务器之间双向转发数据，直到任意一方关闭连接
+fn tunnel(mut client: BufReader<TcpStream>, mut writer: TcpStream, target: &str) {
+    let Ok(upstream) = TcpStream::connect(target) else {
+        return;
+    };
+    let mut upstream_reader = upstream.try_clone().unwrap();
+    let mut upstream_writer = upstream;
+    let forward = thread::spawn(move || {
+        let _ = io::copy(&mut client, &mut upstream_writer);
+        let _ = upstream_writer.shutdown(Shutdown::Write);
+    });
+    let _ = io::copy(&mut upstream_reader, &mut writer);
+    let _ = writer.shutdown(Shutdown::Both);
+    let _ = forward.join();
+}
+
+/// 读取一个完整的请求，连接关闭时返回 None
+pub fn read_request<R: BufRead>(reader: &mut R) -> Option<TestRequest> {
+    let mut line = String::new();
+    if reader.read_line(&mut line).ok()? == 0 {
+        return None;
+    }
+    let mut parts = line.split_whitespace();
+    let method = parts.next()?.to_string();
+    let target = parts.next()?.to_string();
+    let mut headers = Vec::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line).ok()? == 0 {
+            return None;
+        }
+        if line == "\r\n" || line == "\n" {
+            break;
+        }
+        if let Some((key, value)) = line.split_once(':') {
+            headers.push((key.trim().to_string(), value.trim().to_string()));
+        }
+    }
+    let mut request = TestRequest {
+        method,
+        target,
+        headers,
+        body: Vec::new(),
+    };
+    if let Some(len) = request
+        .header("Content-Length")
+        .and_then(|v| v.parse::<usize>().ok())
+    {
+        let mut body = vec![0u8; len];
+        reader.read_exact(&mut body).ok()?;
+        request.body = body;
+    }
+    Some(request)
+}
+
+/// 构造一个带 Content-Length 的响应
+pub fn response(status: &str, headers: &[(&str, &str)], body: &[u8]) -> Vec<u8> {
+    let mut data = format!("HTTP/1.1 {}\r\n", status);
+    for (key, value) in headers {
+        data.push_str(&format!("{}: {}\r\n", key, value));
+    }
+    data.push_str(&format!("Content-Length: {}\r\n\r\n", body.len()));
+    let mut data = data.into_bytes();
+    data.extend_from_slice(body);
+    data
+}
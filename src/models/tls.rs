@@ -0,0 +1,216 @@
+use super::error::{RequestError, Result};
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::path::PathBuf;
+
+/// 连接使用的底层数据流，明文 TCP 和 TLS 连接都实现了该 trait
+pub trait Stream: Read + Write {
+    /// 底层的 TCP 连接，用于设置超时和检查连接是否仍然可用
+    fn tcp(&self) -> &TcpStream;
+}
+
+impl Stream for TcpStream {
+    fn tcp(&self) -> &TcpStream {
+        self
+    }
+}
+
+/// TLS 相关配置
+#[cfg_attr(not(feature = "rustls"), allow(dead_code))]
+#[derive(Debug, Clone, Default)]
+pub struct TlsConfig {
+    /// 使用指定的 PEM 格式 CA 证书文件代替系统根证书
+    pub ca_file: Option<PathBuf>,
+    /// 跳过服务器证书校验
+    pub insecure: bool,
+    /// 通过 ALPN 协商的应用层协议，默认为 http/1.1
+    pub alpn: Option<String>,
+}
+
+/// TLS 后端，负责在已经建立的 TCP 连接上完成握手
+pub trait TlsConnector {
+    fn connect(&self, host: &str, stream: TcpStream) -> Result<Box<dyn Stream>>;
+}
+
+/// 根据编译时启用的 feature 创建默认的 TLS 后端
+#[allow(unused_variables)]
+pub fn default_connector(config: &TlsConfig) -> Result<Box<dyn TlsConnector>> {
+    #[cfg(feature = "rustls")]
+    {
+        Ok(Box::new(rustls_backend::RustlsConnector::new(config)?))
+    }
+    #[cfg(not(feature = "rustls"))]
+    {
+        Err(RequestError::Tls(
+            "编译时未启用任何TLS后端，无法访问https地址".to_string(),
+        ))
+    }
+}
+
+#[cfg(feature = "rustls")]
+mod rustls_backend {
+    use super::*;
+    use log::{debug, warn};
+    use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+    use rustls::crypto::{CryptoProvider, verify_tls12_signature, verify_tls13_signature};
+    use rustls::pki_types::pem::PemObject;
+    use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+    use rustls::{ClientConfig, ClientConnection, DigitallySignedStruct, RootCertStore};
+    use rustls::{SignatureScheme, StreamOwned};
+    use std::io::{ErrorKind, Result as IoResult};
+    use std::sync::Arc;
+
+    pub struct RustlsConnector {
+        config: Arc<ClientConfig>,
+    }
+
+    impl RustlsConnector {
+        pub fn new(config: &TlsConfig) -> Result<Self> {
+            let provider = Arc::new(rustls::crypto::ring::default_provider());
+            let builder = ClientConfig::builder_with_provider(Arc::clone(&provider))
+                .with_safe_default_protocol_versions()
+                .map_err(|e| RequestError::Tls(e.to_string()))?;
+            let mut client_config = if config.insecure {
+                builder
+                    .dangerous()
+                    .with_custom_certificate_verifier(Arc::new(NoVerifier(provider)))
+                    .with_no_client_auth()
+            } else {
+                builder
+                    .with_root_certificates(load_roots(config)?)
+                    .with_no_client_auth()
+            };
+            let alpn = config.alpn.as_deref().unwrap_or("http/1.1");
+            client_config.alpn_protocols = vec![alpn.as_bytes().to_vec()];
+            Ok(RustlsConnector {
+                config: Arc::new(client_config),
+            })
+        }
+    }
+
+    impl TlsConnector for RustlsConnector {
+        fn connect(&self, host: &str, mut stream: TcpStream) -> Result<Box<dyn Stream>> {
+            // IP 地址不会发送 SNI，rustls 会自动处理
+            let server_name = ServerName::try_from(host.to_string())
+                .map_err(|e| RequestError::Tls(format!("无效的主机名 {}: {}", host, e)))?;
+            let mut conn = ClientConnection::new(Arc::clone(&self.config), server_name)
+                .map_err(|e| RequestError::Tls(e.to_string()))?;
+            // 立即完成握手，以便证书错误能在发送请求前报告
+            while conn.is_handshaking() {
+                conn.complete_io(&mut stream)
+                    .map_err(|e| RequestError::Tls(format!("TLS握手失败: {}", e)))?;
+            }
+            debug!(
+                "TLS握手完成: {:?} {:?}",
+                conn.protocol_version(),
+                conn.negotiated_cipher_suite().map(|s| s.suite())
+            );
+            Ok(Box::new(TlsStream(StreamOwned::new(conn, stream))))
+        }
+    }
+
+    // 加载根证书: 指定了 CA 文件时只信任该文件中的证书，否则使用系统根证书
+    fn load_roots(config: &TlsConfig) -> Result<RootCertStore> {
+        let mut roots = RootCertStore::empty();
+        if let Some(path) = config.ca_file.as_ref() {
+            let certs = CertificateDer::pem_file_iter(path)
+                .and_then(|iter| iter.collect::<std::result::Result<Vec<_>, _>>())
+                .map_err(|e| {
+                    RequestError::Tls(format!("读取CA证书 {} 失败: {}", path.display(), e))
+                })?;
+            let (added, _) = roots.add_parsable_certificates(certs);
+            if added == 0 {
+                return Err(RequestError::Tls(format!(
+                    "{} 中没有可用的CA证书",
+                    path.display()
+                )));
+            }
+            return Ok(roots);
+        }
+        let result = rustls_native_certs::load_native_certs();
+        for err in result.errors.iter() {
+            warn!("加载系统根证书出错: {}", err);
+        }
+        let (added, ignored) = roots.add_parsable_certificates(result.certs);
+        debug!("加载系统根证书: {} 个可用, {} 个被忽略", added, ignored);
+        Ok(roots)
+    }
+
+    struct TlsStream(StreamOwned<ClientConnection, TcpStream>);
+
+    impl Read for TlsStream {
+        fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
+            match self.0.read(buf) {
+                // 很多服务器关闭连接前不发送 close_notify，当作正常结束处理，
+                // 消息体是否完整由 Content-Length 或分块编码判断
+                Err(e) if e.kind() == ErrorKind::UnexpectedEof => Ok(0),
+                other => other,
+            }
+        }
+    }
+
+    impl Write for TlsStream {
+        fn write(&mut self, buf: &[u8]) -> IoResult<usize> {
+            self.0.write(buf)
+        }
+
+        fn flush(&mut self) -> IoResult<()> {
+            self.0.flush()
+        }
+    }
+
+    impl Stream for TlsStream {
+        fn tcp(&self) -> &TcpStream {
+            self.0.get_ref()
+        }
+    }
+
+    // --insecure: 不校验证书链和主机名，但仍然校验握手签名
+    #[derive(Debug)]
+    struct NoVerifier(Arc<CryptoProvider>);
+
+    impl ServerCertVerifier for NoVerifier {
+        fn verify_server_cert(
+            &self,
+            _end_entity: &CertificateDer<'_>,
+            _intermediates: &[CertificateDer<'_>],
+            _server_name: &ServerName<'_>,
+            _ocsp_response: &[u8],
+            _now: UnixTime,
+        ) -> std::result::Result<ServerCertVerified, rustls::Error> {
+            Ok(ServerCertVerified::assertion())
+        }
+
+        fn verify_tls12_signature(
+            &self,
+            message: &[u8],
+            cert: &CertificateDer<'_>,
+            dss: &DigitallySignedStruct,
+        ) -> std::result::Result<HandshakeSignatureValid, rustls::Error> {
+            verify_tls12_signature(
+                message,
+                cert,
+                dss,
+                &self.0.signature_verification_algorithms,
+            )
+        }
+
+        fn verify_tls13_signature(
+            &self,
+            message: &[u8],
+            cert: &CertificateDer<'_>,
+            dss: &DigitallySignedStruct,
+        ) -> std::result::Result<HandshakeSignatureValid, rustls::Error> {
+            verify_tls13_signature(
+                message,
+                cert,
+                dss,
+                &self.0.signature_verification_algorithms,
+            )
+        }
+
+        fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+            self.0.signature_verification_algorithms.supported_schemes()
+        }
+    }
+}
@@ -1 +1,101 @@
+/// 清理来自服务器或 URL 的文件名，防止写到当前目录之外
+///
+/// 只保留最后一个路径段，去掉控制字符并替换 Windows 下不允许的字符，
+/// 清理后为空或只剩 `.`、`..` 时返回 None
+pub fn sanitize_filename(name: &str) -> Option<String> {
+    let name = name.rsplit(['/', '\\']).next().unwrap_or_default();
+    let name: String = name
+        .chars()
+        .filter(|c| !c.is_control())
+        .map(|c| match c {
+            '<' | '>' | ':' | '"' | '|' | '?' | '*' => '_',
+            c => c,
+        })
+        .collect();
+    // 去掉开头的点，避免生成隐藏文件或 . / ..
+    let name = name.trim().trim_start_matches('.');
+    if name.is_empty() {
+        None
+    } else {
+        Some(name.to_string())
+    }
+}
 
+/// base64 编码(RFC 4648 4)，带填充，用于 Basic 认证等请求头
+pub fn base64_encode(data: &[u8]) -> String {
+    let mut encoded = encode_base64(
+        data,
+        b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/",
+    );
+    while !encoded.len().is_multiple_of(4) {
+        encoded.push('=');
+    }
+    encoded
+}
+
+/// base64url 编码(RFC 4648 5)，不带填充，可以直接放在 URL 中
+pub fn base64url_encode(data: &[u8]) -> String {
+    encode_base64(
+        data,
+        b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_",
+    )
+}
+
+fn encode_base64(data: &[u8], alphabet: &[u8; 64]) -> String {
+    let mut encoded = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let bits = chunk.iter().enumerate().fold(0u32, |bits, (i, byte)| {
+            bits | (*byte as u32) << (16 - 8 * i)
+        });
+        // n 个字节编码为 n + 1 个字符
+        for i in 0..=chunk.len() {
+            encoded.push(alphabet[(bits >> (18 - 6 * i) & 0x3f) as usize] as char);
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_sanitize_filename() {
+        assert_eq!(
+            sanitize_filename("report.pdf").as_deref(),
+            Some("report.pdf")
+        );
+        assert_eq!(
+            sanitize_filename("../../etc/passwd").as_deref(),
+            Some("passwd")
+        );
+        assert_eq!(
+            sanitize_filename("..\\windows\\a:b?.txt").as_deref(),
+            Some("a_b_.txt")
+        );
+        assert_eq!(sanitize_filename(".bashrc").as_deref(), Some("bashrc"));
+        assert_eq!(sanitize_filename("a\r\nb").as_deref(), Some("ab"));
+        assert_eq!(sanitize_filename(".."), None);
+        assert_eq!(sanitize_filename("dir/"), None);
+    }
+
+    #[test]
+    fn test_base64_encode() {
+        assert_eq!(base64_encode(b""), "");
+        assert_eq!(base64_encode(b"f"), "Zg==");
+        assert_eq!(base64_encode(b"fo"), "Zm8=");
+        assert_eq!(base64_encode(b"foo"), "Zm9v");
+        assert_eq!(base64_encode(b"user:pass"), "dXNlcjpwYXNz");
+        assert_eq!(base64_encode(&[0xfb, 0xff, 0xbf]), "+/+/");
+    }
+
+    #[test]
+    fn test_base64url_encode() {
+        assert_eq!(base64url_encode(b""), "");
+        assert_eq!(base64url_encode(b"f"), "Zg");
+        assert_eq!(base64url_encode(b"fo"), "Zm8");
+        assert_eq!(base64url_encode(b"foo"), "Zm9v");
+        assert_eq!(base64url_encode(b"foob"), "Zm9vYg");
+        assert_eq!(base64url_encode(&[0xfb, 0xff, 0xbf]), "-_-_");
+    }
+}